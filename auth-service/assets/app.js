@@ -6,6 +6,17 @@ const signupLink = document.getElementById("signup-link");
 const twoFALoginLink = document.getElementById("2fa-login-link");
 const signupLoginLink = document.getElementById("signup-login-link");
 
+// /oauth/authorize sends users without a session here with a `next` link
+// to return to once they have logged in
+function continueToNext() {
+    const next = new URLSearchParams(window.location.search).get("next");
+    if (next !== null && next.startsWith("/oauth/")) {
+        window.location.assign(next);
+        return true;
+    }
+    return false;
+}
+
 signupLink.addEventListener("click", (e) => {
     e.preventDefault();
 
@@ -66,7 +77,9 @@ loginButton.addEventListener("click", (e) => {
             loginForm.email.value = "";
             loginForm.password.value = "";
             loginErrAlter.style.display = "none";
-            alert("You have successfully logged in.");
+            if (!continueToNext()) {
+                alert("You have successfully logged in.");
+            }
         } else {
             response.json().then(data => {
                 let error_msg = data.error;
@@ -110,7 +123,10 @@ signupButton.addEventListener("click", (e) => {
             signupSection.style.display = "none";
         } else {
             response.json().then(data => {
-                let error_msg = data.error;
+                let error_msg = data.message;
+                if (Array.isArray(data.errors) && data.errors.length > 0) {
+                    error_msg += "<ul>" + data.errors.map(err => `<li>${err.message}</li>`).join("") + "</ul>";
+                }
                 if (error_msg !== undefined && error_msg !== null && error_msg !== "") {
                     signupErrAlter.innerHTML = `<span><strong>Error: </strong>${error_msg}</span>`;
                     signupErrAlter.style.display = "block";
@@ -145,6 +161,9 @@ TwoFAButton.addEventListener("click", (e) => {
             TwoFAForm.email_code.value = "";
             TwoFAForm.login_attempt_id.value = "";
             TwoFAErrAlter.style.display = "none";
+            if (continueToNext()) {
+                return;
+            }
             alert("You have successfully logged in.");
             loginSection.style.display = "block";
             twoFASection.style.display = "none";
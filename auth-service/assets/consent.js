@@ -0,0 +1,35 @@
+const consentForm = document.getElementById("consent-form");
+const consentErrAlter = document.getElementById("consent-err-alert");
+const clientName = document.getElementById("client-name");
+const clientScope = document.getElementById("client-scope");
+
+// /oauth/authorize sends the user here with the checked authorization
+// request, which is posted back unchanged along with their decision
+const params = new URLSearchParams(window.location.search);
+
+for (const input of consentForm.querySelectorAll("input[type=hidden]")) {
+    const value = params.get(input.name);
+    if (value === null) {
+        input.remove();
+    } else {
+        input.value = value;
+    }
+}
+
+if (params.get("scope")) {
+    clientScope.textContent = `Requested access: ${params.get("scope")}`;
+    clientScope.style.display = "block";
+}
+
+// The name comes from the registered client, not from the URL
+fetch(`/oauth/clients/${encodeURIComponent(params.get("client_id") || "")}`).then(response => {
+    if (response.ok) {
+        response.json().then(data => {
+            clientName.textContent = data.name;
+        });
+    } else {
+        consentForm.style.display = "none";
+        consentErrAlter.textContent = "This application is not registered.";
+        consentErrAlter.style.display = "block";
+    }
+});
@@ -0,0 +1,126 @@
+// Heap used by the in-memory banned token store at a million revocations,
+// banning by `jti` against banning whole session tokens as it used to:
+//
+//     cargo bench --bench banned_token_memory
+//
+// REVOCATIONS can be set to change the number of banned tokens.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use auth_service::domains::data_stores::BannedTokenStore;
+use auth_service::domains::email::Email;
+use auth_service::domains::user_id::UserId;
+use auth_service::services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore;
+use auth_service::utils::auth::{generate_auth_token, validate_token, Authentication};
+use auth_service::utils::clock::{Clock, FakeClock};
+use chrono::Duration;
+
+// Counts live heap bytes, so a measurement is the difference between two reads
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+#[tokio::main]
+async fn main() {
+    let revocations = env_or("REVOCATIONS", 1_000_000);
+
+    let clock = FakeClock::default();
+    let email = Email::parse("bench@example.com".to_owned()).unwrap();
+    let authentication = Authentication::password(&clock);
+    let token =
+        generate_auth_token(&UserId::default(), &email, 0, &authentication, &clock).unwrap();
+    let claims = validate_token(
+        &token,
+        None,
+        Arc::new(HashsetBannedTokenStore::default()),
+        &clock,
+    )
+    .await
+    .unwrap();
+    let expires_at = claims.expires_at();
+
+    // What gets banned now: a UUID per token
+    let store = HashsetBannedTokenStore::default().with_clock(Arc::new(clock.clone()));
+    let before = allocated();
+    for _ in 0..revocations {
+        let jti = uuid::Uuid::new_v4().to_string();
+        let _ = store.add_banned_token(jti, expires_at).await;
+    }
+    let by_jti = allocated() - before;
+    report("by jti", revocations, by_jti);
+
+    clock.advance(Duration::seconds(
+        expires_at.timestamp() - clock.now().timestamp(),
+    ));
+    let removed = store.remove_expired();
+    println!(
+        "after expiry: {} removed, {} left, {} bytes still held",
+        removed,
+        store.len(),
+        allocated().saturating_sub(before)
+    );
+    drop(store);
+
+    // What used to be banned: the whole session token, made unique with a
+    // suffix so it's a little longer than a real one
+    let store = HashsetBannedTokenStore::default();
+    let before = allocated();
+    for _ in 0..revocations {
+        let mut token = format!("{}{}", token, uuid::Uuid::new_v4().simple());
+        token.shrink_to_fit();
+        let _ = store.add_banned_token(token, expires_at).await;
+    }
+    let by_token = allocated() - before;
+    report(
+        &format!("by token ({} bytes each)", token.len() + 32),
+        revocations,
+        by_token,
+    );
+    println!(
+        "banning by jti uses {:.1}x less memory",
+        by_token as f64 / by_jti as f64
+    );
+}
+
+fn allocated() -> usize {
+    ALLOCATED.load(Ordering::Relaxed)
+}
+
+fn report(label: &str, revocations: usize, bytes: usize) {
+    println!(
+        "{}: {} revocations take {:.1} MiB, {} bytes each",
+        label,
+        revocations,
+        bytes as f64 / (1024.0 * 1024.0),
+        bytes / revocations
+    );
+}
+
+fn env_or(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
@@ -0,0 +1,195 @@
+// Tail latency of /verify-token while logins keep the password hashing pool busy.
+// Needs the same Postgres as the integration tests:
+//
+//     cargo bench --bench verify_token_latency
+//
+// LOGIN_CONCURRENCY and VERIFY_REQUESTS can be set to change the load.
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use auth_service::app_state::AppState;
+use auth_service::services::data_stores::hashmap_oauth_client_store::HashmapOAuthClientStore;
+use auth_service::services::data_stores::hashmap_two_fa_code_store::HashmapTwoFACodeStore;
+use auth_service::services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore;
+use auth_service::services::data_stores::postgres_session_store::PostgresSessionStore;
+use auth_service::services::data_stores::postgres_user_store::PostgresUserStore;
+use auth_service::services::mock_email_client::MockEmailClient;
+use auth_service::utils::constants::{DATABASE_URL, JWT_COOKIE_NAME};
+use auth_service::{get_postgres_pool, Application};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::Executor;
+use uuid::Uuid;
+
+const PASSWORD: &str = "password123";
+
+#[tokio::main]
+async fn main() {
+    let login_concurrency = env_or("LOGIN_CONCURRENCY", 64);
+    let verify_requests = env_or("VERIFY_REQUESTS", 500);
+
+    let db_name = Uuid::new_v4().to_string();
+    let address = start_app(&db_name).await;
+    let client = reqwest::Client::new();
+
+    let email = format!("{}@example.com", Uuid::new_v4());
+    let response = client
+        .post(format!("{}/signup", address))
+        .json(&serde_json::json!({ "email": email, "password": PASSWORD, "requires2FA": false }))
+        .send()
+        .await
+        .expect("Failed to sign up");
+    assert_eq!(response.status().as_u16(), 201);
+    let token = login(&client, &address, &email)
+        .await
+        .expect("Failed to log in");
+
+    let idle = measure_verify_token(&client, &address, &token, verify_requests).await;
+    report("idle", &idle);
+
+    let load_started = Instant::now();
+    let stop = Arc::new(AtomicBool::new(false));
+    let logins = Arc::new(AtomicUsize::new(0));
+    let rejected = Arc::new(AtomicUsize::new(0));
+    let workers: Vec<_> = (0..login_concurrency)
+        .map(|_| {
+            let (client, address, email) = (client.clone(), address.clone(), email.clone());
+            let (stop, logins, rejected) = (stop.clone(), logins.clone(), rejected.clone());
+            tokio::spawn(async move {
+                while !stop.load(Ordering::Relaxed) {
+                    match login(&client, &address, &email).await {
+                        Ok(_) => logins.fetch_add(1, Ordering::Relaxed),
+                        Err(_) => rejected.fetch_add(1, Ordering::Relaxed),
+                    };
+                }
+            })
+        })
+        .collect();
+
+    // Let the login load build up before measuring
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let loaded = measure_verify_token(&client, &address, &token, verify_requests).await;
+    stop.store(true, Ordering::Relaxed);
+    let elapsed = load_started.elapsed();
+    for worker in workers {
+        let _ = worker.await;
+    }
+
+    report(&format!("{} concurrent logins", login_concurrency), &loaded);
+    println!(
+        "logins: {} ok, {} rejected with 503 ({:.0} ok/s)",
+        logins.load(Ordering::Relaxed),
+        rejected.load(Ordering::Relaxed),
+        logins.load(Ordering::Relaxed) as f64 / elapsed.as_secs_f64()
+    );
+
+    drop_database(&db_name).await;
+}
+
+async fn start_app(db_name: &str) -> String {
+    let connection = PgPoolOptions::new()
+        .connect(&DATABASE_URL)
+        .await
+        .expect("Failed to connect to Postgres");
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, db_name).as_str())
+        .await
+        .expect("Failed to create database");
+
+    let pg_pool = get_postgres_pool(&format!("{}/{}", DATABASE_URL.as_str(), db_name))
+        .await
+        .expect("Failed to create Postgres connection pool");
+    sqlx::migrate!()
+        .run(&pg_pool)
+        .await
+        .expect("Failed to migrate the database");
+
+    let app_state = AppState::new(
+        Arc::new(PostgresUserStore::new(pg_pool.clone())),
+        Arc::new(HashsetBannedTokenStore::default()),
+        Arc::new(HashmapTwoFACodeStore::default()),
+        Arc::new(MockEmailClient),
+        Arc::new(HashmapOAuthClientStore::default()),
+        Arc::new(PostgresSessionStore::new(pg_pool)),
+    );
+    let app = Application::build(app_state, "127.0.0.1:0")
+        .await
+        .expect("Failed to build app");
+    let address = format!("http://{}", app.address);
+    tokio::spawn(app.run());
+
+    address
+}
+
+async fn drop_database(db_name: &str) {
+    let connection = PgPoolOptions::new()
+        .connect(&DATABASE_URL)
+        .await
+        .expect("Failed to connect to Postgres");
+    connection
+        .execute(format!(r#"DROP DATABASE "{}" WITH (FORCE);"#, db_name).as_str())
+        .await
+        .expect("Failed to drop database");
+}
+
+// Returns the session token, or the status code of a failed login
+async fn login(client: &reqwest::Client, address: &str, email: &str) -> Result<String, u16> {
+    let response = client
+        .post(format!("{}/login", address))
+        .json(&serde_json::json!({ "email": email, "password": PASSWORD }))
+        .send()
+        .await
+        .map_err(|_| 0u16)?;
+
+    if response.status().as_u16() != 200 {
+        return Err(response.status().as_u16());
+    }
+
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .map(|cookie| cookie.value().to_owned())
+        .ok_or(0);
+    token
+}
+
+async fn measure_verify_token(
+    client: &reqwest::Client,
+    address: &str,
+    token: &str,
+    requests: usize,
+) -> Vec<Duration> {
+    let mut latencies = Vec::with_capacity(requests);
+    for _ in 0..requests {
+        let started = Instant::now();
+        let response = client
+            .post(format!("{}/verify-token", address))
+            .json(&serde_json::json!({ "token": token }))
+            .send()
+            .await
+            .expect("Failed to verify token");
+        latencies.push(started.elapsed());
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    latencies.sort();
+    latencies
+}
+
+fn report(label: &str, sorted: &[Duration]) {
+    let percentile = |p: f64| sorted[((sorted.len() - 1) as f64 * p).round() as usize];
+    println!(
+        "verify-token ({}): p50 {:?}  p90 {:?}  p99 {:?}  max {:?}",
+        label,
+        percentile(0.50),
+        percentile(0.90),
+        percentile(0.99),
+        sorted[sorted.len() - 1]
+    );
+}
+
+fn env_or(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
@@ -0,0 +1,5 @@
+-- Add down migration script here
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_email_key;
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_pkey;
+ALTER TABLE users ADD CONSTRAINT users_pkey PRIMARY KEY (email);
+ALTER TABLE users DROP COLUMN IF EXISTS id;
@@ -0,0 +1,13 @@
+-- Add up migration script here
+ALTER TABLE users ADD COLUMN IF NOT EXISTS id UUID;
+
+-- Backfill existing rows with a random id before enforcing the constraint.
+UPDATE users SET id = gen_random_uuid() WHERE id IS NULL;
+
+ALTER TABLE users ALTER COLUMN id SET NOT NULL;
+ALTER TABLE users ALTER COLUMN id SET DEFAULT gen_random_uuid();
+
+-- The id becomes the primary key, email stays unique so it can still be used for lookups.
+ALTER TABLE users DROP CONSTRAINT users_pkey;
+ALTER TABLE users ADD CONSTRAINT users_pkey PRIMARY KEY (id);
+ALTER TABLE users ADD CONSTRAINT users_email_key UNIQUE (email);
@@ -0,0 +1,7 @@
+-- Add up migration script here
+-- Emails are now stored in their canonical, lowercased form.
+-- This fails if two existing accounts only differ by case; merge those by hand first.
+UPDATE users SET email = LOWER(email) WHERE email <> LOWER(email);
+
+-- Guard case-insensitive uniqueness even for writers that skip `Email::parse`
+CREATE UNIQUE INDEX IF NOT EXISTS users_email_lower_key ON users (LOWER(email));
@@ -0,0 +1,7 @@
+-- Add up migration script here
+-- Applications allowed to use /oauth/authorize. Redirect URIs are matched exactly.
+CREATE TABLE IF NOT EXISTS oauth_clients(
+   client_id TEXT NOT NULL PRIMARY KEY,
+   name TEXT NOT NULL,
+   redirect_uris TEXT[] NOT NULL
+);
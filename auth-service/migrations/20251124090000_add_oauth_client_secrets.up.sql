@@ -0,0 +1,6 @@
+-- Add up migration script here
+-- Confidential clients authenticate with a secret, stored as its SHA-256,
+-- and may request the scopes listed here with the client credentials grant.
+ALTER TABLE oauth_clients
+   ADD COLUMN IF NOT EXISTS secret_hash TEXT,
+   ADD COLUMN IF NOT EXISTS scopes TEXT[] NOT NULL DEFAULT '{}';
@@ -0,0 +1,4 @@
+-- Add up migration script here
+-- Every token issued to a user carries their session version, bumping it
+-- ends all of their sessions at once.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS session_version BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,14 @@
+-- Add up migration script here
+-- One row per login, keyed by the `jti` of the session token. Rows go away
+-- with the user, or when the session is ended or has expired.
+CREATE TABLE IF NOT EXISTS sessions(
+   id TEXT NOT NULL PRIMARY KEY,
+   user_id UUID NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+   created_at TIMESTAMPTZ NOT NULL,
+   last_seen_at TIMESTAMPTZ NOT NULL,
+   expires_at TIMESTAMPTZ NOT NULL,
+   ip TEXT,
+   user_agent TEXT
+);
+
+CREATE INDEX IF NOT EXISTS sessions_user_id_idx ON sessions (user_id);
@@ -1,35 +1,85 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
-use crate::domains::data_stores::{BannedTokenStore, TwoFACodeStore, UserStore};
+use crate::domains::data_stores::{
+    BannedTokenStore, OAuthClientStore, SessionStore, TwoFACodeStore, UserStore,
+};
+use crate::domains::password_policy::PasswordPolicy;
 use crate::domains::EmailClient;
+use crate::utils::clock::{Clock, SystemClock};
+use crate::utils::login_throttle::LoginThrottle;
 
-// Using a type alias to improve readability!
-pub type UserStoreType<T> = Arc<RwLock<T>>;
-pub type EmailClientType<T> = Arc<RwLock<T>>;
+// Using a type alias to improve readability! The stores handle concurrent
+// access themselves, so they're shared without an outer lock.
+pub type UserStoreType<T> = Arc<T>;
+pub type BannedTokenStoreType<T> = Arc<T>;
+pub type TwoFACodeStoreType<T> = Arc<T>;
+pub type EmailClientType<T> = Arc<T>;
+pub type OAuthClientStoreType<T> = Arc<T>;
+pub type SessionStoreType<T> = Arc<T>;
 
 #[derive(Clone)]
-pub struct AppState<T: UserStore, T1: BannedTokenStore, T2: TwoFACodeStore, T3: EmailClient> {
+pub struct AppState<
+    T: UserStore,
+    T1: BannedTokenStore,
+    T2: TwoFACodeStore,
+    T3: EmailClient,
+    T4: OAuthClientStore,
+    T5: SessionStore,
+> {
     pub user_store: UserStoreType<T>,
-    pub banned_token_store: UserStoreType<T1>,
-    pub two_fa_store: UserStoreType<T2>,
-    pub email_client: UserStoreType<T3>,
+    pub banned_token_store: BannedTokenStoreType<T1>,
+    pub two_fa_store: TwoFACodeStoreType<T2>,
+    pub email_client: EmailClientType<T3>,
+    pub oauth_client_store: OAuthClientStoreType<T4>,
+    pub session_store: SessionStoreType<T5>,
+    pub password_policy: Arc<PasswordPolicy>,
+    pub login_throttle: Arc<LoginThrottle>,
+    pub clock: Arc<dyn Clock>,
 }
 
-impl<T: UserStore, T1: BannedTokenStore, T2: TwoFACodeStore, T3: EmailClient>
-    AppState<T, T1, T2, T3>
+impl<
+        T: UserStore,
+        T1: BannedTokenStore,
+        T2: TwoFACodeStore,
+        T3: EmailClient,
+        T4: OAuthClientStore,
+        T5: SessionStore,
+    > AppState<T, T1, T2, T3, T4, T5>
 {
     pub fn new(
         user_store: UserStoreType<T>,
-        banned_token_store: UserStoreType<T1>,
-        two_fa_store: UserStoreType<T2>,
+        banned_token_store: BannedTokenStoreType<T1>,
+        two_fa_store: TwoFACodeStoreType<T2>,
         email_client: EmailClientType<T3>,
+        oauth_client_store: OAuthClientStoreType<T4>,
+        session_store: SessionStoreType<T5>,
     ) -> Self {
         Self {
             user_store,
             banned_token_store,
             two_fa_store,
             email_client,
+            oauth_client_store,
+            session_store,
+            password_policy: Arc::new(PasswordPolicy::default()),
+            login_throttle: Arc::new(LoginThrottle::default()),
+            clock: Arc::new(SystemClock),
         }
     }
+
+    pub fn with_password_policy(mut self, password_policy: PasswordPolicy) -> Self {
+        self.password_policy = Arc::new(password_policy);
+        self
+    }
+
+    // Keep a clone to sweep it, see `LoginThrottle::remove_expired`
+    pub fn with_login_throttle(mut self, login_throttle: LoginThrottle) -> Self {
+        self.login_throttle = Arc::new(login_throttle);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
 }
@@ -0,0 +1,85 @@
+// Bulk import of users whose passwords were hashed by another system.
+//
+// Usage: cargo run --bin import_users -- users.jsonl
+//
+// Each line of the input is a JSON object:
+// {"email": "ravi@example.com", "passwordHash": "$2b$12$...", "requires2FA": false, "id": "<uuid>"}
+// `requires2FA` and `id` are optional. Supported hash formats are Argon2, PBKDF2 (PHC),
+// bcrypt and SHA-crypt; they are upgraded to Argon2id when the user next logs in.
+use std::env;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+use auth_service::domains::data_stores::UserStoreError;
+use auth_service::domains::email::Email;
+use auth_service::domains::password_hash::PasswordHash;
+use auth_service::domains::user_id::UserId;
+use auth_service::get_postgres_pool;
+use auth_service::services::data_stores::postgres_user_store::PostgresUserStore;
+use auth_service::utils::constants::DATABASE_URL;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct ImportedUser {
+    id: Option<String>,
+    email: String,
+    #[serde(rename = "passwordHash")]
+    password_hash: String,
+    #[serde(rename = "requires2FA", default)]
+    requires_2fa: bool,
+}
+
+#[tokio::main]
+async fn main() {
+    let path = env::args()
+        .nth(1)
+        .expect("Usage: import_users <users.jsonl>");
+    let file = File::open(&path).expect("Failed to open import file");
+
+    let pg_pool = get_postgres_pool(&DATABASE_URL)
+        .await
+        .expect("Failed to create Postgres connection pool!");
+    sqlx::migrate!()
+        .run(&pg_pool)
+        .await
+        .expect("Failed to run migrations");
+    let user_store = PostgresUserStore::new(pg_pool);
+
+    let (mut imported, mut skipped) = (0, 0);
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line_number = index + 1;
+        let line = line.expect("Failed to read import file");
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match import_line(&user_store, &line).await {
+            Ok(()) => imported += 1,
+            Err(reason) => {
+                skipped += 1;
+                println!("Skipping line {}: {}", line_number, reason);
+            }
+        }
+    }
+
+    println!("Imported {} users, skipped {}", imported, skipped);
+}
+
+async fn import_line(user_store: &PostgresUserStore, line: &str) -> Result<(), String> {
+    let user: ImportedUser = serde_json::from_str(line).map_err(|e| e.to_string())?;
+
+    let id = match user.id {
+        Some(id) => UserId::parse(id)?,
+        None => UserId::default(),
+    };
+    let email = Email::parse(user.email)?;
+    let password_hash = PasswordHash::parse(user.password_hash)?;
+
+    user_store
+        .import_user(&id, &email, &password_hash, user.requires_2fa)
+        .await
+        .map_err(|e| match e {
+            UserStoreError::UserAlreadyExists => "user already exists".to_string(),
+            _ => "unexpected database error".to_string(),
+        })
+}
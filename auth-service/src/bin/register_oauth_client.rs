@@ -0,0 +1,77 @@
+// Registers an application that may use `/oauth/authorize` and `/oauth/token`.
+//
+// Usage: cargo run --bin register_oauth_client -- <client_id> <name> <redirect_uri>...
+//        cargo run --bin register_oauth_client -- --confidential <client_id> <name> <scope>...
+//
+// Codes are only ever sent to one of the given redirect URIs, which must match
+// the `redirect_uri` of an authorization request exactly.
+//
+// Confidential clients are services that get tokens for themselves with the
+// client credentials grant, limited to the given scopes. Their secret is
+// generated here and printed once; only its hash is stored.
+use std::env;
+
+use auth_service::domains::client_secret::ClientSecret;
+use auth_service::domains::data_stores::{OAuthClientStore, OAuthClientStoreError};
+use auth_service::domains::oauth_client::OAuthClient;
+use auth_service::get_postgres_pool;
+use auth_service::services::data_stores::postgres_oauth_client_store::PostgresOAuthClientStore;
+use auth_service::utils::constants::DATABASE_URL;
+
+const USAGE: &str = "Usage: register_oauth_client <client_id> <name> <redirect_uri>...
+       register_oauth_client --confidential <client_id> <name> <scope>...";
+
+#[tokio::main]
+async fn main() {
+    let mut args = env::args().skip(1).peekable();
+    let confidential = args.next_if(|arg| arg == "--confidential").is_some();
+    let client_id = args.next().expect(USAGE);
+    let name = args.next().expect(USAGE);
+    let rest: Vec<String> = args.collect();
+
+    let secret = confidential.then(ClientSecret::generate);
+    let client = match &secret {
+        Some(secret) => OAuthClient::confidential(client_id, name, secret.hash(), rest),
+        None => OAuthClient::new(client_id, name, rest),
+    };
+    let client = match client {
+        Ok(c) => c,
+        Err(reason) => {
+            eprintln!("{}\n{}", reason, USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    let pg_pool = get_postgres_pool(&DATABASE_URL)
+        .await
+        .expect("Failed to create Postgres connection pool!");
+    sqlx::migrate!()
+        .run(&pg_pool)
+        .await
+        .expect("Failed to run migrations");
+    let oauth_client_store = PostgresOAuthClientStore::new(pg_pool);
+
+    match oauth_client_store.add_client(client.clone()).await {
+        Ok(()) => match secret {
+            Some(secret) => println!(
+                "Registered confidential client '{}' with scopes {}\nClient secret (shown only once): {}",
+                client.client_id,
+                client.scopes.join(", "),
+                secret.as_ref()
+            ),
+            None => println!(
+                "Registered client '{}' with redirect URIs {}",
+                client.client_id,
+                client.redirect_uris.join(", ")
+            ),
+        },
+        Err(OAuthClientStoreError::ClientAlreadyExists) => {
+            eprintln!("Client '{}' already exists", client.client_id);
+            std::process::exit(1);
+        }
+        Err(_) => {
+            eprintln!("Unexpected database error");
+            std::process::exit(1);
+        }
+    }
+}
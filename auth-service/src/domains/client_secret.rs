@@ -0,0 +1,111 @@
+use std::fmt;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use zeroize::Zeroize;
+
+// A confidential client's secret. We generate it and only show it once, at
+// registration, so like `Password` it can't be copied around or logged.
+pub struct ClientSecret(String);
+
+impl ClientSecret {
+    // 256 random bits
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self(URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    pub fn parse(s: String) -> Result<Self, String> {
+        if s.is_empty() {
+            return Err("Client secret cannot be empty".into());
+        }
+
+        Ok(Self(s))
+    }
+
+    // Secrets are random rather than chosen by a person, so a fast hash is
+    // enough and client authentication doesn't compete with logins for the
+    // password hashing workers
+    pub fn hash(&self) -> ClientSecretHash {
+        ClientSecretHash(hex(&Sha256::digest(self.0.as_bytes())))
+    }
+}
+
+impl AsRef<str> for ClientSecret {
+    fn as_ref(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl Drop for ClientSecret {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+// What gets stored for a confidential client: the hex SHA-256 of its secret
+#[derive(Clone, PartialEq)]
+pub struct ClientSecretHash(String);
+
+impl ClientSecretHash {
+    pub fn parse(s: String) -> Result<Self, String> {
+        if s.len() != 64 || !s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+            return Err("Client secret hash must be a hex SHA-256 digest".into());
+        }
+
+        Ok(Self(s))
+    }
+
+    // Looks at every byte, so the time taken doesn't tell how much of a
+    // guess was right
+    pub fn matches(&self, secret: &ClientSecret) -> bool {
+        let candidate = secret.hash();
+        candidate
+            .0
+            .bytes()
+            .zip(self.0.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+    }
+}
+
+impl AsRef<str> for ClientSecretHash {
+    fn as_ref(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl fmt::Debug for ClientSecretHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ClientSecretHash(..)")
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_secrets_match_only_their_own_hash() {
+        let secret = ClientSecret::generate();
+        let hash = ClientSecretHash::parse(secret.hash().as_ref().to_string()).unwrap();
+
+        assert!(hash.matches(&secret));
+        assert!(!hash.matches(&ClientSecret::generate()));
+        assert!(!hash.matches(&ClientSecret::parse("guess".to_string()).unwrap()));
+    }
+
+    #[test]
+    fn rejects_anything_but_a_hex_digest() {
+        assert!(ClientSecretHash::parse("secret".to_string()).is_err());
+        assert!(ClientSecretHash::parse("A".repeat(64)).is_err());
+        assert!(ClientSecretHash::parse("a".repeat(64)).is_ok());
+    }
+}
@@ -1,7 +1,8 @@
-use crate::domains::email::Email;
+use crate::domains::{email::Email, password::Password};
 
-use super::user;
+use super::{oauth_client::OAuthClient, session::Session, user, user_id::UserId};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use rand::{random, Rng};
 use uuid::Uuid;
 
@@ -10,6 +11,8 @@ pub enum UserStoreError {
     UserAlreadyExists,
     UserNotFound,
     InvalidCredentials,
+    // Too busy to take the request right now, worth retrying later
+    Unavailable,
     UnexpectedError,
 }
 
@@ -19,29 +22,60 @@ pub enum BannedTokenError {
 
 #[async_trait]
 pub trait UserStore: Clone + Send + Sync + 'static {
-    async fn add_user(&mut self, user: user::User) -> Result<(), UserStoreError>;
+    async fn add_user(&self, user: user::NewUser) -> Result<(), UserStoreError>;
     async fn get_user(&self, email: &Email) -> Result<user::User, UserStoreError>;
-    async fn validate_user(&self, email: &Email, password: &str) -> Result<(), UserStoreError>;
+    async fn get_user_by_id(&self, id: &UserId) -> Result<user::User, UserStoreError>;
+    async fn validate_user(&self, email: &Email, password: &Password)
+        -> Result<(), UserStoreError>;
+    // Changing the email ends every session of the user, like `end_all_sessions`
+    async fn update_email(&self, id: &UserId, new_email: Email) -> Result<(), UserStoreError>;
+    // Tokens only stay valid while they carry the user's current session
+    // version. Stores may serve it from a short-lived cache, so the check
+    // doesn't cost a database round trip per request.
+    async fn get_session_version(&self, id: &UserId) -> Result<u64, UserStoreError>;
+    // Bumps the session version, returning the new one
+    async fn end_all_sessions(&self, id: &UserId) -> Result<u64, UserStoreError>;
 }
+// Tokens are banned by their `jti`, so the token itself is never kept. An
+// entry only has to be remembered until `expires_at`, after which validation
+// rejects the token anyway.
 #[async_trait]
 pub trait BannedTokenStore: Clone + Send + Sync + 'static {
-    async fn add_banned_token(&mut self, banned_token: String) -> Result<(), BannedTokenError>;
-    async fn does_token_exist(&self, banned_token: String) -> bool;
+    async fn add_banned_token(
+        &self,
+        jti: String,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), BannedTokenError>;
+    async fn does_token_exist(&self, jti: String) -> bool;
 }
 
+// This value determines how long a 2FA code can be used after it was added
+pub const TWO_FA_CODE_TTL_SECONDS: i64 = 600; // 10 minutes
+
+// Codes older than `TWO_FA_CODE_TTL_SECONDS` are treated as if they were
+// never added
 #[async_trait::async_trait]
-pub trait TwoFACodeStore {
+pub trait TwoFACodeStore: Clone + Send + Sync + 'static {
     async fn add_code(
-        &mut self,
+        &self,
         email: Email,
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError>;
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError>;
+    async fn remove_code(&self, email: &Email) -> Result<(), TwoFACodeStoreError>;
     async fn get_code(
         &self,
         email: &Email,
-    ) -> Result<&(LoginAttemptId, TwoFACode), TwoFACodeStoreError>;
+    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError>;
+    // Removes the pending code only if both the login attempt and the code
+    // match, in one step, so a code is redeemed at most once and a stale
+    // attempt can't remove the one that replaced it
+    async fn take_code_if(
+        &self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code: &TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError>;
 }
 
 #[derive(Debug, PartialEq)]
@@ -50,6 +84,54 @@ pub enum TwoFACodeStoreError {
     UnexpectedError,
 }
 
+// Registered OAuth clients, looked up by their public client id
+#[async_trait]
+pub trait OAuthClientStore: Clone + Send + Sync + 'static {
+    async fn add_client(&self, client: OAuthClient) -> Result<(), OAuthClientStoreError>;
+    async fn get_client(&self, client_id: &str) -> Result<OAuthClient, OAuthClientStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OAuthClientStoreError {
+    ClientAlreadyExists,
+    ClientNotFound,
+    UnexpectedError,
+}
+
+// How often a session's `last_seen_at` is written at most. Stores may skip
+// touches closer together than this, so verifying a token stays cheap.
+pub const SESSION_LAST_SEEN_RESOLUTION_SECONDS: i64 = 60;
+
+// Sessions of logged in users, kept next to the banned tokens: a session is
+// ended by banning its token and then forgetting it here
+#[async_trait]
+pub trait SessionStore: Clone + Send + Sync + 'static {
+    // Expired sessions may be kept until the store sweeps them, so callers
+    // check `Session::is_expired` on what they get back
+    async fn add_session(&self, session: Session) -> Result<(), SessionStoreError>;
+    async fn get_sessions(&self, user_id: &UserId) -> Result<Vec<Session>, SessionStoreError>;
+    // Unknown sessions are ignored, tokens issued to OAuth clients have none
+    async fn touch_session(
+        &self,
+        id: &str,
+        seen_at: DateTime<Utc>,
+    ) -> Result<(), SessionStoreError>;
+    // Only removes the session if it belongs to the user
+    async fn remove_session(
+        &self,
+        user_id: &UserId,
+        id: &str,
+    ) -> Result<Session, SessionStoreError>;
+    async fn remove_sessions(&self, user_id: &UserId) -> Result<(), SessionStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SessionStoreError {
+    SessionAlreadyExists,
+    SessionNotFound,
+    UnexpectedError,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct LoginAttemptId(String);
 
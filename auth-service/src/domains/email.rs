@@ -1,18 +1,113 @@
+// Limits from RFC 5321 section 4.5.3.1
+const MAX_EMAIL_LENGTH: usize = 254;
+const MAX_LOCAL_PART_LENGTH: usize = 64;
+const MAX_DOMAIN_LENGTH: usize = 253;
+const MAX_LABEL_LENGTH: usize = 63;
+
+// Characters allowed in an RFC 5322 dot-atom besides letters and digits
+const ATEXT_SPECIALS: &str = "!#$%&'*+-/=?^_`{|}~";
+
+// Holds the canonical form of an address: lowercased local part and an
+// ASCII (punycode) lowercased domain. Two addresses that only differ by case
+// or by how the domain is encoded are the same `Email`.
 #[derive(Clone, PartialEq, Hash, Eq, Debug)]
 pub struct Email(String);
 
 impl Email {
     pub fn parse(s: String) -> Result<Self, String> {
-        if s.trim().is_empty() {
+        let s = s.trim();
+        if s.is_empty() {
             return Err("Email cannot be empty".into());
         }
 
-        if !s.contains("@") {
-            return Err("Email must contain '@' symbol".into());
+        let (local, domain) = match s.rsplit_once('@') {
+            Some(parts) => parts,
+            None => return Err("Email must contain '@' symbol".into()),
+        };
+
+        let local = parse_local_part(local)?;
+        let domain = parse_domain(domain)?;
+
+        let email = format!("{}@{}", local, domain);
+        if email.len() > MAX_EMAIL_LENGTH {
+            return Err(format!(
+                "Email cannot be longer than {} characters",
+                MAX_EMAIL_LENGTH
+            ));
+        }
+
+        Ok(Self(email))
+    }
+}
+
+// Only the unquoted dot-atom form is accepted, with UTF-8 allowed as in RFC 6531
+fn parse_local_part(local: &str) -> Result<String, String> {
+    if local.is_empty() {
+        return Err("Email local part cannot be empty".into());
+    }
+
+    if local.len() > MAX_LOCAL_PART_LENGTH {
+        return Err(format!(
+            "Email local part cannot be longer than {} characters",
+            MAX_LOCAL_PART_LENGTH
+        ));
+    }
+
+    for atom in local.split('.') {
+        if atom.is_empty() {
+            return Err("Email local part has a misplaced '.'".into());
+        }
+
+        let valid = atom
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ATEXT_SPECIALS.contains(c) || !c.is_ascii());
+        if !valid {
+            return Err("Email local part contains invalid characters".into());
+        }
+    }
+
+    Ok(local.to_lowercase())
+}
+
+// Internationalized domains are converted to their ASCII form using IDNA
+fn parse_domain(domain: &str) -> Result<String, String> {
+    if domain.is_empty() {
+        return Err("Email domain cannot be empty".into());
+    }
+
+    let domain = idna::domain_to_ascii(domain).map_err(|_| "Email domain is not valid")?;
+
+    if domain.len() > MAX_DOMAIN_LENGTH {
+        return Err(format!(
+            "Email domain cannot be longer than {} characters",
+            MAX_DOMAIN_LENGTH
+        ));
+    }
+
+    let labels: Vec<&str> = domain.split('.').collect();
+    if labels.len() < 2 {
+        return Err("Email domain must contain a '.'".into());
+    }
+
+    for label in &labels {
+        let valid = !label.is_empty()
+            && label.len() <= MAX_LABEL_LENGTH
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !valid {
+            return Err("Email domain is not valid".into());
         }
+    }
 
-        Ok(Self(s))
+    if labels
+        .last()
+        .is_some_and(|tld| tld.chars().all(|c| c.is_ascii_digit()))
+    {
+        return Err("Email domain is not valid".into());
     }
+
+    Ok(domain)
 }
 
 impl AsRef<str> for Email {
@@ -20,3 +115,112 @@ impl AsRef<str> for Email {
         self.0.as_str()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Email;
+    use fake::faker::internet::en::SafeEmail;
+    use fake::Fake;
+
+    #[derive(Debug, Clone)]
+    struct ValidEmailFixture(pub String);
+
+    impl quickcheck::Arbitrary for ValidEmailFixture {
+        fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+            let email = SafeEmail().fake_with_rng(g);
+            Self(email)
+        }
+    }
+
+    #[test]
+    fn empty_string_is_rejected() {
+        assert!(Email::parse("".to_string()).is_err());
+    }
+
+    #[test]
+    fn email_missing_at_symbol_is_rejected() {
+        assert!(Email::parse("ravigmail.com".to_string()).is_err());
+    }
+
+    #[test]
+    fn email_missing_local_part_is_rejected() {
+        assert!(Email::parse("@gmail.com".to_string()).is_err());
+    }
+
+    #[test]
+    fn email_missing_domain_is_rejected() {
+        assert!(Email::parse("ravi@".to_string()).is_err());
+    }
+
+    #[test]
+    fn email_with_misplaced_dots_is_rejected() {
+        for email in ["ravi.@gmail.com", ".ravi@gmail.com", "ra..vi@gmail.com"] {
+            assert!(Email::parse(email.to_string()).is_err(), "{}", email);
+        }
+    }
+
+    #[test]
+    fn email_with_invalid_characters_is_rejected() {
+        for email in ["ra vi@gmail.com", "ra(vi)@gmail.com", "ravi@gm_ail.com"] {
+            assert!(Email::parse(email.to_string()).is_err(), "{}", email);
+        }
+    }
+
+    #[test]
+    fn email_with_invalid_domain_is_rejected() {
+        for email in ["ravi@localhost", "ravi@-gmail.com", "ravi@gmail..com", "ravi@1.2.3.4"] {
+            assert!(Email::parse(email.to_string()).is_err(), "{}", email);
+        }
+    }
+
+    #[test]
+    fn overlong_emails_are_rejected() {
+        let local = "a".repeat(65);
+        assert!(Email::parse(format!("{}@gmail.com", local)).is_err());
+
+        let label = "a".repeat(64);
+        assert!(Email::parse(format!("ravi@{}.com", label)).is_err());
+
+        let domain = format!("{}.com", vec!["a".repeat(60); 5].join("."));
+        assert!(Email::parse(format!("ravi@{}", domain)).is_err());
+    }
+
+    #[test]
+    fn email_is_normalized_to_lowercase() {
+        let email = Email::parse("  Ravi.Lukkani@GMail.COM ".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "ravi.lukkani@gmail.com");
+    }
+
+    #[test]
+    fn internationalized_domain_is_converted_to_ascii() {
+        let email = Email::parse("ravi@Bücher.example".to_string()).unwrap();
+        assert_eq!(email.as_ref(), "ravi@xn--bcher-kva.example");
+        assert_eq!(
+            email,
+            Email::parse("ravi@xn--bcher-kva.example".to_string()).unwrap()
+        );
+    }
+
+    #[test]
+    fn plus_addressing_is_accepted() {
+        assert!(Email::parse("ravi+test@gmail.com".to_string()).is_ok());
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn valid_emails_are_parsed_successfully(valid_email: ValidEmailFixture) -> bool {
+        Email::parse(valid_email.0).is_ok()
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn parsing_is_idempotent(valid_email: ValidEmailFixture) -> bool {
+        let email = Email::parse(valid_email.0).unwrap();
+        Email::parse(email.as_ref().to_owned()) == Ok(email)
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn emails_differing_only_by_case_are_equal(valid_email: ValidEmailFixture) -> bool {
+        let upper = Email::parse(valid_email.0.to_uppercase());
+        let lower = Email::parse(valid_email.0.to_lowercase());
+        upper.is_ok() && upper == lower
+    }
+}
@@ -1,12 +1,59 @@
 use serde::{Deserialize, Serialize};
+
+use super::password_policy::PasswordPolicyViolation;
+
 #[derive(Debug)]
 pub enum AuthAPIError {
     UserAlreadyExists,
     InvalidCredentials,
+    InvalidPassword(Vec<PasswordPolicyViolation>),
     UnexpectedError,
+    ServiceUnavailable,
     IncorrectCredentials,
     MissingToken,
     InvalidToken,
+    // Valid token, but not one that allows this
+    InsufficientScope,
+    // No such session among the caller's
+    SessionNotFound,
+    // Locked out after too many failed logins, for the given time
+    TooManyLoginAttempts(chrono::Duration),
+}
+
+// Errors defined by RFC 6749, returned as `{"error": ..., "error_description": ...}`
+// from the token endpoint or appended to the client's redirect URI
+#[derive(Debug, Clone, PartialEq)]
+pub enum OAuthError {
+    InvalidRequest(&'static str),
+    InvalidClient,
+    InvalidGrant,
+    InvalidScope,
+    UnsupportedGrantType,
+    UnsupportedResponseType,
+    AccessDenied,
+    ServerError,
+}
+
+impl OAuthError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            OAuthError::InvalidRequest(_) => "invalid_request",
+            OAuthError::InvalidClient => "invalid_client",
+            OAuthError::InvalidGrant => "invalid_grant",
+            OAuthError::InvalidScope => "invalid_scope",
+            OAuthError::UnsupportedGrantType => "unsupported_grant_type",
+            OAuthError::UnsupportedResponseType => "unsupported_response_type",
+            OAuthError::AccessDenied => "access_denied",
+            OAuthError::ServerError => "server_error",
+        }
+    }
+
+    pub fn description(&self) -> Option<&'static str> {
+        match self {
+            OAuthError::InvalidRequest(description) => Some(description),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
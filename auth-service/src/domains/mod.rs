@@ -1,8 +1,15 @@
+pub mod client_secret;
 pub mod data_stores;
 pub mod email;
 pub mod error;
+pub mod oauth_client;
 pub mod password;
+pub mod password_hash;
+pub mod password_policy;
+pub mod pkce;
+pub mod session;
 pub mod user;
+pub mod user_id;
 
 pub mod email_client;
 pub use email_client::*;
@@ -0,0 +1,145 @@
+use url::Url;
+
+use super::client_secret::{ClientSecret, ClientSecretHash};
+
+// An application allowed to send users through `/oauth/authorize`. Codes are
+// only ever delivered to one of its registered redirect URIs.
+//
+// Confidential clients are services acting on their own behalf. They prove
+// who they are with a secret and get tokens for the scopes they were
+// registered with through the client credentials grant.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OAuthClient {
+    pub client_id: String,
+    pub name: String,
+    pub redirect_uris: Vec<String>,
+    pub secret_hash: Option<ClientSecretHash>,
+    pub scopes: Vec<String>,
+}
+
+impl OAuthClient {
+    pub fn new(
+        client_id: String,
+        name: String,
+        redirect_uris: Vec<String>,
+    ) -> Result<Self, String> {
+        if client_id.trim().is_empty() {
+            return Err("Client id must not be empty".to_string());
+        }
+        if redirect_uris.is_empty() {
+            return Err("At least one redirect URI is required".to_string());
+        }
+        for redirect_uri in &redirect_uris {
+            let url = Url::parse(redirect_uri).map_err(|e| format!("{}: {}", redirect_uri, e))?;
+            if url.fragment().is_some() {
+                return Err(format!(
+                    "{}: redirect URIs can't have a fragment",
+                    redirect_uri
+                ));
+            }
+        }
+
+        Ok(OAuthClient {
+            client_id,
+            name,
+            redirect_uris,
+            secret_hash: None,
+            scopes: Vec::new(),
+        })
+    }
+
+    pub fn confidential(
+        client_id: String,
+        name: String,
+        secret_hash: ClientSecretHash,
+        scopes: Vec<String>,
+    ) -> Result<Self, String> {
+        if client_id.trim().is_empty() {
+            return Err("Client id must not be empty".to_string());
+        }
+        if scopes.is_empty() {
+            return Err("At least one scope is required".to_string());
+        }
+        for scope in &scopes {
+            if scope.is_empty() || scope.contains(char::is_whitespace) {
+                return Err(format!("'{}' is not a valid scope", scope));
+            }
+        }
+
+        Ok(OAuthClient {
+            client_id,
+            name,
+            redirect_uris: Vec::new(),
+            secret_hash: Some(secret_hash),
+            scopes,
+        })
+    }
+
+    // Public clients have no secret and can't authenticate at all
+    pub fn authenticate(&self, secret: &ClientSecret) -> bool {
+        self.secret_hash
+            .as_ref()
+            .is_some_and(|hash| hash.matches(secret))
+    }
+
+    pub fn allows_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+
+    // Only exact matches count, so a registered URI can't be extended with a
+    // path or query that points somewhere else
+    pub fn allows_redirect(&self, redirect_uri: &str) -> bool {
+        self.redirect_uris.iter().any(|uri| uri == redirect_uri)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(redirect_uris: &[&str]) -> Result<OAuthClient, String> {
+        OAuthClient::new(
+            "spa".to_string(),
+            "Single page app".to_string(),
+            redirect_uris.iter().map(|uri| uri.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn new_rejects_invalid_redirect_uris() {
+        assert!(client(&[]).is_err());
+        assert!(client(&["/callback"]).is_err());
+        assert!(client(&["https://app.example.com/callback#token"]).is_err());
+        assert!(client(&["https://app.example.com/callback"]).is_ok());
+    }
+
+    #[test]
+    fn allows_only_exact_redirect_uris() {
+        let client = client(&["https://app.example.com/callback"]).unwrap();
+
+        assert!(client.allows_redirect("https://app.example.com/callback"));
+        assert!(!client.allows_redirect("https://app.example.com/callback/evil"));
+        assert!(!client.allows_redirect("https://app.example.com/callback?next=evil"));
+        assert!(!client.allows_redirect("https://app.example.com"));
+    }
+
+    #[test]
+    fn only_confidential_clients_authenticate() {
+        let secret = ClientSecret::generate();
+        let service = OAuthClient::confidential(
+            "reports".to_string(),
+            "Reporting service".to_string(),
+            secret.hash(),
+            vec!["introspect".to_string()],
+        )
+        .unwrap();
+
+        assert!(service.authenticate(&secret));
+        assert!(!service.authenticate(&ClientSecret::generate()));
+        assert!(service.allows_scope("introspect"));
+        assert!(!service.allows_scope("admin"));
+
+        let spa = client(&["https://app.example.com/callback"]).unwrap();
+        assert!(!spa.authenticate(&secret));
+    }
+}
@@ -1,16 +1,18 @@
-#[derive(Clone, PartialEq)]
+use zeroize::Zeroize;
+
+// A plaintext password as the user typed it. Deliberately not `Clone` or
+// `Debug` so it can't be copied around or end up in logs, and wiped from
+// memory once dropped.
 pub struct Password(String);
 
 impl Password {
+    // Only structural checks live here; length and strength rules are
+    // deployment specific and enforced by `PasswordPolicy` on signup.
     pub fn parse(s: String) -> Result<Self, String> {
         if s.trim().is_empty() {
             return Err("Password cannot be empty".into());
         }
 
-        if s.len() < 8 {
-            return Err("Password should be more then 8 chars".into());
-        }
-
         Ok(Self(s))
     }
 }
@@ -20,3 +22,9 @@ impl AsRef<str> for Password {
         self.0.as_str()
     }
 }
+
+impl Drop for Password {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
@@ -0,0 +1,66 @@
+use std::fmt;
+
+use crate::utils::password_hashing::HashFormat;
+
+// A stored password hash in one of the formats we can verify. This is what a
+// `User` carries; the plaintext `Password` never outlives the request.
+#[derive(Clone, PartialEq)]
+pub struct PasswordHash(String);
+
+impl PasswordHash {
+    pub fn parse(s: String) -> Result<Self, String> {
+        match HashFormat::detect(&s) {
+            Some(_) => Ok(Self(s)),
+            None => Err("Unsupported password hash format".into()),
+        }
+    }
+}
+
+impl AsRef<str> for PasswordHash {
+    fn as_ref(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+// Hashes aren't secret the way passwords are, but they can still be cracked
+// offline, so keep them out of logs
+impl fmt::Debug for PasswordHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PasswordHash(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_supported_formats() {
+        let hashes = [
+            "$argon2id$v=19$m=4096,t=1,p=1$c2FsdA$aGFzaA",
+            "$2b$04$abcdefghijklmnopqrstuu",
+            "$6$rounds=1000$salt$hash",
+        ];
+
+        for hash in hashes {
+            assert!(PasswordHash::parse(hash.to_string()).is_ok(), "{}", hash);
+        }
+    }
+
+    #[test]
+    fn rejects_plaintext_and_unknown_formats() {
+        let hashes = ["password123", "5f4dcc3b5aa765d61d8327deb882cf99", ""];
+
+        for hash in hashes {
+            assert!(PasswordHash::parse(hash.to_string()).is_err(), "{}", hash);
+        }
+    }
+
+    #[test]
+    fn debug_output_hides_the_hash() {
+        let hash =
+            PasswordHash::parse("$argon2id$v=19$m=4096,t=1,p=1$c2FsdA$aGFzaA".to_string()).unwrap();
+
+        assert_eq!(format!("{:?}", hash), "PasswordHash(..)");
+    }
+}
@@ -0,0 +1,320 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::sync::Arc;
+
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+
+use super::password::Password;
+
+// Length of the SHA-1 prefix used to bucket hashes, same as the HIBP range API
+const HASH_PREFIX_LENGTH: usize = 5;
+
+// Rules a new password has to satisfy. Lengths are counted in Unicode
+// characters and `min_strength` is a zxcvbn score from 0 (weakest) to 4.
+#[derive(Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub min_strength: u8,
+    pub breached_passwords: Option<Arc<BreachedPasswordList>>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 8,
+            max_length: 128,
+            min_strength: 0,
+            breached_passwords: None,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    // Returns every rule the password breaks, so the UI can show them all at once.
+    // `user_inputs` (e.g. the email) are penalised when they appear in the password.
+    pub fn validate(
+        &self,
+        password: &Password,
+        user_inputs: &[&str],
+    ) -> Result<(), Vec<PasswordPolicyViolation>> {
+        let password = password.as_ref();
+        let mut violations = Vec::new();
+
+        let length = password.chars().count();
+        if length < self.min_length {
+            violations.push(PasswordPolicyViolation::TooShort {
+                min_length: self.min_length,
+            });
+        }
+        if length > self.max_length {
+            violations.push(PasswordPolicyViolation::TooLong {
+                max_length: self.max_length,
+            });
+        }
+
+        // Scoring is quadratic in the length, so skip it for oversized input
+        if length <= self.max_length && self.min_strength > 0 {
+            let entropy = zxcvbn::zxcvbn(password, user_inputs);
+            let score = u8::from(entropy.score());
+            if score < self.min_strength {
+                violations.push(PasswordPolicyViolation::TooWeak {
+                    score,
+                    min_strength: self.min_strength,
+                    feedback: entropy.feedback().map(|f| f.to_string()),
+                });
+            }
+        }
+
+        if self
+            .breached_passwords
+            .as_ref()
+            .is_some_and(|list| list.contains(password))
+        {
+            violations.push(PasswordPolicyViolation::Breached);
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PasswordPolicyViolation {
+    TooShort {
+        min_length: usize,
+    },
+    TooLong {
+        max_length: usize,
+    },
+    TooWeak {
+        score: u8,
+        min_strength: u8,
+        feedback: Option<String>,
+    },
+    Breached,
+}
+
+impl PasswordPolicyViolation {
+    pub fn code(&self) -> &'static str {
+        match self {
+            PasswordPolicyViolation::TooShort { .. } => "too_short",
+            PasswordPolicyViolation::TooLong { .. } => "too_long",
+            PasswordPolicyViolation::TooWeak { .. } => "too_weak",
+            PasswordPolicyViolation::Breached => "breached",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            PasswordPolicyViolation::TooShort { min_length } => {
+                format!("Password must be at least {} characters", min_length)
+            }
+            PasswordPolicyViolation::TooLong { max_length } => {
+                format!("Password must be at most {} characters", max_length)
+            }
+            PasswordPolicyViolation::TooWeak { feedback, .. } => match feedback {
+                Some(feedback) => format!("Password is too easy to guess. {}", feedback.trim()),
+                None => "Password is too easy to guess".to_string(),
+            },
+            PasswordPolicyViolation::Breached => {
+                "Password has appeared in a data breach, please choose another one".to_string()
+            }
+        }
+    }
+}
+
+// Shape of a single violation in the signup error response
+#[derive(Debug, Serialize, serde::Deserialize, PartialEq)]
+pub struct PasswordPolicyError {
+    pub code: String,
+    pub message: String,
+}
+
+impl From<&PasswordPolicyViolation> for PasswordPolicyError {
+    fn from(violation: &PasswordPolicyViolation) -> Self {
+        PasswordPolicyError {
+            code: violation.code().to_string(),
+            message: violation.message(),
+        }
+    }
+}
+
+// Known-breached passwords, loaded from a local copy of the Have I Been Pwned
+// SHA-1 list (`<SHA1 HEX>:<COUNT>` per line). Hashes are bucketed by their
+// 5 character prefix like the k-anonymity range API, so a lookup only ever
+// touches one bucket and the plaintext is never stored.
+#[derive(Default)]
+pub struct BreachedPasswordList {
+    ranges: HashMap<String, HashSet<String>>,
+}
+
+impl BreachedPasswordList {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        Self::from_reader(BufReader::new(file))
+    }
+
+    pub fn from_reader(reader: impl BufRead) -> io::Result<Self> {
+        let mut list = BreachedPasswordList::default();
+
+        for line in reader.lines() {
+            let line = line?;
+            let hash = line.split(':').next().unwrap_or_default().trim();
+            if hash.len() != 40 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                continue;
+            }
+
+            let hash = hash.to_ascii_uppercase();
+            let (prefix, suffix) = hash.split_at(HASH_PREFIX_LENGTH);
+            list.ranges
+                .entry(prefix.to_string())
+                .or_default()
+                .insert(suffix.to_string());
+        }
+
+        Ok(list)
+    }
+
+    pub fn contains(&self, password: &str) -> bool {
+        let hash = sha1_hex(password);
+        let (prefix, suffix) = hash.split_at(HASH_PREFIX_LENGTH);
+
+        self.ranges
+            .get(prefix)
+            .is_some_and(|suffixes| suffixes.contains(suffix))
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.values().map(HashSet::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+fn sha1_hex(password: &str) -> String {
+    Sha1::digest(password.as_bytes())
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn password(s: &str) -> Password {
+        Password::parse(s.to_string()).unwrap()
+    }
+
+    #[test]
+    fn length_is_counted_in_characters() {
+        let policy = PasswordPolicy::default();
+
+        // 7 characters but 14 bytes
+        let result = policy.validate(&password("ééééééé"), &[]);
+        assert_eq!(
+            result,
+            Err(vec![PasswordPolicyViolation::TooShort { min_length: 8 }])
+        );
+
+        assert_eq!(policy.validate(&password("éééééééé"), &[]), Ok(()));
+    }
+
+    #[test]
+    fn overlong_passwords_are_rejected() {
+        let policy = PasswordPolicy {
+            max_length: 10,
+            ..PasswordPolicy::default()
+        };
+
+        let result = policy.validate(&password("abcdefghijk"), &[]);
+        assert_eq!(
+            result,
+            Err(vec![PasswordPolicyViolation::TooLong { max_length: 10 }])
+        );
+    }
+
+    #[test]
+    fn weak_passwords_are_rejected_when_strength_is_required() {
+        let policy = PasswordPolicy {
+            min_strength: 3,
+            ..PasswordPolicy::default()
+        };
+
+        let violations = policy.validate(&password("password123"), &[]).unwrap_err();
+        assert!(matches!(
+            violations.as_slice(),
+            [PasswordPolicyViolation::TooWeak { .. }]
+        ));
+
+        assert_eq!(
+            policy.validate(&password("correct horse battery staple"), &[]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn user_inputs_lower_the_strength() {
+        let policy = PasswordPolicy {
+            min_strength: 3,
+            ..PasswordPolicy::default()
+        };
+
+        let candidate = password("ravilukkani1984");
+        assert!(policy.validate(&candidate, &[]).is_ok());
+        assert!(policy
+            .validate(&candidate, &["ravilukkani1984@gmail.com", "ravilukkani1984"])
+            .is_err());
+    }
+
+    #[test]
+    fn breached_passwords_are_rejected() {
+        // SHA-1 of "password123" and "letmein"
+        let file = "CBFDAC6008F9CAB4083784CBD1874F76618D2A97:251682\n\
+                    B7A875FC1EA228B9061041B7CEC4BD3C52AB3CE3:1000\n\
+                    not a hash line\n";
+        let list = BreachedPasswordList::from_reader(file.as_bytes()).unwrap();
+        assert_eq!(list.len(), 2);
+
+        let policy = PasswordPolicy {
+            breached_passwords: Some(Arc::new(list)),
+            ..PasswordPolicy::default()
+        };
+
+        assert_eq!(
+            policy.validate(&password("password123"), &[]),
+            Err(vec![PasswordPolicyViolation::Breached])
+        );
+        assert_eq!(policy.validate(&password("not-in-the-list"), &[]), Ok(()));
+    }
+
+    #[test]
+    fn all_violations_are_reported() {
+        let list = BreachedPasswordList::from_reader(
+            "B7A875FC1EA228B9061041B7CEC4BD3C52AB3CE3:1000".as_bytes(),
+        )
+        .unwrap();
+        let policy = PasswordPolicy {
+            min_strength: 2,
+            breached_passwords: Some(Arc::new(list)),
+            ..PasswordPolicy::default()
+        };
+
+        let codes: Vec<&str> = policy
+            .validate(&password("letmein"), &[])
+            .unwrap_err()
+            .iter()
+            .map(PasswordPolicyViolation::code)
+            .collect();
+        assert_eq!(codes, vec!["too_short", "too_weak", "breached"]);
+    }
+}
@@ -0,0 +1,97 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+// Proof Key for Code Exchange (RFC 7636). The client keeps the verifier to
+// itself and sends only its hash with the authorization request, so a stolen
+// authorization code is useless without it.
+
+// Only the S256 method is accepted, "plain" would send the secret in the clear
+pub const CODE_CHALLENGE_METHOD_S256: &str = "S256";
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodeVerifier(String);
+
+impl CodeVerifier {
+    pub fn parse(verifier: String) -> Result<Self, String> {
+        let valid_length = (43..=128).contains(&verifier.len());
+        let valid_chars = verifier
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~'));
+
+        if valid_length && valid_chars {
+            Ok(CodeVerifier(verifier))
+        } else {
+            Err("Code verifier must be 43 to 128 unreserved characters".to_string())
+        }
+    }
+}
+
+impl AsRef<str> for CodeVerifier {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CodeChallenge(String);
+
+impl CodeChallenge {
+    pub fn parse(challenge: String) -> Result<Self, String> {
+        // A base64url encoded SHA-256 hash
+        match URL_SAFE_NO_PAD.decode(&challenge) {
+            Ok(hash) if hash.len() == 32 => Ok(CodeChallenge(challenge)),
+            _ => Err("Code challenge must be a base64url encoded SHA-256 hash".to_string()),
+        }
+    }
+
+    pub fn s256(verifier: &CodeVerifier) -> Self {
+        let hash = Sha256::digest(verifier.as_ref().as_bytes());
+        CodeChallenge(URL_SAFE_NO_PAD.encode(hash))
+    }
+
+    pub fn is_satisfied_by(&self, verifier: &CodeVerifier) -> bool {
+        *self == Self::s256(verifier)
+    }
+}
+
+impl AsRef<str> for CodeChallenge {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Example from RFC 7636, appendix B
+    const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+    const CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+    #[test]
+    fn s256_matches_the_rfc_example() {
+        let verifier = CodeVerifier::parse(VERIFIER.to_string()).unwrap();
+        let challenge = CodeChallenge::parse(CHALLENGE.to_string()).unwrap();
+
+        assert_eq!(CodeChallenge::s256(&verifier), challenge);
+        assert!(challenge.is_satisfied_by(&verifier));
+
+        let other = CodeVerifier::parse("a".repeat(43)).unwrap();
+        assert!(!challenge.is_satisfied_by(&other));
+    }
+
+    #[test]
+    fn verifier_must_be_long_enough_and_unreserved() {
+        assert!(CodeVerifier::parse("a".repeat(42)).is_err());
+        assert!(CodeVerifier::parse("a".repeat(129)).is_err());
+        assert!(CodeVerifier::parse(format!("{}+", "a".repeat(43))).is_err());
+        assert!(CodeVerifier::parse("a".repeat(128)).is_ok());
+    }
+
+    #[test]
+    fn challenge_must_be_a_sha256_hash() {
+        assert!(CodeChallenge::parse("plain-text-verifier".to_string()).is_err());
+        assert!(CodeChallenge::parse(VERIFIER[..40].to_string()).is_err());
+    }
+}
@@ -0,0 +1,41 @@
+use chrono::{DateTime, Utc};
+
+use super::user_id::UserId;
+
+// A login, recorded when its session cookie is issued. The id is the `jti`
+// of the cookie's token, so ending a session is banning that token.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Session {
+    pub id: String,
+    pub user_id: UserId,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl Session {
+    pub fn new(
+        id: String,
+        user_id: UserId,
+        created_at: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+        ip: Option<String>,
+        user_agent: Option<String>,
+    ) -> Self {
+        Session {
+            id,
+            user_id,
+            created_at,
+            last_seen_at: created_at,
+            expires_at,
+            ip,
+            user_agent,
+        }
+    }
+
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at <= now
+    }
+}
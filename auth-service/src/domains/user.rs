@@ -1,15 +1,46 @@
-use super::{email::Email, password::Password};
+use super::{email::Email, password::Password, password_hash::PasswordHash, user_id::UserId};
 
-#[derive(Clone, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct User {
+    pub id: UserId,
     pub email: Email,
-    pub password: Password,
+    pub password_hash: PasswordHash,
     pub requires_2fa: bool,
+    // Goes into every token issued to the user, see `UserStore::end_all_sessions`
+    pub session_version: u64,
 }
 
 impl User {
-    pub(crate) fn new(email: Email, password: Password, requires_2fa: bool) -> Self {
+    pub(crate) fn new(
+        id: UserId,
+        email: Email,
+        password_hash: PasswordHash,
+        requires_2fa: bool,
+        session_version: u64,
+    ) -> Self {
         User {
+            id,
+            email,
+            password_hash,
+            requires_2fa,
+            session_version,
+        }
+    }
+}
+
+// A user who is signing up. Stores hash the password on insert and only ever
+// hand back a `User`.
+pub struct NewUser {
+    pub id: UserId,
+    pub email: Email,
+    pub password: Password,
+    pub requires_2fa: bool,
+}
+
+impl NewUser {
+    pub(crate) fn new(id: UserId, email: Email, password: Password, requires_2fa: bool) -> Self {
+        NewUser {
+            id,
             email,
             password,
             requires_2fa,
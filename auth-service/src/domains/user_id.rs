@@ -0,0 +1,55 @@
+use std::fmt::Display;
+
+use uuid::Uuid;
+
+// Stable identifier for a user. Unlike the email address it never changes,
+// so it is what we put in the JWT `sub` claim and what other services key on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct UserId(Uuid);
+
+impl UserId {
+    pub fn parse(id: String) -> Result<Self, String> {
+        Uuid::parse_str(&id).map(UserId).map_err(|e| e.to_string())
+    }
+}
+
+impl Default for UserId {
+    fn default() -> Self {
+        UserId(Uuid::new_v4())
+    }
+}
+
+impl From<Uuid> for UserId {
+    fn from(id: Uuid) -> Self {
+        UserId(id)
+    }
+}
+
+impl AsRef<Uuid> for UserId {
+    fn as_ref(&self) -> &Uuid {
+        &self.0
+    }
+}
+
+impl Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_accepts_valid_uuid() {
+        let id = UserId::default();
+        let parsed = UserId::parse(id.to_string()).unwrap();
+        assert_eq!(parsed, id);
+    }
+
+    #[test]
+    fn parse_rejects_invalid_uuid() {
+        assert!(UserId::parse("ravi@gmail.com".to_string()).is_err());
+    }
+}
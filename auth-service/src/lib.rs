@@ -7,20 +7,26 @@ pub mod utils;
 
 use crate::{
     domains::{
-        data_stores::{BannedTokenStore, TwoFACodeStore, UserStore},
-        error::AuthAPIError,
+        data_stores::{
+            BannedTokenStore, OAuthClientStore, SessionStore, TwoFACodeStore, UserStore,
+        },
+        error::{AuthAPIError, OAuthError},
+        password_policy::PasswordPolicyError,
         EmailClient,
     },
     routes::*,
 };
 
 use std::error::Error;
+use std::net::SocketAddr;
 
 use app_state::AppState;
 use axum::{
-    http::{Method, StatusCode},
+    extract::connect_info::{ConnectInfo, IntoMakeServiceWithConnectInfo},
+    http::{header, Method, StatusCode},
+    middleware::AddExtension,
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, post},
     serve::Serve,
     Json, Router,
 };
@@ -30,7 +36,10 @@ use sqlx::{postgres::PgPoolOptions, PgPool};
 use tower_http::{cors::CorsLayer, services::ServeDir};
 
 pub struct Application {
-    server: Serve<Router, Router>,
+    server: Serve<
+        IntoMakeServiceWithConnectInfo<Router, SocketAddr>,
+        AddExtension<Router, ConnectInfo<SocketAddr>>,
+    >,
     pub address: String,
 }
 
@@ -40,8 +49,10 @@ impl Application {
         T1: BannedTokenStore + Clone + Send + Sync + 'static,
         T2: TwoFACodeStore + Clone + Send + Sync + 'static,
         T3: EmailClient + Clone + Send + Sync + 'static,
+        T4: OAuthClientStore + Clone + Send + Sync + 'static,
+        T5: SessionStore + Clone + Send + Sync + 'static,
     >(
-        app_state: AppState<T, T1, T2, T3>,
+        app_state: AppState<T, T1, T2, T3, T4, T5>,
         address: &str,
     ) -> Result<Self, Box<dyn Error>> {
         // Move the Router definition from `main.rs` to here.
@@ -53,8 +64,8 @@ impl Application {
         ];
 
         let cors = CorsLayer::new()
-            // Allow GET and POST requests
-            .allow_methods([Method::GET, Method::POST])
+            // Allow GET, POST and DELETE requests
+            .allow_methods([Method::GET, Method::POST, Method::DELETE])
             // Allow cookies to be included in requests
             .allow_credentials(true)
             .allow_origin(allowed_origins);
@@ -65,13 +76,34 @@ impl Application {
             .route("/login", post(login))
             .route("/verify-2fa", post(verify_2fa))
             .route("/logout", post(logout))
+            .route("/logout-all", post(logout_all))
+            .route("/sessions", get(list_sessions))
+            .route("/sessions/:id", delete(delete_session))
             .route("/verify-token", post(verify_token))
+            .route("/change-email", post(change_email))
+            .route("/confirm-email-change", get(confirm_email_change))
+            .route("/oauth/authorize", get(authorize).post(consent))
+            .route("/oauth/token", post(token))
+            .route("/oauth/introspect", post(introspect))
+            .route("/oauth/revoke", post(revoke))
+            .route("/oauth/clients", post(register_client))
+            .route("/oauth/clients/:client_id", get(client_info))
+            .route(
+                "/.well-known/openid-configuration",
+                get(openid_configuration),
+            )
+            .route("/.well-known/jwks.json", get(jwks))
+            .route("/userinfo", get(userinfo).post(userinfo))
             .with_state(app_state)
             .layer(cors); // Add CORS config to our Axum router
 
         let listener = tokio::net::TcpListener::bind(address).await?;
         let address = listener.local_addr()?.to_string();
-        let server = axum::serve(listener, router);
+        // Handlers see the peer address, sessions record where a login came from
+        let server = axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        );
 
         // Create a new Application instance and return it
         Ok(Application { server, address })
@@ -85,25 +117,98 @@ impl Application {
 #[derive(Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub message: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<PasswordPolicyError>,
 }
 
 impl IntoResponse for AuthAPIError {
     fn into_response(self) -> axum::response::Response {
+        let errors = match &self {
+            AuthAPIError::InvalidPassword(violations) => {
+                violations.iter().map(PasswordPolicyError::from).collect()
+            }
+            _ => Vec::new(),
+        };
+        let retry_after_seconds = match &self {
+            AuthAPIError::ServiceUnavailable => Some(1),
+            // Rounded up, so retrying right on time isn't still locked out
+            AuthAPIError::TooManyLoginAttempts(locked_for) => {
+                Some((locked_for.num_milliseconds() + 999) / 1000)
+            }
+            _ => None,
+        };
+
         let (status, error_message) = match self {
             AuthAPIError::UserAlreadyExists => (StatusCode::CONFLICT, "User Already exists"),
             AuthAPIError::InvalidCredentials => (StatusCode::BAD_REQUEST, "Invalid Credentials"),
+            AuthAPIError::InvalidPassword(_) => (
+                StatusCode::BAD_REQUEST,
+                "Password does not meet the requirements",
+            ),
             AuthAPIError::UnexpectedError => (StatusCode::INTERNAL_SERVER_ERROR, "Unexpcted Error"),
+            AuthAPIError::ServiceUnavailable => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Service is busy, try again shortly",
+            ),
             AuthAPIError::IncorrectCredentials => (StatusCode::UNAUTHORIZED, "Unauthorized"),
             AuthAPIError::MissingToken => (StatusCode::BAD_REQUEST, "Invalid Credentials"),
             AuthAPIError::InvalidToken => (StatusCode::UNAUTHORIZED, "Unauthorized"),
+            AuthAPIError::InsufficientScope => (StatusCode::FORBIDDEN, "Forbidden"),
+            AuthAPIError::SessionNotFound => (StatusCode::NOT_FOUND, "Session not found"),
+            AuthAPIError::TooManyLoginAttempts(_) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "Too many failed logins, try again later",
+            ),
         };
 
         let body = Json(ErrorResponse {
             message: error_message.to_string(),
+            errors,
         });
+
+        if let Some(retry_after_seconds) = retry_after_seconds {
+            let retry_after = [(header::RETRY_AFTER, retry_after_seconds.to_string())];
+            return (status, retry_after, body).into_response();
+        }
         (status, body).into_response()
     }
 }
+#[derive(Serialize, Deserialize)]
+pub struct OAuthErrorResponse {
+    pub error: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error_description: Option<String>,
+}
+
+impl IntoResponse for OAuthError {
+    fn into_response(self) -> axum::response::Response {
+        let status = match self {
+            OAuthError::InvalidClient => StatusCode::UNAUTHORIZED,
+            OAuthError::ServerError => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        };
+
+        let body = Json(OAuthErrorResponse {
+            error: self.code().to_string(),
+            error_description: self.description().map(str::to_string),
+        });
+
+        // Clients that can authenticate are told how (RFC 6749, section 5.2)
+        if status == StatusCode::UNAUTHORIZED {
+            return (
+                status,
+                [
+                    (header::CACHE_CONTROL, "no-store"),
+                    (header::WWW_AUTHENTICATE, "Basic"),
+                ],
+                body,
+            )
+                .into_response();
+        }
+        (status, [(header::CACHE_CONTROL, "no-store")], body).into_response()
+    }
+}
+
 pub async fn get_postgres_pool(url: &str) -> Result<PgPool, sqlx::Error> {
     // Create a new PostgreSQL connection pool
     PgPoolOptions::new().max_connections(5).connect(url).await
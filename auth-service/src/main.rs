@@ -1,20 +1,25 @@
 use auth_service::app_state::AppState;
-use auth_service::domains::EmailClient;
-use auth_service::services::data_stores::hashmap_user_store;
+use auth_service::domains::password_policy::{BreachedPasswordList, PasswordPolicy};
+use auth_service::services::data_stores::postgres_oauth_client_store::PostgresOAuthClientStore;
+use auth_service::services::data_stores::postgres_session_store::PostgresSessionStore;
 use auth_service::services::data_stores::postgres_user_store::PostgresUserStore;
 use auth_service::services::data_stores::{
     hashmap_two_fa_code_store::HashmapTwoFACodeStore,
     hashset_banned_token_store::HashsetBannedTokenStore,
 };
 use auth_service::services::mock_email_client::MockEmailClient;
-use auth_service::services::{self};
-use auth_service::utils::constants::DATABASE_URL;
+use auth_service::utils::clock::{Clock, SystemClock};
+use auth_service::utils::constants::{
+    ARGON2_ITERATIONS, ARGON2_MEMORY_KIB, ARGON2_PARALLELISM, BREACHED_PASSWORDS_FILE,
+    DATABASE_URL, HASHING_QUEUE_LIMIT, HASHING_WORKERS, PASSWORD_MAX_LENGTH, PASSWORD_MIN_LENGTH,
+    PASSWORD_MIN_STRENGTH, SESSION_VERSION_CACHE_SECONDS,
+};
+use auth_service::utils::login_throttle::LoginThrottle;
+use auth_service::utils::password_hashing::{HashingParams, HashingPool};
 use auth_service::{get_postgres_pool, Application};
-use sqlx::{PgPool, Pool, Postgres};
-use std::cell::OnceCell;
-use std::net::IpAddr;
+use sqlx::PgPool;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
 
 async fn sql_db(pool: PgPool) {
     use sqlx::Row;
@@ -46,17 +51,43 @@ async fn sql_db(pool: PgPool) {
 async fn main() {
     let pg_pool = configure_postgresql().await;
     sql_db(pg_pool.clone()).await;
-    let users_store = PostgresUserStore::new(pg_pool);
-    let banned_token_store = HashsetBannedTokenStore::default();
-    let two_fa_store = HashmapTwoFACodeStore::default();
+    let oauth_client_store = PostgresOAuthClientStore::new(pg_pool.clone());
+    let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+    let session_store = PostgresSessionStore::new(pg_pool.clone()).with_clock(clock.clone());
+    let users_store = PostgresUserStore::new(pg_pool)
+        .with_hashing_params(HashingParams {
+            memory_kib: *ARGON2_MEMORY_KIB,
+            iterations: *ARGON2_ITERATIONS,
+            parallelism: *ARGON2_PARALLELISM,
+        })
+        .with_hashing_pool(HashingPool::new(*HASHING_WORKERS, *HASHING_QUEUE_LIMIT))
+        .with_session_version_ttl(Duration::from_secs(*SESSION_VERSION_CACHE_SECONDS))
+        .with_clock(clock.clone());
+    let banned_token_store = HashsetBannedTokenStore::default().with_clock(clock.clone());
+    let two_fa_store = HashmapTwoFACodeStore::default().with_clock(clock.clone());
     let email_client = MockEmailClient;
+    let login_throttle = LoginThrottle::default();
+
+    tokio::spawn(remove_expired_entries(
+        users_store.clone(),
+        banned_token_store.clone(),
+        two_fa_store.clone(),
+        login_throttle.clone(),
+        clock.clone(),
+        session_store.clone(),
+    ));
 
     let app_state = AppState::new(
-        Arc::new(RwLock::new(users_store)),
-        Arc::new(RwLock::new(banned_token_store)),
-        Arc::new(RwLock::new(two_fa_store)),
-        Arc::new(RwLock::new(email_client)),
-    );
+        Arc::new(users_store),
+        Arc::new(banned_token_store),
+        Arc::new(two_fa_store),
+        Arc::new(email_client),
+        Arc::new(oauth_client_store),
+        Arc::new(session_store),
+    )
+    .with_password_policy(configure_password_policy())
+    .with_login_throttle(login_throttle)
+    .with_clock(clock);
 
     let app = Application::build(app_state, "0.0.0.0:3000")
         .await
@@ -64,6 +95,42 @@ async fn main() {
 
     app.run().await.expect("Failed to run app");
 }
+
+// Expired banned tokens, abandoned 2FA codes, failed login counts, session
+// versions of users no longer active and recent session touches would
+// otherwise stay in memory until restart
+async fn remove_expired_entries(
+    users_store: PostgresUserStore,
+    banned_token_store: HashsetBannedTokenStore,
+    two_fa_store: HashmapTwoFACodeStore,
+    login_throttle: LoginThrottle,
+    clock: Arc<dyn Clock>,
+    session_store: PostgresSessionStore,
+) {
+    let mut interval = tokio::time::interval(Duration::from_secs(60));
+    loop {
+        interval.tick().await;
+        users_store.remove_expired();
+        banned_token_store.remove_expired();
+        two_fa_store.remove_expired();
+        login_throttle.remove_expired(clock.now());
+        session_store.remove_expired();
+    }
+}
+
+fn configure_password_policy() -> PasswordPolicy {
+    let breached_passwords = BREACHED_PASSWORDS_FILE.as_ref().map(|path| {
+        Arc::new(BreachedPasswordList::load(path).expect("Failed to load breached passwords"))
+    });
+
+    PasswordPolicy {
+        min_length: *PASSWORD_MIN_LENGTH,
+        max_length: *PASSWORD_MAX_LENGTH,
+        min_strength: *PASSWORD_MIN_STRENGTH,
+        breached_passwords,
+    }
+}
+
 async fn configure_postgresql() -> PgPool {
     // Create a new database connection pool
     let pg_pool = get_postgres_pool(&DATABASE_URL)
@@ -0,0 +1,223 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domains::{
+        data_stores::{
+            BannedTokenStore, OAuthClientStore, SessionStore, TwoFACodeStore, UserStore,
+            UserStoreError,
+        },
+        email::Email,
+        error::AuthAPIError,
+        password::Password,
+        user_id::UserId,
+        EmailClient,
+    },
+    utils::{
+        auth::{
+            generate_email_change_token, validate_email_change_token, validate_session,
+            validate_token,
+        },
+        constants::{AUTH_SERVICE_URL, JWT_COOKIE_NAME, SESSION_AUDIENCE},
+    },
+};
+
+pub(crate) async fn change_email<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    jar: CookieJar,
+    State(state): State<AppState<T, T1, T2, T3, T4, T5>>,
+    Json(request): Json<ChangeEmailRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let token = match jar.get(JWT_COOKIE_NAME) {
+        Some(cookie) => cookie.value().to_owned(),
+        None => return Err(AuthAPIError::MissingToken),
+    };
+
+    let claims = validate_session(
+        &token,
+        Some(&SESSION_AUDIENCE),
+        state.banned_token_store.clone(),
+        state.user_store.clone(),
+        state.clock.as_ref(),
+    )
+    .await
+    .map_err(|_| AuthAPIError::InvalidToken)?;
+    let user_id = UserId::parse(claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+    let user = match state.user_store.get_user_by_id(&user_id).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    };
+
+    let new_email =
+        Email::parse(request.new_email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    if new_email == user.email {
+        return Err(AuthAPIError::InvalidCredentials);
+    }
+
+    let password =
+        Password::parse(request.password).map_err(|_| AuthAPIError::IncorrectCredentials)?;
+    match state.user_store.validate_user(&user.email, &password).await {
+        Ok(()) => {}
+        Err(UserStoreError::Unavailable) => return Err(AuthAPIError::ServiceUnavailable),
+        Err(_) => return Err(AuthAPIError::IncorrectCredentials),
+    }
+
+    if state.user_store.get_user(&new_email).await.is_ok() {
+        return Err(AuthAPIError::UserAlreadyExists);
+    }
+
+    let confirmation_token = generate_email_change_token(
+        &user.id,
+        &user.email,
+        &new_email,
+        user.session_version,
+        state.clock.as_ref(),
+    )
+    .map_err(|_| AuthAPIError::UnexpectedError)?;
+    let confirmation_link = format!(
+        "{}/confirm-email-change?token={}",
+        AUTH_SERVICE_URL.as_str(),
+        confirmation_token
+    );
+
+    state
+        .email_client
+        .send_email(
+            &new_email,
+            "Confirm your new email address",
+            &format!(
+                "Open this link to start using this address for your account: {}",
+                confirmation_link
+            ),
+        )
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    state
+        .email_client
+        .send_email(
+            &user.email,
+            "Your email address is being changed",
+            &format!(
+                "A request was made to change your account email to {}. \
+                 If this wasn't you, change your password now.",
+                new_email.as_ref()
+            ),
+        )
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let response = Json(ChangeEmailResponse {
+        message: "Confirmation email sent".to_string(),
+    });
+
+    Ok((StatusCode::ACCEPTED, response))
+}
+
+pub(crate) async fn confirm_email_change<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    jar: CookieJar,
+    State(state): State<AppState<T, T1, T2, T3, T4, T5>>,
+    Query(request): Query<ConfirmEmailChangeRequest>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let claims = match validate_email_change_token(&request.token, state.clock.as_ref()) {
+        Ok(c) => c,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    let user_id = match UserId::parse(claims.sub) {
+        Ok(id) => id,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+    let new_email = match Email::parse(claims.new_email) {
+        Ok(e) => e,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    let user = match state.user_store.get_user_by_id(&user_id).await {
+        Ok(u) => u,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    // The link is only good for the address it was issued against, and
+    // only until the user logs out everywhere
+    if user.email.as_ref() != claims.current_email || user.session_version != claims.session_version
+    {
+        return (jar, Err(AuthAPIError::InvalidToken));
+    }
+
+    match state.user_store.update_email(&user_id, new_email).await {
+        Ok(()) => {}
+        Err(UserStoreError::UserAlreadyExists) => {
+            return (jar, Err(AuthAPIError::UserAlreadyExists))
+        }
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    }
+    let _ = state.session_store.remove_sessions(&user_id).await;
+
+    // Sessions issued for the old address no longer pass `validate_session`.
+    // Also ban the token of the browser confirming the change and drop its cookie.
+    let jar = match jar.get(JWT_COOKIE_NAME) {
+        Some(cookie) => {
+            let token = cookie.value().to_owned();
+            if let Ok(claims) = validate_token(
+                &token,
+                Some(&SESSION_AUDIENCE),
+                state.banned_token_store.clone(),
+                state.clock.as_ref(),
+            )
+            .await
+            {
+                let _ = state
+                    .banned_token_store
+                    .add_banned_token(claims.jti.clone(), claims.expires_at())
+                    .await;
+            }
+            jar.remove(JWT_COOKIE_NAME)
+        }
+        None => jar,
+    };
+
+    let response = Json(ChangeEmailResponse {
+        message: "Email updated successfully!".to_string(),
+    });
+
+    (jar, Ok((StatusCode::OK, response)))
+}
+
+#[derive(Deserialize)]
+pub struct ChangeEmailRequest {
+    #[serde(rename = "newEmail")]
+    pub new_email: String,
+    pub password: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmEmailChangeRequest {
+    pub token: String,
+}
+
+#[derive(Serialize, PartialEq, Debug, Deserialize)]
+pub struct ChangeEmailResponse {
+    pub message: String,
+}
@@ -0,0 +1,146 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    Form, Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domains::{
+        data_stores::{
+            BannedTokenStore, OAuthClientStore, SessionStore, TwoFACodeStore, UserStore,
+        },
+        error::OAuthError,
+        EmailClient,
+    },
+    utils::auth::{validate_client_token, validate_session},
+};
+
+use super::{authenticate_privileged_client, INTROSPECT_SCOPE};
+
+// Token introspection (RFC 7662) for services that need more than
+// `/verify-token`'s yes or no. Revoked, expired, stale and unknown tokens
+// are all just inactive, so callers can't tell them apart.
+pub(crate) async fn introspect<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    State(state): State<AppState<T, T1, T2, T3, T4, T5>>,
+    headers: HeaderMap,
+    Form(request): Form<IntrospectionRequest>,
+) -> Result<impl IntoResponse, Response> {
+    authenticate_privileged_client(
+        &state,
+        &headers,
+        request.client_id,
+        request.client_secret,
+        INTROSPECT_SCOPE,
+    )
+    .await?;
+
+    let token = request
+        .token
+        .ok_or_else(|| OAuthError::InvalidRequest("token is required").into_response())?;
+
+    let response = introspect_token(&state, &token).await;
+    Ok(([(header::CACHE_CONTROL, "no-store")], Json(response)))
+}
+
+// Any `token_type_hint` is ignored, both kinds of token are cheap to try
+async fn introspect_token<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    state: &AppState<T, T1, T2, T3, T4, T5>,
+    token: &str,
+) -> IntrospectionResponse {
+    let clock = state.clock.as_ref();
+
+    if let Ok(claims) = validate_session(
+        token,
+        None,
+        state.banned_token_store.clone(),
+        state.user_store.clone(),
+        clock,
+    )
+    .await
+    {
+        return IntrospectionResponse {
+            active: true,
+            iss: Some(claims.iss),
+            sub: Some(claims.sub),
+            aud: Some(claims.aud),
+            exp: Some(claims.exp),
+            iat: Some(claims.iat),
+            nbf: Some(claims.nbf),
+            jti: Some(claims.jti),
+            scope: claims.scope,
+            client_id: claims.client_id,
+            amr: Some(claims.authentication.amr),
+        };
+    }
+
+    if let Ok(claims) = validate_client_token(token, state.banned_token_store.clone(), clock).await
+    {
+        return IntrospectionResponse {
+            active: true,
+            iss: Some(claims.iss),
+            sub: Some(claims.sub),
+            aud: Some(claims.aud),
+            exp: Some(claims.exp),
+            iat: Some(claims.iat),
+            nbf: Some(claims.nbf),
+            jti: Some(claims.jti),
+            scope: Some(claims.scope),
+            client_id: Some(claims.client_id),
+            amr: None,
+        };
+    }
+
+    IntrospectionResponse::default()
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IntrospectionRequest {
+    pub token: Option<String>,
+    pub token_type_hint: Option<String>,
+    // Client authentication, unless sent in a header
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+// Inactive tokens are described by `active` alone
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exp: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iat: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amr: Option<Vec<String>>,
+}
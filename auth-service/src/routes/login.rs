@@ -1,9 +1,10 @@
 use std::fmt::Display;
+use std::net::SocketAddr;
 
 use axum::{
     body::Body,
-    extract::State,
-    http::{Response, StatusCode},
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, Response, StatusCode},
     response::IntoResponse,
     Json,
 };
@@ -13,15 +14,21 @@ use serde::{Deserialize, Serialize};
 use crate::{
     app_state::AppState,
     domains::{
-        data_stores::{BannedTokenStore, LoginAttemptId, TwoFACode, TwoFACodeStore, UserStore},
+        data_stores::{
+            BannedTokenStore, LoginAttemptId, OAuthClientStore, SessionStore, TwoFACode,
+            TwoFACodeStore, UserStore, UserStoreError,
+        },
         email::Email,
         error::AuthAPIError,
         password::Password,
-        user, EmailClient,
+        user::User,
+        EmailClient,
     },
-    utils::auth::generate_auth_cookie,
+    routes::sessions::{start_session, Device},
+    utils::auth::Authentication,
 };
-#[derive(Deserialize, Debug)]
+// Not `Debug`, it holds the password as typed
+#[derive(Deserialize)]
 pub struct LoginInfo {
     email: String,
     password: String,
@@ -32,8 +39,12 @@ pub(crate) async fn login<
     T1: BannedTokenStore + Clone + Send + Sync,
     T2: TwoFACodeStore + Clone + Send + Sync,
     T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
 >(
-    State(state): State<AppState<T, T1, T2, T3>>,
+    State(state): State<AppState<T, T1, T2, T3, T4, T5>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     jar: CookieJar,
     Json(request): Json<LoginInfo>,
 ) -> (CookieJar, Result<Response<Body>, AuthAPIError>) {
@@ -47,44 +58,53 @@ pub(crate) async fn login<
         Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
     };
 
-    let validation_result = state
-        .user_store
-        .read()
-        .await
-        .validate_user(&email, password.as_ref())
-        .await;
+    // Checked before the password, so a locked out email costs no hashing
+    if let Some(locked_for) = state.login_throttle.locked_for(&email, state.clock.now()) {
+        return (jar, Err(AuthAPIError::TooManyLoginAttempts(locked_for)));
+    }
 
-    if validation_result.is_err() {
-        return (jar, Err(AuthAPIError::IncorrectCredentials));
+    let validation_result = state.user_store.validate_user(&email, &password).await;
+
+    match validation_result {
+        Ok(()) => state.login_throttle.record_success(&email),
+        Err(UserStoreError::Unavailable) => return (jar, Err(AuthAPIError::ServiceUnavailable)),
+        Err(_) => {
+            state
+                .login_throttle
+                .record_failure(email, state.clock.now());
+            return (jar, Err(AuthAPIError::IncorrectCredentials));
+        }
     }
 
-    let user_lock = state.user_store.read().await;
-    let user = match user_lock.get_user(&email).await {
+    let user = match state.user_store.get_user(&email).await {
         Ok(u) => u,
         Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
     };
 
-    let auth_cookie = match generate_auth_cookie(&email) {
-        Ok(c) => c,
-        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
-    };
-
     if user.requires_2fa {
         handle_2fa(jar, &state, &email).await
     } else {
-        handle_no_2fa(jar, &email).await
+        handle_no_2fa(jar, &state, &user, Device::new(address, &headers)).await
     }
-
-    // Ok((jar.add(auth_cookie), StatusCode::OK.into_response()))
 }
 
-async fn handle_no_2fa(
+async fn handle_no_2fa<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
     jar: CookieJar,
-    email: &Email,
+    state: &AppState<T, T1, T2, T3, T4, T5>,
+    user: &User,
+    device: Device,
 ) -> (CookieJar, Result<Response<Body>, AuthAPIError>) {
-    let auth_cookie = match generate_auth_cookie(email) {
+    let authentication = Authentication::password(state.clock.as_ref());
+    let auth_cookie = match start_session(state, user, &authentication, device).await {
         Ok(c) => c,
-        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+        Err(e) => return (jar, Err(e)),
     };
 
     let updated_jar = jar.add(auth_cookie);
@@ -98,29 +118,31 @@ async fn handle_2fa<
     T1: BannedTokenStore + Clone + Send + Sync,
     T2: TwoFACodeStore + Clone + Send + Sync,
     T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
 >(
     jar: CookieJar,
-    state: &AppState<T, T1, T2, T3>,
+    state: &AppState<T, T1, T2, T3, T4, T5>,
     email: &Email,
 ) -> (CookieJar, Result<Response<Body>, AuthAPIError>) {
     let login_attempt_id = LoginAttemptId::default();
     let code = TwoFACode::default();
+    if state
+        .two_fa_store
+        .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+        .await
+        .is_err()
     {
-        let mut s = state.two_fa_store.try_write().unwrap();
-        let _ = s
-            .add_code(email.clone(), login_attempt_id.clone(), code.clone())
-            .await;
+        return (jar, Err(AuthAPIError::UnexpectedError));
     }
     let response = Json(TwoFactorAuthResponse {
         message: "2FA required".to_string(),
         login_attempt_id: login_attempt_id.as_ref().to_string(),
     });
-    {
-        let email_instance = state.email_client.read().await;
-        let _ = email_instance
-            .send_email(email, "Security code", code.as_ref())
-            .await;
-    }
+    let _ = state
+        .email_client
+        .send_email(email, "Security code", code.as_ref())
+        .await;
 
     (
         jar,
@@ -4,11 +4,17 @@ use axum_extra::extract::CookieJar;
 use crate::{
     app_state::{self, AppState},
     domains::{
-        data_stores::{BannedTokenStore, TwoFACodeStore, UserStore},
+        data_stores::{
+            BannedTokenStore, OAuthClientStore, SessionStore, TwoFACodeStore, UserStore,
+        },
         error::AuthAPIError,
+        user_id::UserId,
         EmailClient,
     },
-    utils::{auth::validate_token, constants::JWT_COOKIE_NAME},
+    utils::{
+        auth::{session_ban_expires_at, validate_session, validate_token},
+        constants::{JWT_COOKIE_NAME, SESSION_AUDIENCE},
+    },
 };
 
 pub(crate) async fn logout<
@@ -16,9 +22,11 @@ pub(crate) async fn logout<
     T1: BannedTokenStore + Clone + Send + Sync,
     T2: TwoFACodeStore + Clone + Send + Sync,
     T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
 >(
     jar: CookieJar,
-    State(state): State<AppState<T, T1, T2, T3>>,
+    State(state): State<AppState<T, T1, T2, T3, T4, T5>>,
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
     let cookie = match jar.get(JWT_COOKIE_NAME) {
         Some(v) => v,
@@ -27,15 +35,76 @@ pub(crate) async fn logout<
     let token = cookie.value().to_owned();
     let cloned_banned_token_store = state.banned_token_store.clone();
 
-    let result = validate_token(&token, cloned_banned_token_store).await;
+    let result = validate_token(
+        &token,
+        Some(&SESSION_AUDIENCE),
+        cloned_banned_token_store,
+        state.clock.as_ref(),
+    )
+    .await;
 
     //if the token is valid. Remove token and return 200.
     match result {
-        Ok(v) => {
-            let mut banned_store = state.banned_token_store.write().await;
-            let res = banned_store.add_banned_token(token).await;
+        Ok(claims) => {
+            let res = state
+                .banned_token_store
+                .add_banned_token(
+                    claims.jti.clone(),
+                    session_ban_expires_at(claims.expires_at()),
+                )
+                .await;
+            if let Ok(user_id) = UserId::parse(claims.sub) {
+                let _ = state
+                    .session_store
+                    .remove_session(&user_id, &claims.jti)
+                    .await;
+            }
             (jar.remove(JWT_COOKIE_NAME), Ok(StatusCode::OK))
         }
         Err(e) => (jar, Err(AuthAPIError::InvalidToken)),
     }
 }
+
+// Ends every session of the user on every device, along with the access
+// tokens OAuth clients got for them
+pub(crate) async fn logout_all<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    jar: CookieJar,
+    State(state): State<AppState<T, T1, T2, T3, T4, T5>>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let token = match jar.get(JWT_COOKIE_NAME) {
+        Some(cookie) => cookie.value().to_owned(),
+        None => return (jar, Err(AuthAPIError::MissingToken)),
+    };
+
+    let claims = match validate_session(
+        &token,
+        Some(&SESSION_AUDIENCE),
+        state.banned_token_store.clone(),
+        state.user_store.clone(),
+        state.clock.as_ref(),
+    )
+    .await
+    {
+        Ok(claims) => claims,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+    let user_id = match UserId::parse(claims.sub) {
+        Ok(id) => id,
+        Err(_) => return (jar, Err(AuthAPIError::InvalidToken)),
+    };
+
+    if state.user_store.end_all_sessions(&user_id).await.is_err() {
+        return (jar, Err(AuthAPIError::UnexpectedError));
+    }
+    // The sessions no longer validate, this only stops them being listed
+    let _ = state.session_store.remove_sessions(&user_id).await;
+
+    (jar.remove(JWT_COOKIE_NAME), Ok(StatusCode::OK))
+}
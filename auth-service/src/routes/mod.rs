@@ -1,11 +1,23 @@
+pub(crate) mod change_email;
+pub(crate) mod introspect;
 pub(crate) mod login;
 pub(crate) mod logout;
+pub(crate) mod oauth;
+pub(crate) mod oidc;
+pub(crate) mod revoke;
+pub(crate) mod sessions;
 pub(crate) mod signup;
 pub(crate) mod verify_2fa;
 pub(crate) mod verify_token;
 
+pub use change_email::*;
+pub use introspect::*;
 pub use login::*;
 pub use logout::*;
+pub use oauth::*;
+pub use oidc::*;
+pub use revoke::*;
+pub use sessions::*;
 pub use signup::*;
 pub use verify_2fa::*;
 pub use verify_token::*;
@@ -0,0 +1,802 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+    Form, Json,
+};
+use axum_extra::extract::CookieJar;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use url::{form_urlencoded, Url};
+
+use crate::{
+    app_state::AppState,
+    domains::{
+        client_secret::ClientSecret,
+        data_stores::{
+            BannedTokenStore, OAuthClientStore, OAuthClientStoreError, SessionStore,
+            TwoFACodeStore, UserStore, UserStoreError,
+        },
+        error::{AuthAPIError, OAuthError},
+        oauth_client::OAuthClient,
+        pkce::{CodeChallenge, CodeVerifier, CODE_CHALLENGE_METHOD_S256},
+        user::User,
+        user_id::UserId,
+        EmailClient,
+    },
+    utils::{
+        auth::{
+            generate_access_token, generate_authorization_code, generate_client_token,
+            generate_id_token, validate_authorization_code, validate_client_token,
+            validate_session, AuthorizationGrant, Claims, ClientTokenClaims,
+            CLIENT_CREDENTIALS_GRANT, CLIENT_TOKEN_TTL_SECONDS, TOKEN_TTL_SECONDS,
+        },
+        clock::Clock,
+        constants::{JWT_COOKIE_NAME, SESSION_AUDIENCE},
+    },
+    OAuthErrorResponse,
+};
+
+// Start of the authorization code flow. Users without a session are sent to
+// the login page first, which brings them back here once they're logged in.
+// Everyone else is shown the consent page for this client.
+pub(crate) async fn authorize<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    jar: CookieJar,
+    State(state): State<AppState<T, T1, T2, T3, T4, T5>>,
+    Query(params): Query<AuthorizeParams>,
+) -> Response {
+    let request = match check_authorization_request(state.oauth_client_store.as_ref(), params).await
+    {
+        Ok(r) => r,
+        Err(response) => return response,
+    };
+
+    if current_user(&jar, &state).await.is_none() {
+        return redirect_to_login(&request);
+    }
+
+    Redirect::to(&format!("/consent.html?{}", authorization_query(&request))).into_response()
+}
+
+// Submitted by the consent page. The session cookie is SameSite=Lax, so it's
+// only sent along when the form was posted from our own page.
+pub(crate) async fn consent<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    jar: CookieJar,
+    State(state): State<AppState<T, T1, T2, T3, T4, T5>>,
+    Form(form): Form<ConsentForm>,
+) -> Response {
+    let request =
+        match check_authorization_request(state.oauth_client_store.as_ref(), form.params).await {
+            Ok(r) => r,
+            Err(response) => return response,
+        };
+
+    let session = match current_user(&jar, &state).await {
+        Some(s) => s,
+        None => return redirect_to_login(&request),
+    };
+
+    if form.decision != "approve" {
+        return redirect_with_error(
+            &request.redirect_uri,
+            &request.state,
+            OAuthError::AccessDenied,
+        );
+    }
+
+    let grant = AuthorizationGrant {
+        sub: session.sub.clone(),
+        session_version: session.session_version,
+        sid: session.jti.clone(),
+        client_id: request.client.client_id.clone(),
+        redirect_uri: request.redirect_uri.clone(),
+        code_challenge: request.code_challenge.as_ref().to_owned(),
+        scope: request.scope.clone(),
+        nonce: request.nonce.clone(),
+        authentication: session.authentication,
+    };
+    let code = match generate_authorization_code(grant, state.clock.as_ref()) {
+        Ok(c) => c,
+        Err(_) => {
+            return redirect_with_error(
+                &request.redirect_uri,
+                &request.state,
+                OAuthError::ServerError,
+            )
+        }
+    };
+
+    let mut params = vec![("code", code.as_str())];
+    if let Some(client_state) = &request.state {
+        params.push(("state", client_state));
+    }
+    redirect_to_client(&request.redirect_uri, &params)
+}
+
+// Issues access tokens, for a user with an authorization code or for a
+// confidential client itself with its credentials
+pub(crate) async fn token<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    State(state): State<AppState<T, T1, T2, T3, T4, T5>>,
+    headers: HeaderMap,
+    Form(request): Form<TokenRequest>,
+) -> Result<impl IntoResponse, OAuthError> {
+    let response = match request.grant_type.as_deref() {
+        Some("authorization_code") => authorization_code_grant(&state, request).await?,
+        Some(CLIENT_CREDENTIALS_GRANT) => {
+            client_credentials_grant(&state, &headers, request).await?
+        }
+        Some(_) => return Err(OAuthError::UnsupportedGrantType),
+        None => return Err(OAuthError::InvalidRequest("grant_type is required")),
+    };
+
+    Ok(([(header::CACHE_CONTROL, "no-store")], Json(response)))
+}
+
+// Clients are public, so the PKCE code verifier is what proves the caller
+// started the flow
+async fn authorization_code_grant<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    state: &AppState<T, T1, T2, T3, T4, T5>,
+    request: TokenRequest,
+) -> Result<TokenResponse, OAuthError> {
+    let (Some(code), Some(redirect_uri), Some(client_id), Some(code_verifier)) = (
+        request.code,
+        request.redirect_uri,
+        request.client_id,
+        request.code_verifier,
+    ) else {
+        return Err(OAuthError::InvalidRequest(
+            "code, redirect_uri, client_id and code_verifier are required",
+        ));
+    };
+
+    let client = match state.oauth_client_store.get_client(&client_id).await {
+        Ok(c) => c,
+        Err(OAuthClientStoreError::ClientNotFound) => return Err(OAuthError::InvalidClient),
+        Err(_) => return Err(OAuthError::ServerError),
+    };
+
+    let claims = validate_authorization_code(&code, state.clock.as_ref())
+        .map_err(|_| OAuthError::InvalidGrant)?;
+    let grant = &claims.grant;
+    if grant.client_id != client.client_id || grant.redirect_uri != redirect_uri {
+        return Err(OAuthError::InvalidGrant);
+    }
+
+    let code_verifier = CodeVerifier::parse(code_verifier).map_err(|_| OAuthError::InvalidGrant)?;
+    let code_challenge =
+        CodeChallenge::parse(grant.code_challenge.clone()).map_err(|_| OAuthError::InvalidGrant)?;
+    if !code_challenge.is_satisfied_by(&code_verifier) {
+        return Err(OAuthError::InvalidGrant);
+    }
+
+    // Remembering the code as used makes it single use, a second exchange
+    // of the same code fails here
+    if state
+        .banned_token_store
+        .add_banned_token(claims.jti.clone(), claims.expires_at())
+        .await
+        .is_err()
+    {
+        return Err(OAuthError::InvalidGrant);
+    }
+
+    let user_id = UserId::parse(grant.sub.clone()).map_err(|_| OAuthError::InvalidGrant)?;
+    let user = match state.user_store.get_user_by_id(&user_id).await {
+        Ok(u) => u,
+        Err(UserStoreError::UserNotFound) => return Err(OAuthError::InvalidGrant),
+        Err(_) => return Err(OAuthError::ServerError),
+    };
+    if user.session_version != grant.session_version {
+        return Err(OAuthError::InvalidGrant);
+    }
+    // The session the code came from may have been ended on its own
+    if state
+        .banned_token_store
+        .does_token_exist(grant.sid.clone())
+        .await
+    {
+        return Err(OAuthError::InvalidGrant);
+    }
+
+    let access_token = generate_access_token(
+        &user.id,
+        &user.email,
+        user.session_version,
+        grant,
+        state.clock.as_ref(),
+    )
+    .map_err(|_| OAuthError::ServerError)?;
+
+    // OpenID Connect clients also get to know who logged in
+    let id_token = if has_scope(grant.scope.as_deref(), OPENID_SCOPE) {
+        let id_token = generate_id_token(
+            &user.id,
+            &grant.client_id,
+            grant.nonce.clone(),
+            &grant.authentication,
+            state.clock.as_ref(),
+        )
+        .map_err(|_| OAuthError::ServerError)?;
+        Some(id_token)
+    } else {
+        None
+    };
+
+    Ok(TokenResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: TOKEN_TTL_SECONDS,
+        scope: grant.scope.clone(),
+        id_token,
+    })
+}
+
+// A service asking for a token of its own. Without a `scope` it gets every
+// scope it was registered with.
+async fn client_credentials_grant<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    state: &AppState<T, T1, T2, T3, T4, T5>,
+    headers: &HeaderMap,
+    request: TokenRequest,
+) -> Result<TokenResponse, OAuthError> {
+    let client = authenticate_client(
+        state.oauth_client_store.as_ref(),
+        headers,
+        request.client_id,
+        request.client_secret,
+    )
+    .await?;
+
+    let scope = match request.scope {
+        Some(scope) => {
+            let requested: Vec<&str> = scope.split(' ').filter(|s| !s.is_empty()).collect();
+            if requested.is_empty() || !requested.iter().all(|s| client.allows_scope(s)) {
+                return Err(OAuthError::InvalidScope);
+            }
+            requested.join(" ")
+        }
+        None => client.scopes.join(" "),
+    };
+
+    let access_token = generate_client_token(&client.client_id, &scope, state.clock.as_ref())
+        .map_err(|_| OAuthError::ServerError)?;
+
+    Ok(TokenResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_in: CLIENT_TOKEN_TTL_SECONDS,
+        scope: Some(scope),
+        id_token: None,
+    })
+}
+
+// Confidential clients send their credentials either with HTTP Basic
+// authentication or as `client_id` and `client_secret` form fields, but
+// not both (RFC 6749, section 2.3.1)
+async fn authenticate_client<T4: OAuthClientStore>(
+    oauth_client_store: &T4,
+    headers: &HeaderMap,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+) -> Result<OAuthClient, OAuthError> {
+    let (client_id, client_secret) = match (basic_credentials(headers)?, client_secret) {
+        (Some(_), Some(_)) => {
+            return Err(OAuthError::InvalidRequest(
+                "Use only one way of authenticating the client",
+            ))
+        }
+        (Some(credentials), None) => credentials,
+        (None, Some(client_secret)) => match client_id {
+            Some(client_id) => (client_id, client_secret),
+            None => return Err(OAuthError::InvalidClient),
+        },
+        (None, None) => return Err(OAuthError::InvalidClient),
+    };
+
+    let client = match oauth_client_store.get_client(&client_id).await {
+        Ok(c) => c,
+        Err(OAuthClientStoreError::ClientNotFound) => return Err(OAuthError::InvalidClient),
+        Err(_) => return Err(OAuthError::ServerError),
+    };
+    let client_secret =
+        ClientSecret::parse(client_secret).map_err(|_| OAuthError::InvalidClient)?;
+    if !client.authenticate(&client_secret) {
+        return Err(OAuthError::InvalidClient);
+    }
+
+    Ok(client)
+}
+
+// Public clients have no secret, so for them the client id has to do
+// (RFC 7009, section 5). Confidential clients still need their credentials.
+pub(crate) async fn identify_client<T4: OAuthClientStore>(
+    oauth_client_store: &T4,
+    headers: &HeaderMap,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+) -> Result<OAuthClient, OAuthError> {
+    if headers.contains_key(header::AUTHORIZATION) || client_secret.is_some() {
+        return authenticate_client(oauth_client_store, headers, client_id, client_secret).await;
+    }
+
+    let client_id = client_id.ok_or(OAuthError::InvalidClient)?;
+    let client = match oauth_client_store.get_client(&client_id).await {
+        Ok(c) => c,
+        Err(OAuthClientStoreError::ClientNotFound) => return Err(OAuthError::InvalidClient),
+        Err(_) => return Err(OAuthError::ServerError),
+    };
+    if client.secret_hash.is_some() {
+        return Err(OAuthError::InvalidClient);
+    }
+
+    Ok(client)
+}
+
+// Both halves are form encoded before being joined with ':'
+fn basic_credentials(headers: &HeaderMap) -> Result<Option<(String, String)>, OAuthError> {
+    let Some(value) = headers.get(header::AUTHORIZATION) else {
+        return Ok(None);
+    };
+
+    let decoded = value
+        .to_str()
+        .ok()
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|credentials| STANDARD.decode(credentials).ok())
+        .and_then(|credentials| String::from_utf8(credentials).ok())
+        .ok_or(OAuthError::InvalidClient)?;
+    let (client_id, client_secret) = decoded.split_once(':').ok_or(OAuthError::InvalidClient)?;
+
+    let decode = |s: &str| -> String {
+        form_urlencoded::parse(format!("v={}", s).as_bytes())
+            .map(|(_, value)| value.into_owned())
+            .next()
+            .unwrap_or_default()
+    };
+    Ok(Some((decode(client_id), decode(client_secret))))
+}
+
+// Lets the consent page show which application is asking, without trusting
+// a name passed along in the URL
+pub(crate) async fn client_info<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    State(state): State<AppState<T, T1, T2, T3, T4, T5>>,
+    Path(client_id): Path<String>,
+) -> Result<impl IntoResponse, OAuthError> {
+    let client = match state.oauth_client_store.get_client(&client_id).await {
+        Ok(c) => c,
+        Err(OAuthClientStoreError::ClientNotFound) => {
+            return Err(OAuthError::InvalidRequest("Unknown client"))
+        }
+        Err(_) => return Err(OAuthError::ServerError),
+    };
+
+    Ok(Json(ClientInfoResponse {
+        client_id: client.client_id,
+        name: client.name,
+    }))
+}
+
+// Lets administrators register public clients without shell access to the
+// database. Needs a client token with the admin scope.
+pub(crate) async fn register_client<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    State(state): State<AppState<T, T1, T2, T3, T4, T5>>,
+    headers: HeaderMap,
+    Json(request): Json<RegisterClientRequest>,
+) -> Result<impl IntoResponse, Response> {
+    require_client_scope(
+        &headers,
+        state.banned_token_store.clone(),
+        state.clock.as_ref(),
+        ADMIN_SCOPE,
+    )
+    .await?;
+
+    let invalid_metadata = |status: StatusCode, description: String| {
+        let body = Json(OAuthErrorResponse {
+            error: "invalid_client_metadata".to_string(),
+            error_description: Some(description),
+        });
+        (status, body).into_response()
+    };
+
+    let client = OAuthClient::new(request.client_id, request.name, request.redirect_uris)
+        .map_err(|reason| invalid_metadata(StatusCode::BAD_REQUEST, reason))?;
+
+    match state.oauth_client_store.add_client(client.clone()).await {
+        Ok(()) => {}
+        Err(OAuthClientStoreError::ClientAlreadyExists) => {
+            return Err(invalid_metadata(
+                StatusCode::CONFLICT,
+                "Client already exists".to_string(),
+            ))
+        }
+        Err(_) => return Err(AuthAPIError::UnexpectedError.into_response()),
+    }
+
+    let response = Json(ClientInfoResponse {
+        client_id: client.client_id,
+        name: client.name,
+    });
+    Ok((StatusCode::CREATED, response))
+}
+
+pub const ADMIN_SCOPE: &str = "admin";
+pub const INTROSPECT_SCOPE: &str = "introspect";
+pub const REVOKE_SCOPE: &str = "revoke";
+
+// For endpoints that, like RFC 7662 introspection, accept either a client
+// token as a bearer token or the client's own credentials. Either way the
+// client has to be allowed `scope`. Returns the client's id.
+pub(crate) async fn authenticate_privileged_client<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    state: &AppState<T, T1, T2, T3, T4, T5>,
+    headers: &HeaderMap,
+    client_id: Option<String>,
+    client_secret: Option<String>,
+    scope: &str,
+) -> Result<String, Response> {
+    if bearer_token(headers).is_some() {
+        let claims = require_client_scope(
+            headers,
+            state.banned_token_store.clone(),
+            state.clock.as_ref(),
+            scope,
+        )
+        .await?;
+        return Ok(claims.client_id);
+    }
+
+    let client = authenticate_client(
+        state.oauth_client_store.as_ref(),
+        headers,
+        client_id,
+        client_secret,
+    )
+    .await
+    .map_err(IntoResponse::into_response)?;
+    if !client.allows_scope(scope) {
+        return Err(AuthAPIError::InsufficientScope.into_response());
+    }
+
+    Ok(client.client_id)
+}
+
+// Guards privileged endpoints: the caller must present a client credentials
+// token that carries `scope`. Failures are reported the way RFC 6750 asks,
+// with a `WWW-Authenticate` challenge.
+pub(crate) async fn require_client_scope<T1: BannedTokenStore + Clone + Send + Sync>(
+    headers: &HeaderMap,
+    banned_token_store: Arc<T1>,
+    clock: &dyn Clock,
+    scope: &str,
+) -> Result<ClientTokenClaims, Response> {
+    let invalid_token = || {
+        (
+            [(
+                header::WWW_AUTHENTICATE,
+                r#"Bearer error="invalid_token""#.to_string(),
+            )],
+            AuthAPIError::InvalidToken,
+        )
+            .into_response()
+    };
+
+    let token = bearer_token(headers).ok_or_else(invalid_token)?;
+    let claims = validate_client_token(token, banned_token_store, clock)
+        .await
+        .map_err(|_| invalid_token())?;
+
+    if !claims.has_scope(scope) {
+        let challenge = format!(r#"Bearer error="insufficient_scope", scope="{}""#, scope);
+        return Err((
+            [(header::WWW_AUTHENTICATE, challenge)],
+            AuthAPIError::InsufficientScope,
+        )
+            .into_response());
+    }
+
+    Ok(claims)
+}
+
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+// An authorization request that passed every check and only needs the
+// user's consent
+struct AuthorizationRequest {
+    client: OAuthClient,
+    redirect_uri: String,
+    scope: Option<String>,
+    state: Option<String>,
+    nonce: Option<String>,
+    code_challenge: CodeChallenge,
+}
+
+async fn check_authorization_request<T4: OAuthClientStore>(
+    oauth_client_store: &T4,
+    params: AuthorizeParams,
+) -> Result<AuthorizationRequest, Response> {
+    // Until the client and redirect URI check out there is nowhere safe to
+    // send the user back to, so these errors are shown directly
+    let client_id = params
+        .client_id
+        .ok_or_else(|| OAuthError::InvalidRequest("client_id is required").into_response())?;
+    let client = match oauth_client_store.get_client(&client_id).await {
+        Ok(c) => c,
+        Err(OAuthClientStoreError::ClientNotFound) => {
+            return Err(OAuthError::InvalidRequest("Unknown client").into_response())
+        }
+        Err(_) => return Err(OAuthError::ServerError.into_response()),
+    };
+    let redirect_uri = match params.redirect_uri {
+        Some(uri) if client.allows_redirect(&uri) => uri,
+        _ => {
+            return Err(OAuthError::InvalidRequest(
+                "redirect_uri is not registered for this client",
+            )
+            .into_response())
+        }
+    };
+
+    // From here on the client is told what went wrong
+    let state = params.state;
+    if params.response_type.as_deref() != Some("code") {
+        return Err(redirect_with_error(
+            &redirect_uri,
+            &state,
+            OAuthError::UnsupportedResponseType,
+        ));
+    }
+    if params.code_challenge_method.as_deref() != Some(CODE_CHALLENGE_METHOD_S256) {
+        return Err(redirect_with_error(
+            &redirect_uri,
+            &state,
+            OAuthError::InvalidRequest("PKCE with code_challenge_method=S256 is required"),
+        ));
+    }
+    let code_challenge = match params.code_challenge.map(CodeChallenge::parse) {
+        Some(Ok(code_challenge)) => code_challenge,
+        _ => {
+            return Err(redirect_with_error(
+                &redirect_uri,
+                &state,
+                OAuthError::InvalidRequest("code_challenge must be a base64url SHA-256 hash"),
+            ))
+        }
+    };
+    // Users can only hand over what the client was registered for, plus
+    // OpenID Connect sign-in
+    let scope = match params.scope {
+        Some(scope) => {
+            let requested: Vec<&str> = scope.split(' ').filter(|s| !s.is_empty()).collect();
+            if !requested
+                .iter()
+                .all(|s| *s == OPENID_SCOPE || client.allows_scope(s))
+            {
+                return Err(redirect_with_error(
+                    &redirect_uri,
+                    &state,
+                    OAuthError::InvalidScope,
+                ));
+            }
+            Some(requested.join(" ")).filter(|scope| !scope.is_empty())
+        }
+        None => None,
+    };
+
+    Ok(AuthorizationRequest {
+        client,
+        redirect_uri,
+        scope,
+        state,
+        nonce: params.nonce,
+        code_challenge,
+    })
+}
+
+async fn current_user<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    jar: &CookieJar,
+    state: &AppState<T, T1, T2, T3, T4, T5>,
+) -> Option<Claims> {
+    let token = jar.get(JWT_COOKIE_NAME)?.value().to_owned();
+    validate_session(
+        &token,
+        Some(&SESSION_AUDIENCE),
+        state.banned_token_store.clone(),
+        state.user_store.clone(),
+        state.clock.as_ref(),
+    )
+    .await
+    .ok()
+}
+
+pub const OPENID_SCOPE: &str = "openid";
+
+// Scopes are a space separated list
+pub(crate) fn has_scope(scope: Option<&str>, wanted: &str) -> bool {
+    scope.is_some_and(|scope| scope.split(' ').any(|s| s == wanted))
+}
+
+// Rebuilt from the checked request rather than copied from the incoming URL
+fn authorization_query(request: &AuthorizationRequest) -> String {
+    let mut query = form_urlencoded::Serializer::new(String::new());
+    query
+        .append_pair("response_type", "code")
+        .append_pair("client_id", &request.client.client_id)
+        .append_pair("redirect_uri", &request.redirect_uri)
+        .append_pair("code_challenge", request.code_challenge.as_ref())
+        .append_pair("code_challenge_method", CODE_CHALLENGE_METHOD_S256);
+    if let Some(scope) = &request.scope {
+        query.append_pair("scope", scope);
+    }
+    if let Some(state) = &request.state {
+        query.append_pair("state", state);
+    }
+    if let Some(nonce) = &request.nonce {
+        query.append_pair("nonce", nonce);
+    }
+    query.finish()
+}
+
+fn redirect_to_login(request: &AuthorizationRequest) -> Response {
+    let next = format!("/oauth/authorize?{}", authorization_query(request));
+    let query = form_urlencoded::Serializer::new(String::new())
+        .append_pair("next", &next)
+        .finish();
+    Redirect::to(&format!("/?{}", query)).into_response()
+}
+
+fn redirect_with_error(
+    redirect_uri: &str,
+    client_state: &Option<String>,
+    error: OAuthError,
+) -> Response {
+    let mut params = vec![("error", error.code())];
+    if let Some(description) = error.description() {
+        params.push(("error_description", description));
+    }
+    if let Some(client_state) = client_state {
+        params.push(("state", client_state));
+    }
+    redirect_to_client(redirect_uri, &params)
+}
+
+fn redirect_to_client(redirect_uri: &str, params: &[(&str, &str)]) -> Response {
+    // Registered redirect URIs were checked to be absolute URLs
+    match Url::parse(redirect_uri) {
+        Ok(mut url) => {
+            url.query_pairs_mut().extend_pairs(params);
+            Redirect::to(url.as_str()).into_response()
+        }
+        Err(_) => OAuthError::ServerError.into_response(),
+    }
+}
+
+// Everything is optional so that missing parameters can be reported the way
+// RFC 6749 asks for, instead of as a generic extractor rejection
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AuthorizeParams {
+    pub response_type: Option<String>,
+    pub client_id: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    // OpenID Connect, echoed in the ID token to tie it to this request
+    pub nonce: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ConsentForm {
+    #[serde(flatten)]
+    pub params: AuthorizeParams,
+    // "approve" or "deny"
+    pub decision: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TokenRequest {
+    pub grant_type: Option<String>,
+    pub code: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub client_id: Option<String>,
+    pub code_verifier: Option<String>,
+    // Client credentials grant
+    pub client_secret: Option<String>,
+    pub scope: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegisterClientRequest {
+    #[serde(rename = "clientId")]
+    pub client_id: String,
+    pub name: String,
+    #[serde(rename = "redirectUris")]
+    pub redirect_uris: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientInfoResponse {
+    #[serde(rename = "clientId")]
+    pub client_id: String,
+    pub name: String,
+}
@@ -0,0 +1,158 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    Json,
+};
+use jsonwebtoken::jwk::JwkSet;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domains::{
+        data_stores::{
+            BannedTokenStore, OAuthClientStore, SessionStore, TwoFACodeStore, UserStore,
+        },
+        error::AuthAPIError,
+        pkce::CODE_CHALLENGE_METHOD_S256,
+        EmailClient,
+    },
+    utils::{
+        auth::{validate_session, CLIENT_CREDENTIALS_GRANT},
+        constants::{AUTH_SERVICE_URL, ID_TOKEN_SIGNING_KEY, JWT_ISSUER},
+    },
+};
+
+use super::{bearer_token, has_scope, OPENID_SCOPE};
+
+// OpenID Connect discovery, so relying parties only need to be configured
+// with our issuer URL
+pub(crate) async fn openid_configuration() -> impl IntoResponse {
+    let issuer = AUTH_SERVICE_URL.as_str();
+
+    Json(OpenIdConfiguration {
+        issuer: JWT_ISSUER.to_string(),
+        authorization_endpoint: format!("{}/oauth/authorize", issuer),
+        token_endpoint: format!("{}/oauth/token", issuer),
+        introspection_endpoint: format!("{}/oauth/introspect", issuer),
+        revocation_endpoint: format!("{}/oauth/revoke", issuer),
+        userinfo_endpoint: format!("{}/userinfo", issuer),
+        jwks_uri: format!("{}/.well-known/jwks.json", issuer),
+        response_types_supported: vec!["code".to_string()],
+        grant_types_supported: vec![
+            "authorization_code".to_string(),
+            CLIENT_CREDENTIALS_GRANT.to_string(),
+        ],
+        subject_types_supported: vec!["public".to_string()],
+        id_token_signing_alg_values_supported: vec!["EdDSA".to_string()],
+        scopes_supported: vec![OPENID_SCOPE.to_string()],
+        token_endpoint_auth_methods_supported: vec![
+            "none".to_string(),
+            "client_secret_basic".to_string(),
+            "client_secret_post".to_string(),
+        ],
+        code_challenge_methods_supported: vec![CODE_CHALLENGE_METHOD_S256.to_string()],
+        claims_supported: [
+            "iss",
+            "sub",
+            "aud",
+            "exp",
+            "iat",
+            "auth_time",
+            "nonce",
+            "amr",
+        ]
+        .iter()
+        .map(|claim| claim.to_string())
+        .collect(),
+    })
+}
+
+// Public half of the key ID tokens are signed with
+pub(crate) async fn jwks() -> impl IntoResponse {
+    Json(JwkSet {
+        keys: vec![ID_TOKEN_SIGNING_KEY.jwk()],
+    })
+}
+
+// Claims about the user an access token was issued for
+pub(crate) async fn userinfo<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    State(state): State<AppState<T, T1, T2, T3, T4, T5>>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, Response> {
+    // RFC 6750 asks for a challenge telling the client what was wrong
+    let invalid_token = || {
+        (
+            [(header::WWW_AUTHENTICATE, r#"Bearer error="invalid_token""#)],
+            AuthAPIError::InvalidToken,
+        )
+            .into_response()
+    };
+
+    let token = bearer_token(&headers).ok_or_else(invalid_token)?;
+
+    let claims = validate_session(
+        token,
+        None,
+        state.banned_token_store.clone(),
+        state.user_store.clone(),
+        state.clock.as_ref(),
+    )
+    .await
+    .map_err(|_| invalid_token())?;
+
+    // Only tokens a client got through an OpenID Connect sign-in, not login
+    // sessions or tokens the user granted for something else
+    if claims.client_id.is_none() {
+        return Err(invalid_token());
+    }
+    if !has_scope(claims.scope.as_deref(), OPENID_SCOPE) {
+        let challenge = format!(
+            r#"Bearer error="insufficient_scope", scope="{}""#,
+            OPENID_SCOPE
+        );
+        return Err((
+            [(header::WWW_AUTHENTICATE, challenge)],
+            AuthAPIError::InsufficientScope,
+        )
+            .into_response());
+    }
+
+    Ok(Json(UserInfoResponse {
+        sub: claims.sub,
+        // Current, or the session would have ended with the email change
+        email: claims.email,
+    }))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenIdConfiguration {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub introspection_endpoint: String,
+    pub revocation_endpoint: String,
+    pub userinfo_endpoint: String,
+    pub jwks_uri: String,
+    pub response_types_supported: Vec<String>,
+    pub grant_types_supported: Vec<String>,
+    pub subject_types_supported: Vec<String>,
+    pub id_token_signing_alg_values_supported: Vec<String>,
+    pub scopes_supported: Vec<String>,
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+    pub code_challenge_methods_supported: Vec<String>,
+    pub claims_supported: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserInfoResponse {
+    pub sub: String,
+    pub email: String,
+}
@@ -0,0 +1,132 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Form,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domains::{
+        data_stores::{
+            BannedTokenStore, OAuthClientStore, SessionStore, TwoFACodeStore, UserStore,
+        },
+        error::OAuthError,
+        EmailClient,
+    },
+    utils::auth::{validate_client_token, validate_token},
+};
+
+use super::{bearer_token, identify_client, require_client_scope, REVOKE_SCOPE};
+
+// Token revocation (RFC 7009). Clients may revoke the tokens they were
+// issued; clients allowed the revoke scope may revoke any token, including a
+// user's login session. Tokens that are unknown, expired, already revoked or
+// not the caller's to revoke get the same 200, so the answer gives nothing
+// away.
+pub(crate) async fn revoke<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    State(state): State<AppState<T, T1, T2, T3, T4, T5>>,
+    headers: HeaderMap,
+    Form(request): Form<RevocationRequest>,
+) -> Result<impl IntoResponse, Response> {
+    let caller = if bearer_token(&headers).is_some() {
+        let claims = require_client_scope(
+            &headers,
+            state.banned_token_store.clone(),
+            state.clock.as_ref(),
+            REVOKE_SCOPE,
+        )
+        .await?;
+        Caller {
+            client_id: claims.client_id,
+            may_revoke_any: true,
+        }
+    } else {
+        let client = identify_client(
+            state.oauth_client_store.as_ref(),
+            &headers,
+            request.client_id,
+            request.client_secret,
+        )
+        .await
+        .map_err(IntoResponse::into_response)?;
+        Caller {
+            may_revoke_any: client.allows_scope(REVOKE_SCOPE),
+            client_id: client.client_id,
+        }
+    };
+
+    let token = request
+        .token
+        .ok_or_else(|| OAuthError::InvalidRequest("token is required").into_response())?;
+
+    revoke_token(&state, &caller, token).await;
+    Ok(StatusCode::OK)
+}
+
+struct Caller {
+    client_id: String,
+    may_revoke_any: bool,
+}
+
+// We don't issue refresh tokens, so access tokens are all there is to
+// revoke. Any `token_type_hint` is ignored.
+async fn revoke_token<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    state: &AppState<T, T1, T2, T3, T4, T5>,
+    caller: &Caller,
+    token: String,
+) {
+    let banned_token_store = state.banned_token_store.clone();
+    let clock = state.clock.as_ref();
+
+    // Login sessions have no client, only privileged callers can end them
+    let (owner, jti, expires_at) =
+        if let Ok(claims) = validate_token(&token, None, banned_token_store.clone(), clock).await {
+            (
+                claims.client_id.clone(),
+                claims.jti.clone(),
+                claims.expires_at(),
+            )
+        } else if let Ok(claims) =
+            validate_client_token(&token, banned_token_store.clone(), clock).await
+        {
+            (
+                Some(claims.client_id.clone()),
+                claims.jti.clone(),
+                claims.expires_at(),
+            )
+        } else {
+            return;
+        };
+
+    if !caller.may_revoke_any && owner.as_deref() != Some(caller.client_id.as_str()) {
+        return;
+    }
+
+    // Already being there is just as good
+    let _ = banned_token_store.add_banned_token(jti, expires_at).await;
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RevocationRequest {
+    pub token: Option<String>,
+    pub token_type_hint: Option<String>,
+    // Client authentication, unless sent in a header
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
@@ -0,0 +1,246 @@
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domains::{
+        data_stores::{
+            BannedTokenStore, OAuthClientStore, SessionStore, SessionStoreError, TwoFACodeStore,
+            UserStore,
+        },
+        error::AuthAPIError,
+        session::Session,
+        user::User,
+        user_id::UserId,
+        EmailClient,
+    },
+    utils::{
+        auth::{
+            generate_session_cookie, session_ban_expires_at, validate_session, Authentication,
+            Claims,
+        },
+        constants::{JWT_COOKIE_NAME, SESSION_AUDIENCE},
+    },
+};
+
+// Where a login came from, shown to the user next to its session. The
+// address is the peer's, a proxy in front of the service hides the client's.
+pub(crate) struct Device {
+    ip: String,
+    user_agent: Option<String>,
+}
+
+impl Device {
+    pub(crate) fn new(address: SocketAddr, headers: &HeaderMap) -> Self {
+        Device {
+            ip: address.ip().to_string(),
+            user_agent: headers
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned),
+        }
+    }
+}
+
+// Issues the session cookie of a user who just logged in and records the
+// session it starts
+pub(crate) async fn start_session<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    state: &AppState<T, T1, T2, T3, T4, T5>,
+    user: &User,
+    authentication: &Authentication,
+    device: Device,
+) -> Result<Cookie<'static>, AuthAPIError> {
+    let (cookie, claims) = generate_session_cookie(
+        &user.id,
+        &user.email,
+        user.session_version,
+        authentication,
+        state.clock.as_ref(),
+    )
+    .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let session = Session::new(
+        claims.jti.clone(),
+        user.id,
+        claims.issued_at(),
+        claims.expires_at(),
+        Some(device.ip),
+        device.user_agent,
+    );
+    state
+        .session_store
+        .add_session(session)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok(cookie)
+}
+
+// Lists the caller's sessions that are still active, newest first
+pub(crate) async fn list_sessions<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    jar: CookieJar,
+    State(state): State<AppState<T, T1, T2, T3, T4, T5>>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let (claims, user_id) = current_session(&jar, &state).await?;
+
+    let now = state.clock.now();
+    let _ = state.session_store.touch_session(&claims.jti, now).await;
+    let sessions = state
+        .session_store
+        .get_sessions(&user_id)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    // Sessions whose token was revoked some other way are over too
+    let mut active = Vec::with_capacity(sessions.len());
+    for session in sessions {
+        if session.is_expired(now)
+            || state
+                .banned_token_store
+                .does_token_exist(session.id.clone())
+                .await
+        {
+            continue;
+        }
+        let current = session.id == claims.jti;
+        active.push(SessionInfo::new(session, current));
+    }
+
+    Ok(Json(SessionsResponse { sessions: active }))
+}
+
+// Ends one of the caller's sessions by banning its token
+pub(crate) async fn delete_session<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    jar: CookieJar,
+    State(state): State<AppState<T, T1, T2, T3, T4, T5>>,
+    Path(id): Path<String>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let (claims, user_id) = match current_session(&jar, &state).await {
+        Ok(session) => session,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    let session = match state.session_store.remove_session(&user_id, &id).await {
+        Ok(session) => session,
+        Err(SessionStoreError::SessionNotFound) => {
+            return (jar, Err(AuthAPIError::SessionNotFound))
+        }
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+    // Already banned, by a logout or a revocation, is just as ended
+    let _ = state
+        .banned_token_store
+        .add_banned_token(
+            session.id.clone(),
+            session_ban_expires_at(session.expires_at),
+        )
+        .await;
+
+    // The path has to match the cookie's, this route's own is deeper
+    let jar = if session.id == claims.jti {
+        jar.remove(Cookie::build(JWT_COOKIE_NAME).path("/"))
+    } else {
+        jar
+    };
+    (jar, Ok(StatusCode::NO_CONTENT))
+}
+
+async fn current_session<
+    T: UserStore + Clone + Send + Sync,
+    T1: BannedTokenStore + Clone + Send + Sync,
+    T2: TwoFACodeStore + Clone + Send + Sync,
+    T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
+>(
+    jar: &CookieJar,
+    state: &AppState<T, T1, T2, T3, T4, T5>,
+) -> Result<(Claims, UserId), AuthAPIError> {
+    let token = jar
+        .get(JWT_COOKIE_NAME)
+        .ok_or(AuthAPIError::MissingToken)?
+        .value()
+        .to_owned();
+
+    let claims = validate_session(
+        &token,
+        Some(&SESSION_AUDIENCE),
+        state.banned_token_store.clone(),
+        state.user_store.clone(),
+        state.clock.as_ref(),
+    )
+    .await
+    .map_err(|_| AuthAPIError::InvalidToken)?;
+    let user_id = UserId::parse(claims.sub.clone()).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    Ok((claims, user_id))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionsResponse {
+    pub sessions: Vec<SessionInfo>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: String,
+    #[serde(rename = "lastSeenAt")]
+    pub last_seen_at: String,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: String,
+    pub ip: Option<String>,
+    #[serde(rename = "userAgent")]
+    pub user_agent: Option<String>,
+    // The session of the cookie the list was asked for with
+    pub current: bool,
+}
+
+impl SessionInfo {
+    fn new(session: Session, current: bool) -> Self {
+        SessionInfo {
+            id: session.id,
+            created_at: rfc3339(session.created_at),
+            last_seen_at: rfc3339(session.last_seen_at),
+            expires_at: rfc3339(session.expires_at),
+            ip: session.ip,
+            user_agent: session.user_agent,
+            current,
+        }
+    }
+}
+
+fn rfc3339(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
@@ -1,10 +1,15 @@
 use crate::{
     domains::{
-        data_stores::{BannedTokenStore, TwoFACodeStore, UserStore},
+        data_stores::{
+            BannedTokenStore, OAuthClientStore, SessionStore, TwoFACodeStore, UserStore,
+            UserStoreError,
+        },
         email::Email,
         error::AuthAPIError,
         password::{self, Password},
-        user::User, EmailClient,
+        user::NewUser,
+        user_id::UserId,
+        EmailClient,
     },
     AppState,
 };
@@ -16,37 +21,41 @@ pub async fn signup<
     T1: BannedTokenStore + Clone + Send + Sync,
     T2: TwoFACodeStore + Clone + Send + Sync,
     T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
 >(
-    State(state): State<AppState<T, T1, T2, T3>>,
+    State(state): State<AppState<T, T1, T2, T3, T4, T5>>,
     Json(request): Json<SignupRequest>,
 ) -> Result<impl IntoResponse, AuthAPIError> {
-    let email;
-    let password;
-
-    match Email::parse(request.email) {
-        Ok(value) => email = value,
+    let email = match Email::parse(request.email) {
+        Ok(value) => value,
         Err(s) => return Err(AuthAPIError::InvalidCredentials),
-    }
-    match Password::parse(request.password) {
-        Ok(value) => password = value,
+    };
+    let password = match Password::parse(request.password) {
+        Ok(value) => value,
         Err(s) => return Err(AuthAPIError::InvalidCredentials),
-    }
+    };
+
+    let local_part = email.as_ref().split('@').next().unwrap_or_default();
+    state
+        .password_policy
+        .validate(&password, &[email.as_ref(), local_part])
+        .map_err(AuthAPIError::InvalidPassword)?;
 
-    let user = User {
+    let user = NewUser {
+        id: UserId::default(),
         email,
         password,
         requires_2fa: request.requires_2fa,
     };
 
-    let mut user_store = state.user_store.write().await;
-    let existing_user = user_store.get_user(&user.email).await;
-    if existing_user.is_ok() {
-        return Err(AuthAPIError::UserAlreadyExists);
-    }
-
-    let add_user = user_store.add_user(user).await;
-    if (add_user.is_err()) {
-        return Err(AuthAPIError::UnexpectedError);
+    // The store enforces unique emails, so concurrent signups for the same
+    // address get a 409 rather than racing a separate lookup
+    match state.user_store.add_user(user).await {
+        Ok(()) => {}
+        Err(UserStoreError::UserAlreadyExists) => return Err(AuthAPIError::UserAlreadyExists),
+        Err(UserStoreError::Unavailable) => return Err(AuthAPIError::ServiceUnavailable),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
     }
 
     let response = Json(SignupResponse {
@@ -1,5 +1,8 @@
 use ::serde::{Deserialize, Serialize};
-use axum::extract::State;
+use std::net::SocketAddr;
+
+use axum::extract::{ConnectInfo, State};
+use axum::http::HeaderMap;
 use axum::Json;
 use axum::{http::StatusCode, response::IntoResponse};
 use axum_extra::extract::CookieJar;
@@ -7,21 +10,27 @@ use uuid::serde;
 
 use crate::app_state::AppState;
 use crate::domains::data_stores::{
-    BannedTokenStore, LoginAttemptId, TwoFACode, TwoFACodeStore, UserStore,
+    BannedTokenStore, LoginAttemptId, OAuthClientStore, SessionStore, TwoFACode, TwoFACodeStore,
+    UserStore,
 };
 use crate::domains::email::Email;
 use crate::domains::error::AuthAPIError;
 use crate::domains::EmailClient;
-use crate::utils::auth::generate_auth_cookie;
+use crate::routes::sessions::{start_session, Device};
+use crate::utils::auth::Authentication;
 
 pub(crate) async fn verify_2fa<
     T: UserStore + Clone + Send + Sync,
     T1: BannedTokenStore + Clone + Send + Sync,
     T2: TwoFACodeStore + Clone + Send + Sync,
     T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
 >(
     jar: CookieJar,
-    State(state): State<AppState<T, T1, T2, T3>>,
+    State(state): State<AppState<T, T1, T2, T3, T4, T5>>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(request): Json<Verify2FARequest>,
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
     let email: Email = match Email::parse(request.email) {
@@ -34,25 +43,31 @@ pub(crate) async fn verify_2fa<
         Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
     };
 
-    let two_fa_code = match TwoFACode::parse(request.two_fa_Code) {
+    let two_fa_code = match TwoFACode::parse(request.two_fa_code) {
         Ok(e) => e,
         Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
     };
-    let mut write_lock = state.two_fa_store.write().await;
-    let stored_login_attempt_id = match write_lock.get_code(&email).await {
-        Ok(e) => e,
-        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
-    };
-
-    if !(login_attempt_id == stored_login_attempt_id.0 && two_fa_code == stored_login_attempt_id.1)
+    // Checking and removing the code is one step, so only one of several
+    // concurrent attempts with the same code gets through
+    if state
+        .two_fa_store
+        .take_code_if(&email, &login_attempt_id, &two_fa_code)
+        .await
+        .is_err()
     {
         return (jar, Err(AuthAPIError::IncorrectCredentials));
     }
 
-    write_lock.remove_code(&email).await;
-    let auth_cookie = match generate_auth_cookie(&email) {
+    let user = match state.user_store.get_user(&email).await {
+        Ok(u) => u,
+        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+    };
+
+    let authentication = Authentication::two_factor(state.clock.as_ref());
+    let device = Device::new(address, &headers);
+    let auth_cookie = match start_session(&state, &user, &authentication, device).await {
         Ok(c) => c,
-        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+        Err(e) => return (jar, Err(e)),
     };
 
     let updated_jar = jar.add(auth_cookie);
@@ -66,5 +81,5 @@ pub struct Verify2FARequest {
     #[serde(rename = "loginAttemptId")]
     pub login_attempt_id: String,
     #[serde(rename = "2FACode")]
-    two_fa_Code: String,
+    two_fa_code: String,
 }
@@ -4,27 +4,56 @@ use axum::{http::StatusCode, response::IntoResponse};
 use serde::Deserialize;
 
 use crate::app_state::AppState;
-use crate::domains::data_stores::{BannedTokenStore, TwoFACodeStore, UserStore};
+use crate::domains::data_stores::{
+    BannedTokenStore, OAuthClientStore, SessionStore, TwoFACodeStore, UserStore,
+};
 use crate::domains::email::Email;
 use crate::domains::error::AuthAPIError;
 use crate::domains::EmailClient;
 use crate::utils::auth;
+use crate::utils::constants::SESSION_AUDIENCE;
 
 pub(crate) async fn verify_token<
     T: UserStore + Send + Sync + Clone,
     T1: BannedTokenStore + Send + Sync + Clone,
     T2: TwoFACodeStore + Clone + Send + Sync,
     T3: EmailClient + Clone + Send + Sync,
+    T4: OAuthClientStore + Clone + Send + Sync,
+    T5: SessionStore + Clone + Send + Sync,
 >(
-    State(app_state): State<AppState<T, T1, T2, T3>>,
+    State(app_state): State<AppState<T, T1, T2, T3, T4, T5>>,
     Json(request): Json<VerifyTokenString>,
 ) -> Result<impl IntoResponse, AuthAPIError> {
-    match auth::validate_token(&request.token, app_state.banned_token_store).await {
-        Ok(s) => Ok(StatusCode::OK.into_response()),
+    match auth::validate_session(
+        &request.token,
+        Some(
+            request
+                .audience
+                .as_deref()
+                .unwrap_or(SESSION_AUDIENCE.as_str()),
+        ),
+        app_state.banned_token_store,
+        app_state.user_store,
+        app_state.clock.as_ref(),
+    )
+    .await
+    {
+        Ok(claims) => {
+            // Checked on every request the app serves, so this is how
+            // sessions get their last seen time
+            let _ = app_state
+                .session_store
+                .touch_session(&claims.jti, app_state.clock.now())
+                .await;
+            Ok(StatusCode::OK.into_response())
+        }
         Err(e) => Err(AuthAPIError::InvalidToken),
     }
 }
 #[derive(Deserialize)]
 pub struct VerifyTokenString {
     token: String,
+    // Services other than the app holding login sessions, like OAuth clients,
+    // say which audience the token has to be for
+    audience: Option<String>,
 }
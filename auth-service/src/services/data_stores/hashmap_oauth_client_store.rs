@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+
+use crate::domains::data_stores::{OAuthClientStore, OAuthClientStoreError};
+use crate::domains::oauth_client::OAuthClient;
+
+// Clones share the same registered clients
+#[derive(Default, Clone)]
+pub struct HashmapOAuthClientStore {
+    clients: Arc<DashMap<String, OAuthClient>>,
+}
+
+#[async_trait]
+impl OAuthClientStore for HashmapOAuthClientStore {
+    async fn add_client(&self, client: OAuthClient) -> Result<(), OAuthClientStoreError> {
+        match self.clients.entry(client.client_id.clone()) {
+            Entry::Occupied(_) => Err(OAuthClientStoreError::ClientAlreadyExists),
+            Entry::Vacant(entry) => {
+                entry.insert(client);
+                Ok(())
+            }
+        }
+    }
+
+    async fn get_client(&self, client_id: &str) -> Result<OAuthClient, OAuthClientStoreError> {
+        self.clients
+            .get(client_id)
+            .map(|client| client.clone())
+            .ok_or(OAuthClientStoreError::ClientNotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(client_id: &str) -> OAuthClient {
+        OAuthClient::new(
+            client_id.to_string(),
+            "Single page app".to_string(),
+            vec!["https://app.example.com/callback".to_string()],
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_add_and_get_client() {
+        let store = HashmapOAuthClientStore::default();
+
+        assert!(store.add_client(client("spa")).await.is_ok());
+        assert_eq!(store.get_client("spa").await, Ok(client("spa")));
+        assert_eq!(
+            store.get_client("other").await,
+            Err(OAuthClientStoreError::ClientNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_add_existing_client() {
+        let store = HashmapOAuthClientStore::default();
+
+        let _ = store.add_client(client("spa")).await;
+        assert_eq!(
+            store.add_client(client("spa")).await,
+            Err(OAuthClientStoreError::ClientAlreadyExists)
+        );
+    }
+}
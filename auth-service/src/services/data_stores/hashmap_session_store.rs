@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+
+use crate::domains::data_stores::{SessionStore, SessionStoreError};
+use crate::domains::session::Session;
+use crate::domains::user_id::UserId;
+use crate::utils::clock::{Clock, SystemClock};
+
+// Sessions keyed by id. Listing a user's sessions scans them all, which is
+// fine for the sizes this store is used at.
+#[derive(Clone)]
+pub struct HashmapSessionStore {
+    sessions: Arc<DashMap<String, Session>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for HashmapSessionStore {
+    fn default() -> Self {
+        Self {
+            sessions: Arc::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl HashmapSessionStore {
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // Scans every session, so it's left to the periodic sweep rather than
+    // done on each login. Returns how many were removed.
+    pub fn remove_expired(&self) -> usize {
+        let now = self.clock.now();
+        let mut removed = 0;
+        self.sessions.retain(|_, session| {
+            let keep = !session.is_expired(now);
+            removed += usize::from(!keep);
+            keep
+        });
+        removed
+    }
+}
+
+#[async_trait]
+impl SessionStore for HashmapSessionStore {
+    async fn add_session(&self, session: Session) -> Result<(), SessionStoreError> {
+        match self.sessions.entry(session.id.clone()) {
+            Entry::Occupied(_) => Err(SessionStoreError::SessionAlreadyExists),
+            Entry::Vacant(entry) => {
+                entry.insert(session);
+                Ok(())
+            }
+        }
+    }
+
+    async fn get_sessions(&self, user_id: &UserId) -> Result<Vec<Session>, SessionStoreError> {
+        let mut sessions: Vec<Session> = self
+            .sessions
+            .iter()
+            .filter(|s| s.user_id == *user_id)
+            .map(|s| s.clone())
+            .collect();
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.created_at));
+        Ok(sessions)
+    }
+
+    async fn touch_session(
+        &self,
+        id: &str,
+        seen_at: DateTime<Utc>,
+    ) -> Result<(), SessionStoreError> {
+        if let Some(mut session) = self.sessions.get_mut(id) {
+            session.last_seen_at = session.last_seen_at.max(seen_at);
+        }
+        Ok(())
+    }
+
+    async fn remove_session(
+        &self,
+        user_id: &UserId,
+        id: &str,
+    ) -> Result<Session, SessionStoreError> {
+        self.sessions
+            .remove_if(id, |_, s| s.user_id == *user_id)
+            .map(|(_, session)| session)
+            .ok_or(SessionStoreError::SessionNotFound)
+    }
+
+    async fn remove_sessions(&self, user_id: &UserId) -> Result<(), SessionStoreError> {
+        self.sessions.retain(|_, s| s.user_id != *user_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::utils::clock::FakeClock;
+
+    fn session(user_id: UserId, created_at: DateTime<Utc>) -> Session {
+        Session::new(
+            uuid::Uuid::new_v4().to_string(),
+            user_id,
+            created_at,
+            created_at + Duration::minutes(10),
+            None,
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn expired_sessions_are_swept_test() {
+        let clock = FakeClock::default();
+        let store = HashmapSessionStore::default().with_clock(Arc::new(clock.clone()));
+        let user_id = UserId::default();
+
+        let older = session(user_id, clock.now());
+        store.add_session(older).await.unwrap();
+        clock.advance(Duration::minutes(5));
+        let newer = session(user_id, clock.now());
+        store.add_session(newer.clone()).await.unwrap();
+
+        // Logging in doesn't sweep, the list only shrinks when asked to
+        clock.advance(Duration::minutes(5));
+        assert_eq!(store.get_sessions(&user_id).await.unwrap().len(), 2);
+        assert_eq!(store.remove_expired(), 1);
+        assert_eq!(store.get_sessions(&user_id).await, Ok(vec![newer]));
+
+        clock.advance(Duration::minutes(5));
+        assert_eq!(store.remove_expired(), 1);
+        assert!(store.sessions.is_empty());
+    }
+}
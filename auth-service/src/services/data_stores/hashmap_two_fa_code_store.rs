@@ -1,43 +1,116 @@
-use std::collections::HashMap;
+use std::sync::Arc;
 
 use axum::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
 
-use crate::domains::data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError};
+use crate::domains::data_stores::{
+    LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError, TWO_FA_CODE_TTL_SECONDS,
+};
 use crate::domains::email::Email;
-#[derive(Default, Clone)]
+use crate::utils::clock::{Clock, SystemClock};
+
+struct PendingCode {
+    login_attempt_id: LoginAttemptId,
+    code: TwoFACode,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Clone)]
 pub struct HashmapTwoFACodeStore {
-    codes: HashMap<Email, (LoginAttemptId, TwoFACode)>,
+    codes: Arc<DashMap<Email, PendingCode>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for HashmapTwoFACodeStore {
+    fn default() -> Self {
+        Self {
+            codes: Arc::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl HashmapTwoFACodeStore {
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // Expired codes are dropped when someone asks for them, this catches the
+    // ones nobody comes back for. Returns how many were removed.
+    pub fn remove_expired(&self) -> usize {
+        let now = self.clock.now();
+        let mut removed = 0;
+        self.codes.retain(|_, pending| {
+            let keep = pending.expires_at > now;
+            removed += usize::from(!keep);
+            keep
+        });
+        removed
+    }
 }
 
 #[async_trait]
 impl TwoFACodeStore for HashmapTwoFACodeStore {
     async fn add_code(
-        &mut self,
+        &self,
         email: Email,
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError> {
-        self.codes.insert(email, (login_attempt_id, code));
+        let expires_at = self.clock.now() + Duration::seconds(TWO_FA_CODE_TTL_SECONDS);
+        self.codes.insert(
+            email,
+            PendingCode {
+                login_attempt_id,
+                code,
+                expires_at,
+            },
+        );
 
         Ok(())
     }
 
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        match self.codes.get(email) {
-            Some(a) => {
-                self.codes.remove(email);
-                Ok(())
-            }
-            None => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+    async fn remove_code(&self, email: &Email) -> Result<(), TwoFACodeStoreError> {
+        let now = self.clock.now();
+        match self.codes.remove(email) {
+            Some((_, pending)) if pending.expires_at > now => Ok(()),
+            _ => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
         }
     }
 
     async fn get_code(
         &self,
         email: &Email,
-    ) -> Result<&(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
-        match self.codes.get(email) {
-            Some(a) => Ok((a)),
+    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
+        let now = self.clock.now();
+        if let Some(pending) = self.codes.get(email) {
+            if pending.expires_at > now {
+                return Ok((pending.login_attempt_id.clone(), pending.code.clone()));
+            }
+        }
+
+        // Only an expired code is removed, a fresh one may have replaced it since
+        self.codes
+            .remove_if(email, |_, pending| pending.expires_at <= now);
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+    }
+
+    async fn take_code_if(
+        &self,
+        email: &Email,
+        login_attempt_id: &LoginAttemptId,
+        code: &TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let now = self.clock.now();
+        let taken = self.codes.remove_if(email, |_, pending| {
+            pending.expires_at > now
+                && pending.login_attempt_id == *login_attempt_id
+                && pending.code == *code
+        });
+        match taken {
+            Some(_) => Ok(()),
             None => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
         }
     }
@@ -45,13 +118,14 @@ impl TwoFACodeStore for HashmapTwoFACodeStore {
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
-
     use crate::domains::data_stores::{
-        LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError,
+        LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError, TWO_FA_CODE_TTL_SECONDS,
     };
     use crate::domains::email::Email;
     use crate::services::data_stores::hashmap_two_fa_code_store::HashmapTwoFACodeStore;
+    use crate::utils::clock::FakeClock;
+    use chrono::Duration;
+    use std::sync::Arc;
 
     #[tokio::test]
     async fn add_valid_codes_test() {
@@ -59,9 +133,7 @@ mod tests {
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
 
-        let mut hash_map_store = HashmapTwoFACodeStore {
-            codes: HashMap::default(),
-        };
+        let hash_map_store = HashmapTwoFACodeStore::default();
 
         let resp = hash_map_store.add_code(email, login_attempt_id, code).await;
         assert_eq!(resp, Ok(()));
@@ -73,9 +145,7 @@ mod tests {
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
 
-        let mut hash_map_store = HashmapTwoFACodeStore {
-            codes: HashMap::default(),
-        };
+        let hash_map_store = HashmapTwoFACodeStore::default();
 
         let resp = hash_map_store
             .add_code(email.clone(), login_attempt_id, code)
@@ -94,4 +164,40 @@ mod tests {
 
         assert_eq!(resp_remove, Ok(()));
     }
+
+    #[tokio::test]
+    async fn codes_expire_test() {
+        let clock = FakeClock::default();
+        let store = HashmapTwoFACodeStore::default().with_clock(Arc::new(clock.clone()));
+        let email = Email::parse("ravi@gmail.com".to_string()).unwrap();
+        let other = Email::parse("other@gmail.com".to_string()).unwrap();
+
+        for email in [&email, &other] {
+            store
+                .add_code(
+                    email.clone(),
+                    LoginAttemptId::default(),
+                    TwoFACode::default(),
+                )
+                .await
+                .unwrap();
+        }
+
+        clock.advance(Duration::seconds(TWO_FA_CODE_TTL_SECONDS - 1));
+        assert!(store.get_code(&email).await.is_ok());
+
+        clock.advance(Duration::seconds(1));
+        assert_eq!(
+            store.get_code(&email).await,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        );
+        assert_eq!(
+            store.remove_code(&email).await,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        );
+
+        // The one nobody asked for is swept
+        assert_eq!(store.remove_expired(), 1);
+        assert!(store.codes.is_empty());
+    }
 }
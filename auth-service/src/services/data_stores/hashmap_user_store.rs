@@ -1,44 +1,187 @@
 use crate::domains::data_stores::{UserStore, UserStoreError};
 use crate::domains::email::Email;
-use crate::domains::user;
+use crate::domains::password::Password;
+use crate::domains::password_hash::PasswordHash;
+use crate::domains::user::{self, NewUser};
+use crate::domains::user_id::UserId;
+use crate::utils::password_hashing::{CredentialHasher, HashingParams, HashingPool};
 use async_trait::async_trait;
-use std::collections::HashMap;
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use std::sync::Arc;
 
+use super::hashing_error;
+
+// Clones share the same users, like clones of a connection pool would.
+// Passwords are hashed on insert just like the Postgres store does.
+//
+// Users are kept by id, with an index from email to id. A user is only found
+// under an email while it is still theirs, so an email change never shows
+// them under both addresses or under neither.
 #[derive(Default, Clone)]
 pub struct HashMapUserStore {
-    users: HashMap<Email, user::User>,
+    users: Arc<DashMap<UserId, user::User>>,
+    emails: Arc<DashMap<Email, UserId>>,
+    hasher: CredentialHasher,
 }
-#[async_trait]
 
-impl UserStore for HashMapUserStore {
-    async fn add_user(&mut self, user: user::User) -> Result<(), UserStoreError> {
-        if (self.users.contains_key(&user.email)) {
-            Err(UserStoreError::UserAlreadyExists)
-        } else {
-            self.users.insert(user.email.clone(), user);
-            Ok(())
+impl HashMapUserStore {
+    pub fn with_hashing_params(mut self, hashing_params: HashingParams) -> Self {
+        self.hasher = CredentialHasher::new(hashing_params, self.hasher.pool().clone());
+        self
+    }
+
+    pub fn with_hashing_pool(mut self, hashing_pool: HashingPool) -> Self {
+        self.hasher = CredentialHasher::new(self.hasher.params().clone(), hashing_pool);
+        self
+    }
+
+    // Insert a user whose password was hashed by another system. Any format
+    // `PasswordHash` accepts is upgraded to Argon2id on first login.
+    pub async fn import_user(
+        &self,
+        id: &UserId,
+        email: &Email,
+        password_hash: &PasswordHash,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError> {
+        self.insert(user::User::new(
+            *id,
+            email.clone(),
+            password_hash.clone(),
+            requires_2fa,
+            0,
+        ))
+    }
+
+    fn insert(&self, user: user::User) -> Result<(), UserStoreError> {
+        match self.emails.entry(user.email.clone()) {
+            Entry::Occupied(_) => return Err(UserStoreError::UserAlreadyExists),
+            Entry::Vacant(entry) => {
+                entry.insert(user.id);
+            }
         }
+        self.users.insert(user.id, user);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl UserStore for HashMapUserStore {
+    async fn add_user(&self, user: NewUser) -> Result<(), UserStoreError> {
+        let password_hash = self
+            .hasher
+            .hash(user.password.as_ref())
+            .await
+            .map_err(hashing_error)?;
+
+        self.insert(user::User::new(
+            user.id,
+            user.email,
+            password_hash,
+            user.requires_2fa,
+            0,
+        ))
     }
 
     async fn get_user(&self, email: &Email) -> Result<user::User, UserStoreError> {
-        match self.users.get(email) {
-            Some(a) => Ok(a.clone()),
-            None => Err(UserStoreError::UserNotFound),
-        }
+        let id = self
+            .emails
+            .get(email)
+            .map(|id| *id)
+            .ok_or(UserStoreError::UserNotFound)?;
+        self.users
+            .get(&id)
+            .filter(|user| user.email == *email)
+            .map(|user| user.value().clone())
+            .ok_or(UserStoreError::UserNotFound)
     }
 
-    async fn validate_user(&self, email: &Email, password: &str) -> Result<(), UserStoreError> {
-        let r = self.get_user(email).await;
+    async fn get_user_by_id(&self, id: &UserId) -> Result<user::User, UserStoreError> {
+        self.users
+            .get(id)
+            .map(|user| user.value().clone())
+            .ok_or(UserStoreError::UserNotFound)
+    }
+
+    async fn validate_user(
+        &self,
+        email: &Email,
+        password: &Password,
+    ) -> Result<(), UserStoreError> {
+        let password = password.as_ref();
+        // Unknown emails do the same work and get the same error as a wrong password
+        let user = match self.get_user(email).await {
+            Ok(user) => user,
+            Err(_) => {
+                self.hasher
+                    .verify_dummy(password)
+                    .await
+                    .map_err(hashing_error)?;
+                return Err(UserStoreError::InvalidCredentials);
+            }
+        };
 
-        if r.is_err() {
+        let verified = self
+            .hasher
+            .verify(&user.password_hash, password)
+            .await
+            .map_err(hashing_error)?;
+        if !verified {
+            return Err(UserStoreError::InvalidCredentials);
+        }
+
+        // Like the Postgres store: only the hash we verified is replaced, and
+        // a failed upgrade fails the login and is tried again on the next one
+        if self.hasher.needs_rehash(&user.password_hash) {
+            let new_password_hash = self.hasher.hash(password).await.map_err(hashing_error)?;
+            if let Some(mut stored) = self.users.get_mut(&user.id) {
+                if stored.password_hash == user.password_hash {
+                    stored.password_hash = new_password_hash;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn update_email(&self, id: &UserId, new_email: Email) -> Result<(), UserStoreError> {
+        if !self.users.contains_key(id) {
             return Err(UserStoreError::UserNotFound);
         }
 
-        if r.is_ok_and(|x| x.password.as_ref() == password) {
-            Ok(())
-        } else {
-            Err(UserStoreError::InvalidCredentials)
+        // Claim the new address first so a concurrent signup can't take it
+        match self.emails.entry(new_email.clone()) {
+            Entry::Occupied(_) => return Err(UserStoreError::UserAlreadyExists),
+            Entry::Vacant(entry) => {
+                entry.insert(*id);
+            }
         }
+
+        // Swapped in place, under the same lock `end_all_sessions` takes, so
+        // neither bump of the session version is lost
+        let old_email = match self.users.get_mut(id) {
+            Some(mut user) => {
+                user.session_version += 1;
+                std::mem::replace(&mut user.email, new_email)
+            }
+            None => {
+                self.emails.remove(&new_email);
+                return Err(UserStoreError::UserNotFound);
+            }
+        };
+        self.emails.remove_if(&old_email, |_, owner| owner == id);
+        Ok(())
+    }
+
+    async fn get_session_version(&self, id: &UserId) -> Result<u64, UserStoreError> {
+        self.get_user_by_id(id).await.map(|u| u.session_version)
+    }
+
+    async fn end_all_sessions(&self, id: &UserId) -> Result<u64, UserStoreError> {
+        let mut user = self.users.get_mut(id).ok_or(UserStoreError::UserNotFound)?;
+        user.session_version += 1;
+        Ok(user.session_version)
     }
 }
 #[cfg(test)]
@@ -48,42 +191,81 @@ mod tests {
 
     use super::*;
 
+    fn store() -> HashMapUserStore {
+        HashMapUserStore::default()
+            .with_hashing_params(HashingParams {
+                memory_kib: 4096,
+                iterations: 1,
+                parallelism: 1,
+            })
+            .with_hashing_pool(HashingPool::new(2, 64))
+    }
+
+    fn password() -> Password {
+        Password::parse("Password123".to_string()).unwrap()
+    }
+
     #[tokio::test]
     async fn test_add_user() {
         let email = Email::parse("Ravi@gmailk.ocm".to_string()).unwrap();
-        let password = Password::parse("Password123".to_string()).unwrap();
 
-        let u1 = user::User {
+        let u1 = NewUser {
+            id: UserId::default(),
             email,
-            password,
+            password: password(),
             requires_2fa: true,
         };
 
-        let mut store = HashMapUserStore::default();
+        let store = store();
         let first = store.add_user(u1).await;
         assert_eq!(first, Ok(()));
     }
 
+    #[tokio::test]
+    async fn test_add_user_is_case_insensitive() {
+        let u1 = NewUser::new(
+            UserId::default(),
+            Email::parse("Ravi@x.com".to_string()).unwrap(),
+            password(),
+            false,
+        );
+        let u2 = NewUser::new(
+            UserId::default(),
+            Email::parse("ravi@X.COM".to_string()).unwrap(),
+            password(),
+            false,
+        );
+
+        let store = store();
+        assert_eq!(store.add_user(u1).await, Ok(()));
+        assert_eq!(
+            store.add_user(u2).await,
+            Err(UserStoreError::UserAlreadyExists)
+        );
+    }
+
     #[tokio::test]
     async fn test_get_user() {
         let email = Email::parse("Ravi@gmailk.ocm".to_string()).unwrap();
-        let password = Password::parse("Password123".to_string()).unwrap();
 
-        let u1 = user::User {
+        let u1 = NewUser {
+            id: UserId::default(),
             email,
-            password,
+            password: password(),
             requires_2fa: true,
         };
 
-        let mut store = HashMapUserStore::default();
+        let store = store();
         let _first = store.add_user(u1).await;
 
         let g = store
             .get_user(&Email::parse("Ravi@gmailk.ocm".to_string()).unwrap())
             .await
             .unwrap();
-        assert_eq!(g.email.as_ref(), "Ravi@gmailk.ocm");
-        assert_eq!(g.password.as_ref(), "Password123");
+        assert_eq!(g.email.as_ref(), "ravi@gmailk.ocm");
+        // Only the hash is kept
+        assert!(g.password_hash.as_ref().starts_with("$argon2id$"));
+        assert!(!g.password_hash.as_ref().contains("Password123"));
 
         let failed = store
             .get_user(&Email::parse("Ravi1@gmail.com".to_string()).unwrap())
@@ -94,21 +276,145 @@ mod tests {
     #[tokio::test]
     async fn test_validate_user() {
         let email = Email::parse("Ravi@gmailk.ocm".to_string()).unwrap();
-        let password = Password::parse("Password123".to_string()).unwrap();
 
-        let u1 = user::User {
+        let u1 = NewUser {
+            id: UserId::default(),
             email,
-            password,
+            password: password(),
             requires_2fa: true,
         };
 
-        let mut store = HashMapUserStore::default();
+        let store = store();
         let _first = store.add_user(u1).await;
 
         let email = Email::parse("Ravi@gmailk.ocm".to_string()).unwrap();
-        let password = Password::parse("Password123".to_string()).unwrap();
+        let password = password();
 
-        let res = store.validate_user(&email, password.as_ref()).await;
+        let res = store.validate_user(&email, &password).await;
         assert_eq!(res, Ok(()));
+
+        let res = store
+            .validate_user(&email, &Password::parse("Password124".to_string()).unwrap())
+            .await;
+        assert_eq!(res, Err(UserStoreError::InvalidCredentials));
+
+        let unknown = Email::parse("nobody@gmailk.ocm".to_string()).unwrap();
+        let res = store.validate_user(&unknown, &password).await;
+        assert_eq!(res, Err(UserStoreError::InvalidCredentials));
+    }
+
+    #[tokio::test]
+    async fn test_get_user_by_id() {
+        let email = Email::parse("Ravi@gmailk.ocm".to_string()).unwrap();
+        let id = UserId::default();
+
+        let u1 = NewUser::new(id, email, password(), false);
+
+        let store = store();
+        let _first = store.add_user(u1).await;
+
+        let g = store.get_user_by_id(&id).await.unwrap();
+        assert_eq!(g.id, id);
+        assert_eq!(g.email.as_ref(), "ravi@gmailk.ocm");
+
+        let failed = store.get_user_by_id(&UserId::default()).await;
+        assert!(matches!(failed, Err(UserStoreError::UserNotFound)));
+    }
+
+    #[tokio::test]
+    async fn test_update_email() {
+        let email = Email::parse("ravi@gmail.com".to_string()).unwrap();
+        let taken = Email::parse("taken@gmail.com".to_string()).unwrap();
+        let new_email = Email::parse("ravi@example.com".to_string()).unwrap();
+        let id = UserId::default();
+
+        let store = store();
+        let _ = store
+            .add_user(NewUser::new(id, email.clone(), password(), false))
+            .await;
+        let _ = store
+            .add_user(NewUser::new(
+                UserId::default(),
+                taken.clone(),
+                password(),
+                false,
+            ))
+            .await;
+
+        let res = store.update_email(&id, taken).await;
+        assert_eq!(res, Err(UserStoreError::UserAlreadyExists));
+
+        let res = store
+            .update_email(&UserId::default(), new_email.clone())
+            .await;
+        assert_eq!(res, Err(UserStoreError::UserNotFound));
+
+        let res = store.update_email(&id, new_email.clone()).await;
+        assert_eq!(res, Ok(()));
+
+        assert!(matches!(
+            store.get_user(&email).await,
+            Err(UserStoreError::UserNotFound)
+        ));
+        let user = store.get_user(&new_email).await.unwrap();
+        assert_eq!(user.id, id);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_access_from_shared_store() {
+        let store = store();
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for i in 0..64 {
+            let store = store.clone();
+            tasks.spawn(async move {
+                let email = Email::parse(format!("user{}@example.com", i % 16)).unwrap();
+                let user = NewUser::new(UserId::default(), email.clone(), password(), false);
+                let added = store.add_user(user).await.is_ok();
+                assert!(store.get_user(&email).await.is_ok());
+                added
+            });
+        }
+
+        let mut added = 0;
+        while let Some(result) = tasks.join_next().await {
+            added += result.unwrap() as usize;
+        }
+
+        // Exactly one insert wins for each of the 16 addresses
+        assert_eq!(added, 16);
+        assert_eq!(store.users.len(), 16);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_add_same_email() {
+        let store = store();
+
+        let mut tasks = tokio::task::JoinSet::new();
+        for i in 0..32 {
+            let store = store.clone();
+            // Same address, written differently
+            let email = if i % 2 == 0 {
+                "ravi@x.com"
+            } else {
+                "RAVI@X.COM"
+            };
+            let email = Email::parse(email.to_string()).unwrap();
+            tasks.spawn(async move {
+                let user = NewUser::new(UserId::default(), email, password(), false);
+                store.add_user(user).await
+            });
+        }
+
+        let mut results = Vec::new();
+        while let Some(result) = tasks.join_next().await {
+            results.push(result.unwrap());
+        }
+
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+        assert!(results
+            .iter()
+            .filter(|r| r.is_err())
+            .all(|r| r == &Err(UserStoreError::UserAlreadyExists)));
     }
 }
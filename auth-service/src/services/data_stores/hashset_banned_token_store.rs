@@ -1,71 +1,155 @@
-use std::collections::HashSet;
+use std::sync::Arc;
 
 use axum::async_trait;
-use uuid::serde::simple::serialize;
+use chrono::{DateTime, Utc};
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
 
 use crate::domains::data_stores::{BannedTokenError, BannedTokenStore};
-#[derive(Default, Clone)]
+use crate::utils::clock::{Clock, SystemClock};
+
+// The `jti` of each banned token is kept with its expiry so it can be swept
+// once the token no longer validates anyway
+#[derive(Clone)]
 pub struct HashsetBannedTokenStore {
-    banned_token: HashSet<String>,
+    banned_jtis: Arc<DashMap<String, DateTime<Utc>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for HashsetBannedTokenStore {
+    fn default() -> Self {
+        Self {
+            banned_jtis: Arc::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl HashsetBannedTokenStore {
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // Returns how many expired tokens were removed. The map gives the memory
+    // back too, or a burst of revocations would keep it at its largest.
+    pub fn remove_expired(&self) -> usize {
+        let now = self.clock.now();
+        let mut removed = 0;
+        self.banned_jtis.retain(|_, expires_at| {
+            let keep = *expires_at > now;
+            removed += usize::from(!keep);
+            keep
+        });
+        if removed > 0 {
+            self.banned_jtis.shrink_to_fit();
+        }
+        removed
+    }
+
+    pub fn len(&self) -> usize {
+        self.banned_jtis.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.banned_jtis.is_empty()
+    }
 }
+
 #[async_trait]
 impl BannedTokenStore for HashsetBannedTokenStore {
-    async fn add_banned_token(&mut self, banned_token: String) -> Result<(), BannedTokenError> {
-        let current = self.banned_token.get(&banned_token);
-        match current {
-            Some(t) => Err(BannedTokenError::TokenExists),
-            None => {
-                self.banned_token.insert(banned_token);
+    async fn add_banned_token(
+        &self,
+        jti: String,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), BannedTokenError> {
+        let now = self.clock.now();
+        match self.banned_jtis.entry(jti) {
+            Entry::Occupied(entry) if *entry.get() > now => Err(BannedTokenError::TokenExists),
+            Entry::Occupied(mut entry) => {
+                entry.insert(expires_at);
+                Ok(())
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(expires_at);
                 Ok(())
             }
         }
     }
 
-    async fn does_token_exist(&self, banned_token: String) -> bool {
-        match self.banned_token.get(&banned_token) {
-            Some(t) => true,
-            None => false,
-        }
+    // Entries past their expiry count as gone before the next sweep
+    async fn does_token_exist(&self, jti: String) -> bool {
+        let now = self.clock.now();
+        self.banned_jtis
+            .get(&jti)
+            .is_some_and(|expires_at| *expires_at > now)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashSet;
-
     use crate::domains::data_stores::{BannedTokenError, BannedTokenStore};
     use crate::services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore;
+    use crate::utils::clock::{Clock, FakeClock};
+    use chrono::{DateTime, Duration, Utc};
+    use std::sync::Arc;
+
+    fn expires_at() -> DateTime<Utc> {
+        Utc::now() + Duration::minutes(10)
+    }
 
     #[tokio::test]
     async fn test_add_token() {
-        let mut b_tokens = HashsetBannedTokenStore {
-            banned_token: HashSet::new(),
-        };
+        let b_tokens = HashsetBannedTokenStore::default();
 
-        let res = b_tokens.add_banned_token("Ravi Lukkani".to_owned()).await;
-        assert_eq!(res.is_ok(), true);
+        let res = b_tokens
+            .add_banned_token("Ravi Lukkani".to_owned(), expires_at())
+            .await;
+        assert!(res.is_ok());
     }
 
     #[tokio::test]
     async fn test_add_same_token() {
-        let mut b_tokens = HashsetBannedTokenStore {
-            banned_token: HashSet::new(),
-        };
+        let b_tokens = HashsetBannedTokenStore::default();
 
-        let res = b_tokens.add_banned_token("Ravi Lukkani".to_owned()).await;
-        let res1 = b_tokens.add_banned_token("Ravi Lukkani".to_owned()).await;
+        let res = b_tokens
+            .add_banned_token("Ravi Lukkani".to_owned(), expires_at())
+            .await;
+        let res1 = b_tokens
+            .add_banned_token("Ravi Lukkani".to_owned(), expires_at())
+            .await;
         assert!(matches!(res1, Err(BannedTokenError::TokenExists)));
     }
 
     #[tokio::test]
     async fn test_check_token_exists() {
-        let mut b_tokens = HashsetBannedTokenStore {
-            banned_token: HashSet::new(),
-        };
+        let b_tokens = HashsetBannedTokenStore::default();
 
-        let res = b_tokens.add_banned_token("Ravi Lukkani".to_owned()).await;
+        let res = b_tokens
+            .add_banned_token("Ravi Lukkani".to_owned(), expires_at())
+            .await;
         let exist = b_tokens.does_token_exist("Ravi Lukkani".to_owned()).await;
 
         assert!(exist);
     }
+
+    #[tokio::test]
+    async fn test_remove_expired_tokens() {
+        let clock = FakeClock::default();
+        let b_tokens = HashsetBannedTokenStore::default().with_clock(Arc::new(clock.clone()));
+
+        let soon = clock.now() + Duration::minutes(1);
+        let later = clock.now() + Duration::minutes(10);
+        let _ = b_tokens.add_banned_token("soon".to_owned(), soon).await;
+        let _ = b_tokens.add_banned_token("later".to_owned(), later).await;
+
+        assert_eq!(b_tokens.remove_expired(), 0);
+
+        clock.advance(Duration::minutes(1));
+        assert!(!b_tokens.does_token_exist("soon".to_owned()).await);
+        assert_eq!(b_tokens.len(), 2);
+        assert_eq!(b_tokens.remove_expired(), 1);
+        assert_eq!(b_tokens.len(), 1);
+        assert!(b_tokens.does_token_exist("later".to_owned()).await);
+    }
 }
@@ -1,4 +1,18 @@
+pub mod hashmap_oauth_client_store;
+pub mod hashmap_session_store;
 pub mod hashmap_two_fa_code_store;
 pub mod hashmap_user_store;
 pub mod hashset_banned_token_store;
+pub mod postgres_oauth_client_store;
+pub mod postgres_session_store;
 pub mod postgres_user_store;
+
+use crate::domains::data_stores::UserStoreError;
+use crate::utils::password_hashing::HashingError;
+
+fn hashing_error(e: HashingError) -> UserStoreError {
+    match e {
+        HashingError::Saturated => UserStoreError::Unavailable,
+        HashingError::WorkerFailed | HashingError::HashFailed => UserStoreError::UnexpectedError,
+    }
+}
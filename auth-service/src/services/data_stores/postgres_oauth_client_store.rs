@@ -0,0 +1,72 @@
+use sqlx::PgPool;
+
+use crate::domains::client_secret::ClientSecretHash;
+use crate::domains::data_stores::{OAuthClientStore, OAuthClientStoreError};
+use crate::domains::oauth_client::OAuthClient;
+
+#[derive(Clone)]
+pub struct PostgresOAuthClientStore {
+    pool: PgPool,
+}
+
+impl PostgresOAuthClientStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl OAuthClientStore for PostgresOAuthClientStore {
+    async fn add_client(&self, client: OAuthClient) -> Result<(), OAuthClientStoreError> {
+        sqlx::query!(
+            r#"
+        INSERT INTO oauth_clients (client_id, name, redirect_uris, secret_hash, scopes)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+            client.client_id,                                // $1
+            client.name,                                     // $2
+            &client.redirect_uris[..],                       // $3
+            client.secret_hash.as_ref().map(|h| h.as_ref()), // $4
+            &client.scopes[..]                               // $5
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(db_err) if db_err.is_unique_violation() => {
+                OAuthClientStoreError::ClientAlreadyExists
+            }
+            _ => OAuthClientStoreError::UnexpectedError,
+        })?;
+
+        Ok(())
+    }
+
+    async fn get_client(&self, client_id: &str) -> Result<OAuthClient, OAuthClientStoreError> {
+        let record = sqlx::query!(
+            r#"
+    SELECT client_id, name, redirect_uris, secret_hash, scopes
+    FROM oauth_clients
+    WHERE client_id = $1
+    "#,
+            client_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| OAuthClientStoreError::UnexpectedError)?
+        .ok_or(OAuthClientStoreError::ClientNotFound)?;
+
+        let secret_hash = record
+            .secret_hash
+            .map(ClientSecretHash::parse)
+            .transpose()
+            .map_err(|_| OAuthClientStoreError::UnexpectedError)?;
+
+        Ok(OAuthClient {
+            client_id: record.client_id,
+            name: record.name,
+            redirect_uris: record.redirect_uris,
+            secret_hash,
+            scopes: record.scopes,
+        })
+    }
+}
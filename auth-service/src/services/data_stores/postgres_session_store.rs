@@ -0,0 +1,199 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use sqlx::PgPool;
+
+use crate::domains::data_stores::{
+    SessionStore, SessionStoreError, SESSION_LAST_SEEN_RESOLUTION_SECONDS,
+};
+use crate::domains::session::Session;
+use crate::domains::user_id::UserId;
+use crate::utils::clock::{Clock, SystemClock};
+
+#[derive(Clone)]
+pub struct PostgresSessionStore {
+    pool: PgPool,
+    // When this instance last wrote each session's `last_seen_at`. Touches
+    // closer together than the resolution don't reach the database.
+    last_written: Arc<DashMap<String, DateTime<Utc>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl PostgresSessionStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            last_written: Arc::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    // Returns how many remembered writes were old enough to be dropped
+    pub fn remove_expired(&self) -> usize {
+        let cutoff = self.clock.now() - resolution();
+        let mut removed = 0;
+        self.last_written.retain(|_, written_at| {
+            let keep = *written_at > cutoff;
+            removed += usize::from(!keep);
+            keep
+        });
+        removed
+    }
+}
+
+fn resolution() -> Duration {
+    Duration::seconds(SESSION_LAST_SEEN_RESOLUTION_SECONDS)
+}
+
+#[async_trait::async_trait]
+impl SessionStore for PostgresSessionStore {
+    async fn add_session(&self, session: Session) -> Result<(), SessionStoreError> {
+        sqlx::query!(
+            r#"
+        DELETE FROM sessions
+        WHERE user_id = $1 AND expires_at <= $2
+        "#,
+            session.user_id.as_ref(), // $1
+            session.created_at        // $2
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| SessionStoreError::UnexpectedError)?;
+
+        sqlx::query!(
+            r#"
+        INSERT INTO sessions (id, user_id, created_at, last_seen_at, expires_at, ip, user_agent)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+            session.id,               // $1
+            session.user_id.as_ref(), // $2
+            session.created_at,       // $3
+            session.last_seen_at,     // $4
+            session.expires_at,       // $5
+            session.ip,               // $6
+            session.user_agent        // $7
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e.as_database_error() {
+            Some(db_err) if db_err.is_unique_violation() => SessionStoreError::SessionAlreadyExists,
+            _ => SessionStoreError::UnexpectedError,
+        })?;
+
+        self.last_written.insert(session.id, session.last_seen_at);
+        Ok(())
+    }
+
+    async fn get_sessions(&self, user_id: &UserId) -> Result<Vec<Session>, SessionStoreError> {
+        let records = sqlx::query!(
+            r#"
+    SELECT id, user_id, created_at, last_seen_at, expires_at, ip, user_agent
+    FROM sessions
+    WHERE user_id = $1
+    ORDER BY created_at DESC
+    "#,
+            user_id.as_ref()
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| SessionStoreError::UnexpectedError)?;
+
+        Ok(records
+            .into_iter()
+            .map(|record| Session {
+                id: record.id,
+                user_id: record.user_id.into(),
+                created_at: record.created_at,
+                last_seen_at: record.last_seen_at,
+                expires_at: record.expires_at,
+                ip: record.ip,
+                user_agent: record.user_agent,
+            })
+            .collect())
+    }
+
+    async fn touch_session(
+        &self,
+        id: &str,
+        seen_at: DateTime<Utc>,
+    ) -> Result<(), SessionStoreError> {
+        if self
+            .last_written
+            .get(id)
+            .is_some_and(|written_at| seen_at - *written_at < resolution())
+        {
+            return Ok(());
+        }
+
+        sqlx::query!(
+            r#"
+        UPDATE sessions
+        SET last_seen_at = GREATEST(last_seen_at, $2)
+        WHERE id = $1
+        "#,
+            id,      // $1
+            seen_at  // $2
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| SessionStoreError::UnexpectedError)?;
+
+        // Unknown ids are remembered too, or every check of a token without
+        // a session would cost a write. A failed write isn't, so the next
+        // touch tries again.
+        self.last_written.insert(id.to_string(), seen_at);
+        Ok(())
+    }
+
+    async fn remove_session(
+        &self,
+        user_id: &UserId,
+        id: &str,
+    ) -> Result<Session, SessionStoreError> {
+        let record = sqlx::query!(
+            r#"
+        DELETE FROM sessions
+        WHERE id = $1 AND user_id = $2
+        RETURNING id, user_id, created_at, last_seen_at, expires_at, ip, user_agent
+        "#,
+            id,               // $1
+            user_id.as_ref()  // $2
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| SessionStoreError::UnexpectedError)?
+        .ok_or(SessionStoreError::SessionNotFound)?;
+
+        self.last_written.remove(id);
+        Ok(Session {
+            id: record.id,
+            user_id: record.user_id.into(),
+            created_at: record.created_at,
+            last_seen_at: record.last_seen_at,
+            expires_at: record.expires_at,
+            ip: record.ip,
+            user_agent: record.user_agent,
+        })
+    }
+
+    async fn remove_sessions(&self, user_id: &UserId) -> Result<(), SessionStoreError> {
+        sqlx::query!(
+            r#"
+        DELETE FROM sessions
+        WHERE user_id = $1
+        "#,
+            user_id.as_ref()
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| SessionStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
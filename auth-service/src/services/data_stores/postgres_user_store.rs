@@ -1,63 +1,146 @@
-use std::{error::Error, panic::panic_any};
-
-use argon2::{
-    password_hash::SaltString, Algorithm, Argon2, Params, PasswordHash, PasswordHasher,
-    PasswordVerifier, Version,
-};
-
 use crate::domains::{
     data_stores::{UserStore, UserStoreError},
     email::{self, Email},
-    password::{self, Password},
-    user::{self, User},
+    password::Password,
+    password_hash::PasswordHash,
+    user::{self, NewUser, User},
+    user_id::UserId,
 };
+use crate::utils::clock::{Clock, SystemClock};
+use crate::utils::password_hashing::{CredentialHasher, HashingParams, HashingPool};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
 use sqlx::PgPool;
-use sqlx::Row;
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::hashing_error;
+
+const DEFAULT_SESSION_VERSION_TTL: Duration = Duration::from_secs(5);
 
 #[derive(Clone)]
 pub struct PostgresUserStore {
     pool: PgPool,
+    hasher: CredentialHasher,
+    session_versions: SessionVersionCache,
 }
 
 impl PostgresUserStore {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            hasher: CredentialHasher::default(),
+            session_versions: SessionVersionCache::new(DEFAULT_SESSION_VERSION_TTL),
+        }
+    }
+
+    // How long a session version read from the database is trusted. Sessions
+    // ended through this instance are rejected right away, through another
+    // one only once the cached version is this old.
+    pub fn with_session_version_ttl(mut self, ttl: Duration) -> Self {
+        self.session_versions.ttl =
+            chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::MAX);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.session_versions.clock = clock;
+        self
+    }
+
+    // Returns how many cached session versions were dropped
+    pub fn remove_expired(&self) -> usize {
+        self.session_versions.remove_expired()
+    }
+
+    pub fn with_hashing_params(mut self, hashing_params: HashingParams) -> Self {
+        self.hasher = CredentialHasher::new(hashing_params, self.hasher.pool().clone());
+        self
     }
-}
 
-async fn verify_password_hash(
-    expected_password_hash: &str,
-    password_candidate: &str,
-) -> Result<(), Box<dyn Error>> {
-    let expected_password_hash: PasswordHash<'_> = PasswordHash::new(expected_password_hash)?;
+    pub fn with_hashing_pool(mut self, hashing_pool: HashingPool) -> Self {
+        self.hasher = CredentialHasher::new(self.hasher.params().clone(), hashing_pool);
+        self
+    }
+
+    // Insert a user whose password was hashed by another system. Any format
+    // `PasswordHash` accepts is upgraded to Argon2id on first login.
+    pub async fn import_user(
+        &self,
+        id: &UserId,
+        email: &Email,
+        password_hash: &PasswordHash,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError> {
+        sqlx::query!(
+            r#"
+        INSERT INTO users (id, email, password_hash, requires_2fa)
+        VALUES ($1, $2, $3, $4)
+        "#,
+            id.as_ref(),            // $1
+            email.as_ref(),         // $2
+            password_hash.as_ref(), // $3
+            requires_2fa            // $4
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(write_error)?;
+
+        Ok(())
+    }
+
+    // Replace a hash made with outdated parameters now that we know the password.
+    // Only swaps the exact hash we verified, in case it changed in the meantime.
+    async fn upgrade_password_hash(
+        &self,
+        email: &Email,
+        old_password_hash: &PasswordHash,
+        password: &str,
+    ) -> Result<(), UserStoreError> {
+        let new_password_hash = self.hasher.hash(password).await.map_err(hashing_error)?;
+
+        sqlx::query!(
+            r#"
+        UPDATE users
+        SET password_hash = $1
+        WHERE email = $2 AND password_hash = $3
+        "#,
+            new_password_hash.as_ref(), // $1
+            email.as_ref(),             // $2
+            old_password_hash.as_ref()  // $3
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
 
-    Argon2::default()
-        .verify_password(password_candidate.as_bytes(), &expected_password_hash)
-        .map_err(|e| e.into())
+        Ok(())
+    }
 }
 
 #[async_trait::async_trait]
 impl UserStore for PostgresUserStore {
-    async fn add_user(&mut self, user: user::User) -> Result<(), UserStoreError> {
-        let password_hash = compute_password_hash(user.password.as_ref())
+    async fn add_user(&self, user: NewUser) -> Result<(), UserStoreError> {
+        let password_hash = self
+            .hasher
+            .hash(user.password.as_ref())
             .await
-            .map_err(|_| UserStoreError::UnexpectedError)?;
+            .map_err(hashing_error)?;
 
         // compile-time verified query
         let result = sqlx::query!(
             r#"
-        INSERT INTO users (email, password_hash, requires_2fa)
-        VALUES ($1, $2, $3)
+        INSERT INTO users (id, email, password_hash, requires_2fa)
+        VALUES ($1, $2, $3, $4)
         "#,
-            user.email.as_ref(), // $1
-            password_hash,       // $2
-            user.requires_2fa    // $3
+            user.id.as_ref(),       // $1
+            user.email.as_ref(),    // $2
+            password_hash.as_ref(), // $3
+            user.requires_2fa       // $4
         )
         .execute(&self.pool)
         .await
-        .map_err(|_| UserStoreError::UnexpectedError)?;
-        let n = result.rows_affected();
-        println!("users insert rows_affected = {}", n);
+        .map_err(write_error)?;
+
         if result.rows_affected() == 1 {
             Ok(())
         } else {
@@ -68,7 +151,7 @@ impl UserStore for PostgresUserStore {
     async fn get_user(&self, email: &Email) -> Result<user::User, UserStoreError> {
         let result = sqlx::query!(
             r#"
-    SELECT email, password_hash, requires_2fa
+    SELECT id, email, password_hash, requires_2fa, session_version
     FROM users
     WHERE email = $1
     "#,
@@ -84,52 +167,231 @@ impl UserStore for PostgresUserStore {
                 let email =
                     Email::parse(record.email).map_err(|_| UserStoreError::UnexpectedError)?;
 
-                // You probably want the stored hash, not a hardcoded password:
-                let password_hash = Password::parse(record.password_hash)
+                let password_hash = PasswordHash::parse(record.password_hash)
                     .map_err(|_| UserStoreError::UnexpectedError)?;
 
                 // If the column is nullable, the macro types it as Option<bool>
                 let requires_2fa = record.requires_2fa;
 
-                Ok(User::new(email, password_hash, requires_2fa))
+                Ok(User::new(
+                    record.id.into(),
+                    email,
+                    password_hash,
+                    requires_2fa,
+                    session_version(record.session_version)?,
+                ))
+            }
+            None => Err(UserStoreError::UserNotFound),
+        }
+    }
+
+    async fn get_user_by_id(&self, id: &UserId) -> Result<user::User, UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+    SELECT id, email, password_hash, requires_2fa, session_version
+    FROM users
+    WHERE id = $1
+    "#,
+            id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match result {
+            Some(record) => {
+                let email =
+                    Email::parse(record.email).map_err(|_| UserStoreError::UnexpectedError)?;
+                let password_hash = PasswordHash::parse(record.password_hash)
+                    .map_err(|_| UserStoreError::UnexpectedError)?;
+
+                Ok(User::new(
+                    record.id.into(),
+                    email,
+                    password_hash,
+                    record.requires_2fa,
+                    session_version(record.session_version)?,
+                ))
             }
             None => Err(UserStoreError::UserNotFound),
         }
     }
 
-    async fn validate_user(&self, email: &Email, password: &str) -> Result<(), UserStoreError> {
-        let result = sqlx::query("select * from users where email = $1")
-            .bind(email.as_ref())
-            .fetch_one(&self.pool)
+    async fn validate_user(
+        &self,
+        email: &Email,
+        password: &Password,
+    ) -> Result<(), UserStoreError> {
+        let password = password.as_ref();
+        let result = sqlx::query!(
+            r#"
+    SELECT password_hash
+    FROM users
+    WHERE email = $1
+    "#,
+            email.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        // Unknown emails go through the same verification as a wrong password
+        // and get the same error, so neither reveals who has an account
+        let expected_password_hash = match result {
+            Some(record) => PasswordHash::parse(record.password_hash)
+                .map_err(|_| UserStoreError::UnexpectedError)?,
+            None => {
+                self.hasher
+                    .verify_dummy(password)
+                    .await
+                    .map_err(hashing_error)?;
+                return Err(UserStoreError::InvalidCredentials);
+            }
+        };
+
+        let verified = self
+            .hasher
+            .verify(&expected_password_hash, password)
             .await
-            .map_err(|_| UserStoreError::UnexpectedError)?;
-        println!("got user from validation");
-        if result.is_empty() {
+            .map_err(hashing_error)?;
+        if !verified {
             return Err(UserStoreError::InvalidCredentials);
         }
 
-        let email =
-            Email::parse(result.get("email")).map_err(|_| UserStoreError::UnexpectedError)?;
-        let expected_password_hash: String = result.get("password_hash");
-        let requires_2fa: bool = result.get("requires_2fa");
-        println!("Expected Hash  {}", expected_password_hash);
-        verify_password_hash(&expected_password_hash, password)
-            .await
-            .map_err(|_| UserStoreError::InvalidCredentials)?;
+        // A failed upgrade fails the login too, like any other store error,
+        // and is tried again when the user retries
+        if self.hasher.needs_rehash(&expected_password_hash) {
+            self.upgrade_password_hash(email, &expected_password_hash, password)
+                .await?;
+        }
+
+        Ok(())
+    }
 
+    async fn update_email(&self, id: &UserId, new_email: Email) -> Result<(), UserStoreError> {
+        let record = sqlx::query!(
+            r#"
+        UPDATE users
+        SET email = $1, session_version = session_version + 1
+        WHERE id = $2
+        RETURNING session_version
+        "#,
+            new_email.as_ref(), // $1
+            id.as_ref()         // $2
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(write_error)?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        self.session_versions
+            .insert(*id, session_version(record.session_version)?);
         Ok(())
     }
+
+    async fn get_session_version(&self, id: &UserId) -> Result<u64, UserStoreError> {
+        if let Some(version) = self.session_versions.get(id) {
+            return Ok(version);
+        }
+
+        let record = sqlx::query!(
+            r#"
+    SELECT session_version
+    FROM users
+    WHERE id = $1
+    "#,
+            id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        let version = session_version(record.session_version)?;
+        self.session_versions.insert(*id, version);
+        Ok(version)
+    }
+
+    async fn end_all_sessions(&self, id: &UserId) -> Result<u64, UserStoreError> {
+        let record = sqlx::query!(
+            r#"
+        UPDATE users
+        SET session_version = session_version + 1
+        WHERE id = $1
+        RETURNING session_version
+        "#,
+            id.as_ref()
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)?;
+
+        let version = session_version(record.session_version)?;
+        self.session_versions.insert(*id, version);
+        Ok(version)
+    }
 }
 
-async fn compute_password_hash(password: &str) -> Result<String, Box<dyn Error>> {
-    let salt: SaltString = SaltString::generate(&mut rand::thread_rng());
-    let password_hash = Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(15000, 2, 1, None)?,
-    )
-    .hash_password(password.as_bytes(), &salt)?
-    .to_string();
-
-    Ok(password_hash)
+// Session versions this instance read or wrote recently
+#[derive(Clone)]
+struct SessionVersionCache {
+    versions: Arc<DashMap<UserId, (u64, DateTime<Utc>)>>,
+    ttl: chrono::Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl SessionVersionCache {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            versions: Arc::default(),
+            ttl: chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::MAX),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    fn is_fresh(&self, cached_at: DateTime<Utc>) -> bool {
+        self.clock.now() - cached_at < self.ttl
+    }
+
+    fn get(&self, id: &UserId) -> Option<u64> {
+        self.versions
+            .get(id)
+            .filter(|entry| self.is_fresh(entry.1))
+            .map(|entry| entry.0)
+    }
+
+    // Versions only count up, so when concurrent requests race to cache
+    // theirs the highest one is the current one
+    fn insert(&self, id: UserId, version: u64) {
+        let now = self.clock.now();
+        let mut entry = self.versions.entry(id).or_insert((version, now));
+        if entry.0 <= version {
+            *entry = (version, now);
+        }
+    }
+
+    fn remove_expired(&self) -> usize {
+        let mut removed = 0;
+        self.versions.retain(|_, (_, cached_at)| {
+            let keep = self.is_fresh(*cached_at);
+            removed += usize::from(!keep);
+            keep
+        });
+        removed
+    }
+}
+
+// The column is a BIGINT that only ever counts up from 0
+fn session_version(version: i64) -> Result<u64, UserStoreError> {
+    u64::try_from(version).map_err(|_| UserStoreError::UnexpectedError)
+}
+
+// The unique indexes on id and email are what keep users distinct, so a
+// violation means someone got there first
+fn write_error(e: sqlx::Error) -> UserStoreError {
+    match e.as_database_error() {
+        Some(db_err) if db_err.is_unique_violation() => UserStoreError::UserAlreadyExists,
+        _ => UserStoreError::UnexpectedError,
+    }
 }
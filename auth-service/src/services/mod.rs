@@ -1,2 +1,3 @@
 pub mod data_stores;
 pub mod mock_email_client;
+pub mod recording_email_client;
@@ -0,0 +1,131 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+
+use crate::domains::email::Email;
+use crate::domains::EmailClient;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentEmail {
+    pub recipient: Email,
+    pub subject: String,
+    pub content: String,
+    pub sent_at: DateTime<Utc>,
+}
+
+// Keeps every message instead of delivering it, so tests can read what a
+// user would find in their inbox. Clones share the same outbox.
+#[derive(Debug, Default, Clone)]
+pub struct RecordingEmailClient {
+    sent: Arc<Mutex<Vec<SentEmail>>>,
+}
+
+impl RecordingEmailClient {
+    // Everything sent so far, oldest first
+    pub fn sent_emails(&self) -> Vec<SentEmail> {
+        self.sent.lock().unwrap().clone()
+    }
+
+    pub fn sent_to(&self, recipient: &Email) -> Vec<SentEmail> {
+        self.sent
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|email| &email.recipient == recipient)
+            .cloned()
+            .collect()
+    }
+
+    pub fn last_sent_to(&self, recipient: &Email) -> Option<SentEmail> {
+        self.sent
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|email| &email.recipient == recipient)
+            .cloned()
+    }
+
+    pub fn last_with_subject(&self, recipient: &Email, subject: &str) -> Option<SentEmail> {
+        self.sent
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|email| &email.recipient == recipient && email.subject == subject)
+            .cloned()
+    }
+
+    pub fn clear(&self) {
+        self.sent.lock().unwrap().clear();
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailClient for RecordingEmailClient {
+    async fn send_email(
+        &self,
+        recipient: &Email,
+        subject: &str,
+        content: &str,
+    ) -> Result<(), String> {
+        self.sent.lock().unwrap().push(SentEmail {
+            recipient: recipient.clone(),
+            subject: subject.to_string(),
+            content: content.to_string(),
+            sent_at: Utc::now(),
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(address: &str) -> Email {
+        Email::parse(address.to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_records_sent_emails_in_order() {
+        let client = RecordingEmailClient::default();
+        let ravi = email("ravi@example.com");
+        let other = email("other@example.com");
+
+        client.send_email(&ravi, "First", "one").await.unwrap();
+        client.send_email(&other, "Hello", "two").await.unwrap();
+        client.send_email(&ravi, "Second", "three").await.unwrap();
+
+        let sent = client.sent_emails();
+        assert_eq!(sent.len(), 3);
+        assert!(sent.windows(2).all(|w| w[0].sent_at <= w[1].sent_at));
+
+        let to_ravi = client.sent_to(&ravi);
+        assert_eq!(to_ravi.len(), 2);
+        assert_eq!(to_ravi[0].subject, "First");
+
+        let last = client.last_sent_to(&ravi).unwrap();
+        assert_eq!(last.subject, "Second");
+        assert_eq!(last.content, "three");
+        assert_eq!(
+            client.last_with_subject(&ravi, "First").unwrap().content,
+            "one"
+        );
+        assert!(client.last_sent_to(&email("nobody@example.com")).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_clones_share_the_outbox() {
+        let client = RecordingEmailClient::default();
+        let clone = client.clone();
+        let ravi = email("ravi@example.com");
+
+        clone.send_email(&ravi, "Subject", "body").await.unwrap();
+        assert_eq!(client.sent_to(&ravi).len(), 1);
+
+        client.clear();
+        assert!(clone.sent_emails().is_empty());
+    }
+}
@@ -1,17 +1,24 @@
 use std::fmt::Display;
 use std::result;
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
 use axum_extra::extract::cookie::{Cookie, SameSite};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::errors::ErrorKind;
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Validation};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use super::constants::{JWT_COOKIE_NAME, JWT_SECRET};
-use crate::domains::data_stores::{BannedTokenError, BannedTokenStore};
+use super::clock::Clock;
+use super::constants::{
+    ID_TOKEN_SIGNING_KEY, JWT_COOKIE_NAME, JWT_ISSUER, JWT_LEEWAY_SECONDS, JWT_SECRET,
+    SESSION_AUDIENCE,
+};
+use crate::domains::data_stores::{BannedTokenError, BannedTokenStore, UserStore};
 use crate::domains::email::Email;
 use crate::domains::error::AuthAPIError;
+use crate::domains::user::User;
+use crate::domains::user_id::UserId;
 
 // This is definitely NOT a good secret. We will update it soon!
 //const JWT_SECRET: &str = "secret";
@@ -20,14 +27,65 @@ use crate::domains::error::AuthAPIError;
 pub enum ValidateTokenError {
     TokenError(jsonwebtoken::errors::Error),
     Banned(AuthAPIError),
+    StaleSession,
+}
+
+// How and when the user proved who they are. Set at login and carried into
+// every token issued for that session, so relying parties can tell a
+// password login from one that also passed 2FA.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Authentication {
+    // Authentication method references, values from RFC 8176
+    #[serde(default)]
+    pub amr: Vec<String>,
+    #[serde(default)]
+    pub auth_time: usize,
+}
+
+impl Authentication {
+    pub fn password(clock: &dyn Clock) -> Self {
+        Self::new(&["pwd"], clock)
+    }
+
+    // The emailed code is a one-time password on top of the password
+    pub fn two_factor(clock: &dyn Clock) -> Self {
+        Self::new(&["pwd", "otp", "mfa"], clock)
+    }
+
+    fn new(amr: &[&str], clock: &dyn Clock) -> Self {
+        Authentication {
+            amr: amr.iter().map(|method| method.to_string()).collect(),
+            auth_time: clock.now().timestamp().try_into().unwrap_or_default(),
+        }
+    }
 }
 
 // Create cookie with a new JWT auth token
-pub fn generate_auth_cookie(email: &Email) -> Result<Cookie<'static>, GenerateTokenError> {
-    let token = generate_auth_token(email)?;
+pub fn generate_auth_cookie(
+    user_id: &UserId,
+    email: &Email,
+    session_version: u64,
+    authentication: &Authentication,
+    clock: &dyn Clock,
+) -> Result<Cookie<'static>, GenerateTokenError> {
+    let token = generate_auth_token(user_id, email, session_version, authentication, clock)?;
     Ok(create_auth_cookie(token))
 }
 
+// Like `generate_auth_cookie`, also handing back the claims of the token so
+// the session it starts can be recorded
+pub fn generate_session_cookie(
+    user_id: &UserId,
+    email: &Email,
+    session_version: u64,
+    authentication: &Authentication,
+    clock: &dyn Clock,
+) -> Result<(Cookie<'static>, Claims), GenerateTokenError> {
+    let claims = auth_claims(user_id, email, session_version, authentication, clock)?;
+    let token = create_token(&claims).map_err(GenerateTokenError::TokenError)?;
+    Ok((create_auth_cookie(token), claims))
+}
+
 // Create cookie and set the value to the passed-in token string
 fn create_auth_cookie(token: String) -> Cookie<'static> {
     let cookie = Cookie::build((JWT_COOKIE_NAME, token))
@@ -49,12 +107,57 @@ pub enum GenerateTokenError {
 pub const TOKEN_TTL_SECONDS: i64 = 600; // 10 minutes
 
 // Create JWT auth token
-pub fn generate_auth_token(email: &Email) -> Result<String, GenerateTokenError> {
+pub fn generate_auth_token(
+    user_id: &UserId,
+    email: &Email,
+    session_version: u64,
+    authentication: &Authentication,
+    clock: &dyn Clock,
+) -> Result<String, GenerateTokenError> {
+    let claims = auth_claims(user_id, email, session_version, authentication, clock)?;
+
+    create_token(&claims).map_err(GenerateTokenError::TokenError)
+}
+
+// Create the JWT auth token handed to an OAuth client, which also records
+// the client, the scope the user agreed to and the session they did it from
+pub fn generate_access_token(
+    user_id: &UserId,
+    email: &Email,
+    session_version: u64,
+    grant: &AuthorizationGrant,
+    clock: &dyn Clock,
+) -> Result<String, GenerateTokenError> {
+    let claims = Claims {
+        aud: grant.client_id.clone(),
+        client_id: Some(grant.client_id.clone()),
+        scope: grant.scope.clone(),
+        sid: Some(grant.sid.clone()),
+        ..auth_claims(
+            user_id,
+            email,
+            session_version,
+            &grant.authentication,
+            clock,
+        )?
+    };
+
+    create_token(&claims).map_err(GenerateTokenError::TokenError)
+}
+
+fn auth_claims(
+    user_id: &UserId,
+    email: &Email,
+    session_version: u64,
+    authentication: &Authentication,
+    clock: &dyn Clock,
+) -> Result<Claims, GenerateTokenError> {
     let delta = chrono::Duration::try_seconds(TOKEN_TTL_SECONDS)
         .ok_or(GenerateTokenError::UnexpectedError)?;
 
     // Create JWT expiration time
-    let exp = Utc::now()
+    let now = clock.now();
+    let exp = now
         .checked_add_signed(delta)
         .ok_or(GenerateTokenError::UnexpectedError)?
         .timestamp();
@@ -63,33 +166,407 @@ pub fn generate_auth_token(email: &Email) -> Result<String, GenerateTokenError>
     let exp: usize = exp
         .try_into()
         .map_err(|_| GenerateTokenError::UnexpectedError)?;
+    let iat: usize = now
+        .timestamp()
+        .try_into()
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
 
-    let sub = email.as_ref().to_owned();
-
-    let claims = Claims { sub, exp };
+    // The subject is the stable user id, the email is only informational
+    let sub = user_id.to_string();
+    let email = email.as_ref().to_owned();
 
-    create_token(&claims).map_err(GenerateTokenError::TokenError)
+    Ok(Claims {
+        iss: JWT_ISSUER.to_owned(),
+        sub,
+        aud: SESSION_AUDIENCE.to_owned(),
+        exp,
+        iat,
+        nbf: iat,
+        jti: Uuid::new_v4().to_string(),
+        email,
+        session_version,
+        client_id: None,
+        scope: None,
+        sid: None,
+        authentication: authentication.clone(),
+    })
 }
 
-// Check if JWT auth token is valid by decoding it using the JWT secret
+// Check if JWT auth token is valid by decoding it using the JWT secret.
+// With an `audience`, only tokens issued for it pass: `SESSION_AUDIENCE` for
+// login sessions, a client id for the tokens that client got through OAuth.
 pub async fn validate_token<T: BannedTokenStore + Send + Sync + Clone>(
     token: &str,
-    banned_token_store: Arc<RwLock<T>>,
+    audience: Option<&str>,
+    banned_token_store: Arc<T>,
+    clock: &dyn Clock,
 ) -> Result<Claims, ValidateTokenError> {
-    let store = banned_token_store.read().await;
-    let result = store.does_token_exist(token.to_owned()).await;
-
-    if (result) {
-        Err(ValidateTokenError::Banned(AuthAPIError::InvalidToken))
-    } else {
-        decode::<Claims>(
-            token,
-            &DecodingKey::from_secret(JWT_SECRET.as_bytes()),
-            &Validation::default(),
-        )
-        .map(|data| data.claims)
-        .map_err(ValidateTokenError::TokenError)
+    let claims = decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(JWT_SECRET.as_bytes()),
+        &access_token_validation(audience),
+    )
+    .map(|data| data.claims)
+    .map_err(ValidateTokenError::TokenError)?;
+
+    if banned_token_store
+        .does_token_exist(claims.jti.clone())
+        .await
+    {
+        return Err(ValidateTokenError::Banned(AuthAPIError::InvalidToken));
+    }
+    // A token an OAuth client got through a login session ends with it
+    if let Some(sid) = &claims.sid {
+        if banned_token_store.does_token_exist(sid.clone()).await {
+            return Err(ValidateTokenError::Banned(AuthAPIError::InvalidToken));
+        }
+    }
+
+    check_not_before(claims.nbf, clock)?;
+    check_expiry(claims.exp, clock)?;
+    Ok(claims)
+}
+
+// Validate the token and make sure the user hasn't ended their sessions since
+// it was issued, by logging out everywhere or changing their email. The
+// session version usually comes from the user store's cache rather than the
+// database, so this is cheap enough for every request.
+pub async fn validate_session<
+    T: UserStore + Send + Sync + Clone,
+    T1: BannedTokenStore + Send + Sync + Clone,
+>(
+    token: &str,
+    audience: Option<&str>,
+    banned_token_store: Arc<T1>,
+    user_store: Arc<T>,
+    clock: &dyn Clock,
+) -> Result<Claims, ValidateTokenError> {
+    let claims = validate_token(token, audience, banned_token_store, clock).await?;
+
+    let user_id =
+        UserId::parse(claims.sub.clone()).map_err(|_| ValidateTokenError::StaleSession)?;
+    let session_version = user_store
+        .get_session_version(&user_id)
+        .await
+        .map_err(|_| ValidateTokenError::StaleSession)?;
+
+    if claims.session_version != session_version {
+        return Err(ValidateTokenError::StaleSession);
+    }
+
+    Ok(claims)
+}
+
+// This value determines how long an email change confirmation link is valid for
+pub const EMAIL_CHANGE_TTL_SECONDS: i64 = 3600; // 1 hour
+
+// Create the token embedded in the link sent to the new address
+pub fn generate_email_change_token(
+    user_id: &UserId,
+    current_email: &Email,
+    new_email: &Email,
+    session_version: u64,
+    clock: &dyn Clock,
+) -> Result<String, GenerateTokenError> {
+    let delta = chrono::Duration::try_seconds(EMAIL_CHANGE_TTL_SECONDS)
+        .ok_or(GenerateTokenError::UnexpectedError)?;
+
+    let exp = clock
+        .now()
+        .checked_add_signed(delta)
+        .ok_or(GenerateTokenError::UnexpectedError)?
+        .timestamp();
+
+    let exp: usize = exp
+        .try_into()
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    let claims = EmailChangeClaims {
+        sub: user_id.to_string(),
+        current_email: current_email.as_ref().to_owned(),
+        new_email: new_email.as_ref().to_owned(),
+        session_version,
+        exp,
+    };
+
+    encode(
+        &jsonwebtoken::Header::default(),
+        &claims,
+        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+    )
+    .map_err(GenerateTokenError::TokenError)
+}
+
+pub fn validate_email_change_token(
+    token: &str,
+    clock: &dyn Clock,
+) -> Result<EmailChangeClaims, ValidateTokenError> {
+    let claims = decode::<EmailChangeClaims>(
+        token,
+        &DecodingKey::from_secret(JWT_SECRET.as_bytes()),
+        &validation(),
+    )
+    .map(|data| data.claims)
+    .map_err(ValidateTokenError::TokenError)?;
+
+    check_expiry(claims.exp, clock)?;
+    Ok(claims)
+}
+
+// This value determines how long an authorization code can be redeemed for
+pub const AUTHORIZATION_CODE_TTL_SECONDS: i64 = 60; // 1 minute
+
+// Sessions are ended by banning their id. What an OAuth client got from one
+// can outlive it: the code is exchanged up to its TTL after being issued and
+// the access token then lasts its own. The ban is kept until those are over.
+pub fn session_ban_expires_at(session_expires_at: DateTime<Utc>) -> DateTime<Utc> {
+    session_expires_at
+        + chrono::Duration::seconds(AUTHORIZATION_CODE_TTL_SECONDS + TOKEN_TTL_SECONDS)
+}
+
+// What the user consented to at `/oauth/authorize`, handed back to the
+// client as an authorization code it can exchange for an access token
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuthorizationGrant {
+    pub sub: String,
+    // Of the session the code was issued from, so ending that session also
+    // voids codes not yet exchanged
+    pub session_version: u64,
+    // Id of that session. Ending just it voids the code and the access token
+    // it's exchanged for, see `session_ban_expires_at`.
+    pub sid: String,
+    pub client_id: String,
+    pub redirect_uri: String,
+    pub code_challenge: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(flatten)]
+    pub authentication: Authentication,
+}
+
+pub fn generate_authorization_code(
+    grant: AuthorizationGrant,
+    clock: &dyn Clock,
+) -> Result<String, GenerateTokenError> {
+    let delta = chrono::Duration::try_seconds(AUTHORIZATION_CODE_TTL_SECONDS)
+        .ok_or(GenerateTokenError::UnexpectedError)?;
+
+    let exp = clock
+        .now()
+        .checked_add_signed(delta)
+        .ok_or(GenerateTokenError::UnexpectedError)?
+        .timestamp();
+
+    let exp: usize = exp
+        .try_into()
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    let claims = AuthorizationCodeClaims {
+        grant,
+        exp,
+        jti: Uuid::new_v4().to_string(),
+    };
+
+    encode(
+        &jsonwebtoken::Header::default(),
+        &claims,
+        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+    )
+    .map_err(GenerateTokenError::TokenError)
+}
+
+// Codes have no `email` claim and auth tokens no `code_challenge`, so neither
+// can be passed off as the other
+pub fn validate_authorization_code(
+    code: &str,
+    clock: &dyn Clock,
+) -> Result<AuthorizationCodeClaims, ValidateTokenError> {
+    let claims = decode::<AuthorizationCodeClaims>(
+        code,
+        &DecodingKey::from_secret(JWT_SECRET.as_bytes()),
+        &validation(),
+    )
+    .map(|data| data.claims)
+    .map_err(ValidateTokenError::TokenError)?;
+
+    check_expiry(claims.exp, clock)?;
+    Ok(claims)
+}
+
+// Tells the client who logged in, signed with a key it can fetch from our
+// JWKS endpoint rather than the secret our own tokens use
+pub fn generate_id_token(
+    user_id: &UserId,
+    client_id: &str,
+    nonce: Option<String>,
+    authentication: &Authentication,
+    clock: &dyn Clock,
+) -> Result<String, GenerateTokenError> {
+    let delta = chrono::Duration::try_seconds(TOKEN_TTL_SECONDS)
+        .ok_or(GenerateTokenError::UnexpectedError)?;
+
+    let now = clock.now();
+    let exp = now
+        .checked_add_signed(delta)
+        .ok_or(GenerateTokenError::UnexpectedError)?
+        .timestamp();
+
+    let claims = IdTokenClaims {
+        iss: JWT_ISSUER.to_owned(),
+        sub: user_id.to_string(),
+        aud: client_id.to_owned(),
+        exp: exp
+            .try_into()
+            .map_err(|_| GenerateTokenError::UnexpectedError)?,
+        iat: now
+            .timestamp()
+            .try_into()
+            .map_err(|_| GenerateTokenError::UnexpectedError)?,
+        nonce,
+        authentication: authentication.clone(),
+    };
+
+    encode(
+        &ID_TOKEN_SIGNING_KEY.header(),
+        &claims,
+        ID_TOKEN_SIGNING_KEY.encoding_key(),
+    )
+    .map_err(GenerateTokenError::TokenError)
+}
+
+// This value determines how long a client credentials token is valid for.
+// Services can get a new one whenever they need, so keep it short.
+pub const CLIENT_TOKEN_TTL_SECONDS: i64 = 300; // 5 minutes
+
+pub const CLIENT_CREDENTIALS_GRANT: &str = "client_credentials";
+
+// Access token a confidential client got for itself, not for a user
+pub fn generate_client_token(
+    client_id: &str,
+    scope: &str,
+    clock: &dyn Clock,
+) -> Result<String, GenerateTokenError> {
+    let delta = chrono::Duration::try_seconds(CLIENT_TOKEN_TTL_SECONDS)
+        .ok_or(GenerateTokenError::UnexpectedError)?;
+
+    let now = clock.now();
+    let exp = now
+        .checked_add_signed(delta)
+        .ok_or(GenerateTokenError::UnexpectedError)?
+        .timestamp();
+
+    let exp: usize = exp
+        .try_into()
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+    let iat: usize = now
+        .timestamp()
+        .try_into()
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    // Client tokens are only good for our own privileged endpoints
+    let claims = ClientTokenClaims {
+        iss: JWT_ISSUER.to_owned(),
+        sub: client_id.to_owned(),
+        aud: JWT_ISSUER.to_owned(),
+        exp,
+        iat,
+        nbf: iat,
+        jti: Uuid::new_v4().to_string(),
+        client_id: client_id.to_owned(),
+        grant_type: CLIENT_CREDENTIALS_GRANT.to_owned(),
+        scope: scope.to_owned(),
+    };
+
+    encode(
+        &jsonwebtoken::Header::default(),
+        &claims,
+        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+    )
+    .map_err(GenerateTokenError::TokenError)
+}
+
+// Neither user tokens nor authorization codes have a `grant_type`, so they
+// don't pass for a client token however they were scoped
+pub async fn validate_client_token<T: BannedTokenStore + Send + Sync + Clone>(
+    token: &str,
+    banned_token_store: Arc<T>,
+    clock: &dyn Clock,
+) -> Result<ClientTokenClaims, ValidateTokenError> {
+    let claims = decode::<ClientTokenClaims>(
+        token,
+        &DecodingKey::from_secret(JWT_SECRET.as_bytes()),
+        &access_token_validation(Some(JWT_ISSUER.as_str())),
+    )
+    .map(|data| data.claims)
+    .map_err(ValidateTokenError::TokenError)?;
+
+    if claims.grant_type != CLIENT_CREDENTIALS_GRANT {
+        return Err(ValidateTokenError::TokenError(
+            ErrorKind::InvalidToken.into(),
+        ));
+    }
+
+    if banned_token_store
+        .does_token_exist(claims.jti.clone())
+        .await
+    {
+        return Err(ValidateTokenError::Banned(AuthAPIError::InvalidToken));
     }
+
+    check_not_before(claims.nbf, clock)?;
+    check_expiry(claims.exp, clock)?;
+    Ok(claims)
+}
+
+// jsonwebtoken would check `exp` against the system time, so we do it
+// ourselves against the clock we were given
+fn validation() -> Validation {
+    let mut validation = Validation::default();
+    validation.validate_exp = false;
+    validation
+}
+
+// Access tokens must come from our issuer. Without an expected audience any
+// audience is accepted, for callers that work with every kind of token.
+fn access_token_validation(audience: Option<&str>) -> Validation {
+    let mut validation = validation();
+    validation.set_issuer(&[JWT_ISSUER.as_str()]);
+    validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud"]);
+    match audience {
+        Some(audience) => validation.set_audience(&[audience]),
+        None => validation.validate_aud = false,
+    }
+    validation
+}
+
+// `JWT_LEEWAY_SECONDS` allows for clocks of other servers being a little off
+// from ours when they mint or check tokens
+fn check_expiry(exp: usize, clock: &dyn Clock) -> Result<(), ValidateTokenError> {
+    if !is_expired(exp, *JWT_LEEWAY_SECONDS, clock) {
+        return Ok(());
+    }
+    Err(ValidateTokenError::TokenError(
+        ErrorKind::ExpiredSignature.into(),
+    ))
+}
+
+fn check_not_before(nbf: usize, clock: &dyn Clock) -> Result<(), ValidateTokenError> {
+    if !is_immature(nbf, *JWT_LEEWAY_SECONDS, clock) {
+        return Ok(());
+    }
+    Err(ValidateTokenError::TokenError(
+        ErrorKind::ImmatureSignature.into(),
+    ))
+}
+
+fn is_expired(exp: usize, leeway: i64, clock: &dyn Clock) -> bool {
+    exp as i64 + leeway <= clock.now().timestamp()
+}
+
+fn is_immature(nbf: usize, leeway: i64, clock: &dyn Clock) -> bool {
+    nbf as i64 > clock.now().timestamp() + leeway
 }
 
 // Create JWT auth token by encoding claims using the JWT secret
@@ -103,20 +580,120 @@ fn create_token(claims: &Claims) -> Result<String, jsonwebtoken::errors::Error>
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    pub exp: usize,
+    pub iat: usize,
+    pub nbf: usize,
+    pub jti: String,
+    pub email: String,
+    pub session_version: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    // The login session an OAuth access token was issued from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    #[serde(flatten)]
+    pub authentication: Authentication,
+}
+
+impl Claims {
+    // Banned tokens only need to be remembered until this point
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.exp as i64, 0).unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+
+    pub fn issued_at(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.iat as i64, 0).unwrap_or(DateTime::<Utc>::MIN_UTC)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailChangeClaims {
     pub sub: String,
+    pub current_email: String,
+    pub new_email: String,
+    // Of the user when the link was sent, so logging out everywhere also
+    // voids links not yet opened
+    pub session_version: u64,
     pub exp: usize,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthorizationCodeClaims {
+    #[serde(flatten)]
+    pub grant: AuthorizationGrant,
+    pub exp: usize,
+    pub jti: String,
+}
+
+impl AuthorizationCodeClaims {
+    // Redeemed codes only need to be remembered until this point
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.exp as i64, 0).unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    pub exp: usize,
+    pub iat: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(flatten)]
+    pub authentication: Authentication,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    pub exp: usize,
+    pub iat: usize,
+    pub nbf: usize,
+    pub jti: String,
+    pub client_id: String,
+    pub grant_type: String,
+    pub scope: String,
+}
+
+impl ClientTokenClaims {
+    // Revoked tokens only need to be remembered until this point
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.exp as i64, 0).unwrap_or(DateTime::<Utc>::MAX_UTC)
+    }
+
+    pub fn has_scope(&self, wanted: &str) -> bool {
+        self.scope.split(' ').any(|scope| scope == wanted)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore;
+    use crate::utils::clock::{FakeClock, SystemClock};
+    use chrono::SubsecRound;
 
     use super::*;
 
     #[tokio::test]
     async fn test_generate_auth_cookie() {
         let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let cookie = generate_auth_cookie(&email).unwrap();
+        let cookie = generate_auth_cookie(
+            &UserId::default(),
+            &email,
+            0,
+            &Authentication::password(&SystemClock),
+            &SystemClock,
+        )
+        .unwrap();
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
         assert_eq!(cookie.value().split('.').count(), 3);
         assert_eq!(cookie.path(), Some("/"));
@@ -124,6 +701,35 @@ mod tests {
         assert_eq!(cookie.same_site(), Some(SameSite::Lax));
     }
 
+    #[tokio::test]
+    async fn test_generate_session_cookie_returns_the_claims_of_its_token() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let clock = FakeClock::default();
+        let (cookie, claims) = generate_session_cookie(
+            &UserId::default(),
+            &email,
+            0,
+            &Authentication::password(&clock),
+            &clock,
+        )
+        .unwrap();
+
+        let validated = validate_token(
+            cookie.value(),
+            Some(&SESSION_AUDIENCE),
+            Arc::new(HashsetBannedTokenStore::default()),
+            &clock,
+        )
+        .await
+        .unwrap();
+        assert_eq!(validated.jti, claims.jti);
+        assert_eq!(claims.issued_at(), clock.now().trunc_subsecs(0));
+        assert_eq!(
+            claims.expires_at() - claims.issued_at(),
+            chrono::Duration::seconds(TOKEN_TTL_SECONDS)
+        );
+    }
+
     #[tokio::test]
     async fn test_create_auth_cookie() {
         let token = "test_token".to_owned();
@@ -138,22 +744,36 @@ mod tests {
     #[tokio::test]
     async fn test_generate_auth_token() {
         let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let result = generate_auth_token(&email).unwrap();
+        let result = generate_auth_token(
+            &UserId::default(),
+            &email,
+            0,
+            &Authentication::password(&SystemClock),
+            &SystemClock,
+        )
+        .unwrap();
         assert_eq!(result.split('.').count(), 3);
     }
 
     #[tokio::test]
     async fn test_validate_token_with_valid_token() {
         let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let token = generate_auth_token(&email).unwrap();
-        let mut banned_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let user_id = UserId::default();
+        let token = generate_auth_token(
+            &user_id,
+            &email,
+            0,
+            &Authentication::password(&SystemClock),
+            &SystemClock,
+        )
+        .unwrap();
+        let banned_store = Arc::new(HashsetBannedTokenStore::default());
 
-        {
-            let mut gaurd = banned_store.write().await;
-            gaurd.add_banned_token(token.clone());
-        }
-        let result = validate_token(&token, banned_store).await.unwrap();
-        assert_eq!(result.sub, "test@example.com");
+        let result = validate_token(&token, Some(&SESSION_AUDIENCE), banned_store, &SystemClock)
+            .await
+            .unwrap();
+        assert_eq!(result.sub, user_id.to_string());
+        assert_eq!(result.email, "test@example.com");
 
         let exp = Utc::now()
             .checked_add_signed(chrono::Duration::try_minutes(9).expect("valid duration"))
@@ -165,16 +785,359 @@ mod tests {
 
     #[tokio::test]
     async fn test_validate_token_with_invalid_token() {
-        let mut banned_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
-
-        {
-            let mut gaurd = banned_store.write().await;
-            gaurd.add_banned_token("foobar".to_string());
-        }
+        let banned_store = Arc::new(HashsetBannedTokenStore::default());
+        let _ = banned_store
+            .add_banned_token("foobar".to_string(), Utc::now())
+            .await;
 
         let token = "foobar".to_owned();
-        let result1 = validate_token(&token, banned_store).await;
+        let result1 =
+            validate_token(&token, Some(&SESSION_AUDIENCE), banned_store, &SystemClock).await;
 
         assert!(result1.is_err());
     }
+
+    #[tokio::test]
+    async fn test_email_change_token_round_trip() {
+        let user_id = UserId::default();
+        let current = Email::parse("old@example.com".to_owned()).unwrap();
+        let new = Email::parse("new@example.com".to_owned()).unwrap();
+
+        let token = generate_email_change_token(&user_id, &current, &new, 3, &SystemClock).unwrap();
+        let claims = validate_email_change_token(&token, &SystemClock).unwrap();
+
+        assert_eq!(claims.sub, user_id.to_string());
+        assert_eq!(claims.current_email, "old@example.com");
+        assert_eq!(claims.new_email, "new@example.com");
+        assert_eq!(claims.session_version, 3);
+    }
+
+    #[tokio::test]
+    async fn test_auth_and_email_change_tokens_are_not_interchangeable() {
+        let user_id = UserId::default();
+        let email = Email::parse("old@example.com".to_owned()).unwrap();
+        let banned_store = Arc::new(HashsetBannedTokenStore::default());
+
+        let auth_token = generate_auth_token(
+            &user_id,
+            &email,
+            0,
+            &Authentication::password(&SystemClock),
+            &SystemClock,
+        )
+        .unwrap();
+        assert!(validate_email_change_token(&auth_token, &SystemClock).is_err());
+
+        let change_token =
+            generate_email_change_token(&user_id, &email, &email, 0, &SystemClock).unwrap();
+        assert!(validate_token(
+            &change_token,
+            Some(&SESSION_AUDIENCE),
+            banned_store,
+            &SystemClock
+        )
+        .await
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tokens_expire_by_the_clock() {
+        let clock = FakeClock::default();
+        let user_id = UserId::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let banned_store = Arc::new(HashsetBannedTokenStore::default());
+
+        let token = generate_auth_token(
+            &user_id,
+            &email,
+            0,
+            &Authentication::password(&clock),
+            &clock,
+        )
+        .unwrap();
+        let change_token =
+            generate_email_change_token(&user_id, &email, &email, 0, &clock).unwrap();
+
+        clock.advance(chrono::Duration::seconds(TOKEN_TTL_SECONDS - 1));
+        assert!(validate_token(
+            &token,
+            Some(&SESSION_AUDIENCE),
+            banned_store.clone(),
+            &clock
+        )
+        .await
+        .is_ok());
+
+        clock.advance(chrono::Duration::seconds(1));
+        let expired = validate_token(&token, Some(&SESSION_AUDIENCE), banned_store, &clock).await;
+        assert!(matches!(
+            expired,
+            Err(ValidateTokenError::TokenError(e)) if e.kind() == &ErrorKind::ExpiredSignature
+        ));
+
+        // Confirmation links last longer than sessions
+        assert!(validate_email_change_token(&change_token, &clock).is_ok());
+        clock.advance(chrono::Duration::seconds(EMAIL_CHANGE_TTL_SECONDS));
+        assert!(validate_email_change_token(&change_token, &clock).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_authorization_codes_are_not_auth_tokens() {
+        let clock = FakeClock::default();
+        let user_id = UserId::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let banned_store = Arc::new(HashsetBannedTokenStore::default());
+        let grant = AuthorizationGrant {
+            sub: user_id.to_string(),
+            session_version: 0,
+            sid: "session".to_owned(),
+            client_id: "spa".to_owned(),
+            redirect_uri: "https://app.example.com/callback".to_owned(),
+            code_challenge: "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM".to_owned(),
+            scope: None,
+            nonce: None,
+            authentication: Authentication::password(&clock),
+        };
+
+        let code = generate_authorization_code(grant.clone(), &clock).unwrap();
+        assert_eq!(
+            validate_authorization_code(&code, &clock).unwrap().grant,
+            grant
+        );
+        assert!(
+            validate_token(&code, Some(&SESSION_AUDIENCE), banned_store, &clock)
+                .await
+                .is_err()
+        );
+
+        let auth_token = generate_auth_token(
+            &user_id,
+            &email,
+            0,
+            &Authentication::password(&clock),
+            &clock,
+        )
+        .unwrap();
+        assert!(validate_authorization_code(&auth_token, &clock).is_err());
+
+        clock.advance(chrono::Duration::seconds(AUTHORIZATION_CODE_TTL_SECONDS));
+        assert!(validate_authorization_code(&code, &clock).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_client_tokens_are_not_interchangeable_with_user_tokens() {
+        let clock = FakeClock::default();
+        let user_id = UserId::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let banned_store = Arc::new(HashsetBannedTokenStore::default());
+
+        let client_token = generate_client_token("reports", "introspect admin", &clock).unwrap();
+        let claims = validate_client_token(&client_token, banned_store.clone(), &clock)
+            .await
+            .unwrap();
+        assert_eq!(claims.client_id, "reports");
+        assert!(claims.has_scope("admin"));
+        assert!(!claims.has_scope("intro"));
+        assert!(validate_token(
+            &client_token,
+            Some(&SESSION_AUDIENCE),
+            banned_store.clone(),
+            &clock
+        )
+        .await
+        .is_err());
+
+        // A user can ask /oauth/authorize for any scope, the code still isn't
+        // a client token
+        let grant = AuthorizationGrant {
+            sub: user_id.to_string(),
+            session_version: 0,
+            sid: "session".to_owned(),
+            client_id: "reports".to_owned(),
+            redirect_uri: "https://app.example.com/callback".to_owned(),
+            code_challenge: "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM".to_owned(),
+            scope: Some("admin".to_owned()),
+            nonce: None,
+            authentication: Authentication::password(&clock),
+        };
+        let code = generate_authorization_code(grant, &clock).unwrap();
+        let auth_token = generate_auth_token(
+            &user_id,
+            &email,
+            0,
+            &Authentication::password(&clock),
+            &clock,
+        )
+        .unwrap();
+        for token in [code, auth_token] {
+            assert!(validate_client_token(&token, banned_store.clone(), &clock)
+                .await
+                .is_err());
+        }
+
+        clock.advance(chrono::Duration::seconds(CLIENT_TOKEN_TTL_SECONDS));
+        assert!(validate_client_token(&client_token, banned_store, &clock)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_access_tokens_are_only_good_for_their_audience() {
+        let user_id = UserId::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let authentication = Authentication::password(&SystemClock);
+        let banned_store = Arc::new(HashsetBannedTokenStore::default());
+
+        let session =
+            generate_auth_token(&user_id, &email, 0, &authentication, &SystemClock).unwrap();
+        let grant = AuthorizationGrant {
+            sub: user_id.to_string(),
+            session_version: 0,
+            sid: "session".to_owned(),
+            client_id: "spa".to_owned(),
+            redirect_uri: "https://app.example.com/callback".to_owned(),
+            code_challenge: "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM".to_owned(),
+            scope: None,
+            nonce: None,
+            authentication: authentication.clone(),
+        };
+        let access = generate_access_token(&user_id, &email, 0, &grant, &SystemClock).unwrap();
+
+        let claims = validate_token(&access, Some("spa"), banned_store.clone(), &SystemClock)
+            .await
+            .unwrap();
+        assert_eq!(claims.aud, "spa");
+        assert_eq!(claims.iss, *JWT_ISSUER);
+        assert!(validate_token(
+            &access,
+            Some(&SESSION_AUDIENCE),
+            banned_store.clone(),
+            &SystemClock
+        )
+        .await
+        .is_err());
+        assert!(
+            validate_token(&session, Some("spa"), banned_store.clone(), &SystemClock)
+                .await
+                .is_err()
+        );
+
+        // Callers that take any kind of token still check the issuer
+        assert!(
+            validate_token(&access, None, banned_store.clone(), &SystemClock)
+                .await
+                .is_ok()
+        );
+        let foreign = Claims {
+            iss: "https://someone-else.example.com".to_owned(),
+            ..auth_claims(&user_id, &email, 0, &authentication, &SystemClock).unwrap()
+        };
+        let foreign = create_token(&foreign).unwrap();
+        assert!(validate_token(&foreign, None, banned_store, &SystemClock)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_every_token_gets_its_own_id() {
+        let user_id = UserId::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let authentication = Authentication::password(&SystemClock);
+        let banned_store = Arc::new(HashsetBannedTokenStore::default());
+
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let token =
+                generate_auth_token(&user_id, &email, 0, &authentication, &SystemClock).unwrap();
+            let claims = validate_token(&token, None, banned_store.clone(), &SystemClock)
+                .await
+                .unwrap();
+            assert_eq!(claims.nbf, claims.iat);
+            ids.push(claims.jti);
+        }
+        assert_ne!(ids[0], ids[1]);
+    }
+
+    #[tokio::test]
+    async fn test_banning_a_jti_only_rejects_that_token() {
+        let clock = FakeClock::default();
+        let user_id = UserId::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let authentication = Authentication::password(&clock);
+        let banned_store =
+            Arc::new(HashsetBannedTokenStore::default().with_clock(Arc::new(clock.clone())));
+
+        let banned = generate_auth_token(&user_id, &email, 0, &authentication, &clock).unwrap();
+        let other = generate_auth_token(&user_id, &email, 0, &authentication, &clock).unwrap();
+        let client_token = generate_client_token("reports", "introspect", &clock).unwrap();
+
+        let claims = validate_token(&banned, None, banned_store.clone(), &clock)
+            .await
+            .unwrap();
+        let client_claims = validate_client_token(&client_token, banned_store.clone(), &clock)
+            .await
+            .unwrap();
+        let _ = banned_store
+            .add_banned_token(claims.jti.clone(), claims.expires_at())
+            .await;
+        let _ = banned_store
+            .add_banned_token(client_claims.jti.clone(), client_claims.expires_at())
+            .await;
+
+        assert!(matches!(
+            validate_token(&banned, None, banned_store.clone(), &clock).await,
+            Err(ValidateTokenError::Banned(_))
+        ));
+        assert!(
+            validate_client_token(&client_token, banned_store.clone(), &clock)
+                .await
+                .is_err()
+        );
+        assert!(validate_token(&other, None, banned_store, &clock)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_tokens_are_not_valid_before_they_were_issued() {
+        let clock = FakeClock::default();
+        let user_id = UserId::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let banned_store = Arc::new(HashsetBannedTokenStore::default());
+
+        clock.advance(chrono::Duration::seconds(60));
+        let token = generate_auth_token(
+            &user_id,
+            &email,
+            0,
+            &Authentication::password(&clock),
+            &clock,
+        )
+        .unwrap();
+        let client_token = generate_client_token("reports", "introspect", &clock).unwrap();
+        clock.advance(chrono::Duration::seconds(-60));
+
+        let immature = validate_token(&token, None, banned_store.clone(), &clock).await;
+        assert!(matches!(
+            immature,
+            Err(ValidateTokenError::TokenError(e)) if e.kind() == &ErrorKind::ImmatureSignature
+        ));
+        assert!(validate_client_token(&client_token, banned_store, &clock)
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_leeway_extends_both_ends_of_a_token_lifetime() {
+        let clock = FakeClock::default();
+        let now = clock.now().timestamp() as usize;
+
+        assert!(is_expired(now, 0, &clock));
+        assert!(!is_expired(now, 30, &clock));
+        assert!(is_expired(now - 30, 30, &clock));
+
+        assert!(is_immature(now + 1, 0, &clock));
+        assert!(!is_immature(now + 30, 30, &clock));
+        assert!(is_immature(now + 31, 30, &clock));
+    }
 }
@@ -0,0 +1,72 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+
+// Where the service gets the current time from, so anything that expires can
+// be tested by moving a fake clock instead of sleeping.
+pub trait Clock: Send + Sync + 'static {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+// Only moves when told to. Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct FakeClock {
+    now: Arc<Mutex<DateTime<Utc>>>,
+}
+
+impl FakeClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = now;
+    }
+}
+
+impl Default for FakeClock {
+    fn default() -> Self {
+        Self::new(Utc::now())
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fake_clock_only_moves_when_told() {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let clock = FakeClock::new(start);
+        assert_eq!(clock.now(), start);
+        assert_eq!(clock.now(), start);
+
+        let shared = clock.clone();
+        shared.advance(Duration::seconds(90));
+        assert_eq!(clock.now(), start + Duration::seconds(90));
+
+        clock.set(start);
+        assert_eq!(shared.now(), start);
+    }
+}
@@ -2,10 +2,34 @@ use dotenvy::dotenv;
 use lazy_static::lazy_static;
 use std::env as std_env;
 
+use super::signing_key::SigningKey;
+
 // Define a lazily evaluated static. lazy_static is needed because std_env::var is not a const function.
 lazy_static! {
     pub static ref JWT_SECRET: String = set_token();
     pub static ref DATABASE_URL: String = set_db_url();
+    pub static ref AUTH_SERVICE_URL: String = set_auth_service_url();
+    pub static ref JWT_ISSUER: String =
+        set_optional(env::JWT_ISSUER_ENV_VAR).unwrap_or(AUTH_SERVICE_URL.clone());
+    pub static ref SESSION_AUDIENCE: String =
+        set_optional(env::SESSION_AUDIENCE_ENV_VAR).unwrap_or("app-service".to_owned());
+    pub static ref JWT_LEEWAY_SECONDS: i64 = set_parsed(env::JWT_LEEWAY_SECONDS_ENV_VAR, 0);
+    pub static ref SESSION_VERSION_CACHE_SECONDS: u64 =
+        set_parsed(env::SESSION_VERSION_CACHE_SECONDS_ENV_VAR, 5);
+    pub static ref ID_TOKEN_SIGNING_KEY: SigningKey = set_id_token_signing_key();
+    pub static ref PASSWORD_MIN_LENGTH: usize = set_parsed(env::PASSWORD_MIN_LENGTH_ENV_VAR, 8);
+    pub static ref PASSWORD_MAX_LENGTH: usize = set_parsed(env::PASSWORD_MAX_LENGTH_ENV_VAR, 128);
+    pub static ref PASSWORD_MIN_STRENGTH: u8 = set_parsed(env::PASSWORD_MIN_STRENGTH_ENV_VAR, 0);
+    pub static ref BREACHED_PASSWORDS_FILE: Option<String> =
+        set_optional(env::BREACHED_PASSWORDS_FILE_ENV_VAR);
+    pub static ref ARGON2_MEMORY_KIB: u32 = set_parsed(env::ARGON2_MEMORY_KIB_ENV_VAR, 15000);
+    pub static ref ARGON2_ITERATIONS: u32 = set_parsed(env::ARGON2_ITERATIONS_ENV_VAR, 2);
+    pub static ref ARGON2_PARALLELISM: u32 = set_parsed(env::ARGON2_PARALLELISM_ENV_VAR, 1);
+    pub static ref HASHING_WORKERS: usize = set_parsed(
+        env::HASHING_WORKERS_ENV_VAR,
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    );
+    pub static ref HASHING_QUEUE_LIMIT: usize = set_parsed(env::HASHING_QUEUE_LIMIT_ENV_VAR, 32);
 }
 
 fn set_token() -> String {
@@ -26,9 +50,57 @@ fn set_db_url() -> String {
     db_url
 }
 
+fn set_auth_service_url() -> String {
+    // Used to build links we email to users, so fall back to the local dev address
+    set_optional(env::AUTH_SERVICE_URL_ENV_VAR).unwrap_or("http://localhost:3000".to_owned())
+}
+
+fn set_id_token_signing_key() -> SigningKey {
+    // Without a configured key every restart invalidates issued ID tokens,
+    // and instances behind a load balancer won't agree on the key
+    match set_optional(env::ID_TOKEN_SIGNING_KEY_FILE_ENV_VAR) {
+        Some(path) => {
+            let pem = std::fs::read_to_string(&path)
+                .unwrap_or_else(|e| panic!("Failed to read {}: {}", path, e));
+            SigningKey::from_pkcs8_pem(&pem)
+                .unwrap_or_else(|e| panic!("{} is not a valid signing key: {}", path, e))
+        }
+        None => SigningKey::generate(),
+    }
+}
+
+fn set_optional(name: &str) -> Option<String> {
+    dotenv().ok(); // Load environment variables
+    std_env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn set_parsed<T: std::str::FromStr>(name: &str, default: T) -> T {
+    match set_optional(name) {
+        Some(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} is not a valid value for {}.", value, name)),
+        None => default,
+    }
+}
+
 pub mod env {
     pub const JWT_SECRET_ENV_VAR: &str = "JWT_SECRET";
     pub const DATABASE_URL_ENV_VAR: &str = "DATABASE_URL";
+    pub const AUTH_SERVICE_URL_ENV_VAR: &str = "AUTH_SERVICE_URL";
+    pub const JWT_ISSUER_ENV_VAR: &str = "JWT_ISSUER";
+    pub const SESSION_AUDIENCE_ENV_VAR: &str = "SESSION_AUDIENCE";
+    pub const JWT_LEEWAY_SECONDS_ENV_VAR: &str = "JWT_LEEWAY_SECONDS";
+    pub const SESSION_VERSION_CACHE_SECONDS_ENV_VAR: &str = "SESSION_VERSION_CACHE_SECONDS";
+    pub const ID_TOKEN_SIGNING_KEY_FILE_ENV_VAR: &str = "ID_TOKEN_SIGNING_KEY_FILE";
+    pub const PASSWORD_MIN_LENGTH_ENV_VAR: &str = "PASSWORD_MIN_LENGTH";
+    pub const PASSWORD_MAX_LENGTH_ENV_VAR: &str = "PASSWORD_MAX_LENGTH";
+    pub const PASSWORD_MIN_STRENGTH_ENV_VAR: &str = "PASSWORD_MIN_STRENGTH";
+    pub const BREACHED_PASSWORDS_FILE_ENV_VAR: &str = "BREACHED_PASSWORDS_FILE";
+    pub const ARGON2_MEMORY_KIB_ENV_VAR: &str = "ARGON2_MEMORY_KIB";
+    pub const ARGON2_ITERATIONS_ENV_VAR: &str = "ARGON2_ITERATIONS";
+    pub const ARGON2_PARALLELISM_ENV_VAR: &str = "ARGON2_PARALLELISM";
+    pub const HASHING_WORKERS_ENV_VAR: &str = "HASHING_WORKERS";
+    pub const HASHING_QUEUE_LIMIT_ENV_VAR: &str = "HASHING_QUEUE_LIMIT";
 }
 
 pub const JWT_COOKIE_NAME: &str = "jwt";
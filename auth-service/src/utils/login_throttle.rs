@@ -0,0 +1,194 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+
+use crate::domains::email::Email;
+
+// Failed logins allowed for one email within the window before it's locked out
+pub const MAX_FAILED_LOGINS: u32 = 5;
+pub const FAILED_LOGIN_WINDOW_SECONDS: i64 = 900; // 15 minutes
+pub const LOGIN_LOCKOUT_SECONDS: i64 = 900; // 15 minutes
+
+struct FailedLogins {
+    count: u32,
+    window_ends_at: DateTime<Utc>,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+// Counts failed logins per email. Unknown emails are counted the same way, so
+// being locked out doesn't tell whether an account exists. Callers pass in the
+// time from the app's clock. Clones share the same counts.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    max_failures: u32,
+    window: Duration,
+    lockout: Duration,
+    failures: Arc<DashMap<Email, FailedLogins>>,
+}
+
+impl Default for LoginThrottle {
+    fn default() -> Self {
+        Self::new(
+            MAX_FAILED_LOGINS,
+            Duration::seconds(FAILED_LOGIN_WINDOW_SECONDS),
+            Duration::seconds(LOGIN_LOCKOUT_SECONDS),
+        )
+    }
+}
+
+impl LoginThrottle {
+    pub fn new(max_failures: u32, window: Duration, lockout: Duration) -> Self {
+        Self {
+            max_failures,
+            window,
+            lockout,
+            failures: Arc::default(),
+        }
+    }
+
+    // How long until the email may try again, if it's locked out
+    pub fn locked_for(&self, email: &Email, now: DateTime<Utc>) -> Option<Duration> {
+        let failures = self.failures.get(email)?;
+        failures
+            .locked_until
+            .filter(|locked_until| *locked_until > now)
+            .map(|locked_until| locked_until - now)
+    }
+
+    pub fn record_failure(&self, email: Email, now: DateTime<Utc>) {
+        let fresh = || FailedLogins {
+            count: 0,
+            window_ends_at: now + self.window,
+            locked_until: None,
+        };
+        let mut failures = self.failures.entry(email).or_insert_with(fresh);
+
+        // A lockout that ran out starts the count over, like a window that did
+        let lockout_over = failures
+            .locked_until
+            .is_some_and(|locked_until| locked_until <= now);
+        if failures.window_ends_at <= now || lockout_over {
+            *failures = fresh();
+        }
+
+        failures.count += 1;
+        if failures.count >= self.max_failures {
+            failures.locked_until = Some(now + self.lockout);
+        }
+    }
+
+    pub fn record_success(&self, email: &Email) {
+        self.failures.remove(email);
+    }
+
+    // Drops counts whose window and lockout are both over. Returns how many
+    // were removed.
+    pub fn remove_expired(&self, now: DateTime<Utc>) -> usize {
+        let mut removed = 0;
+        self.failures.retain(|_, failures| {
+            let keep = failures.window_ends_at > now
+                || failures
+                    .locked_until
+                    .is_some_and(|locked_until| locked_until > now);
+            removed += usize::from(!keep);
+            keep
+        });
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::clock::{Clock, FakeClock};
+
+    fn email(email: &str) -> Email {
+        Email::parse(email.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_locks_out_after_max_failures_until_lockout_ends() {
+        let clock = FakeClock::default();
+        let throttle = LoginThrottle::default();
+        let ravi = email("ravi@gmail.com");
+
+        for _ in 0..MAX_FAILED_LOGINS - 1 {
+            throttle.record_failure(ravi.clone(), clock.now());
+            assert_eq!(throttle.locked_for(&ravi, clock.now()), None);
+        }
+        throttle.record_failure(ravi.clone(), clock.now());
+        assert_eq!(
+            throttle.locked_for(&ravi, clock.now()),
+            Some(Duration::seconds(LOGIN_LOCKOUT_SECONDS))
+        );
+        // Other emails aren't affected
+        assert_eq!(
+            throttle.locked_for(&email("other@gmail.com"), clock.now()),
+            None
+        );
+
+        clock.advance(Duration::seconds(LOGIN_LOCKOUT_SECONDS - 1));
+        assert_eq!(
+            throttle.locked_for(&ravi, clock.now()),
+            Some(Duration::seconds(1))
+        );
+
+        // Once it's over a single failure doesn't lock the email again
+        clock.advance(Duration::seconds(1));
+        assert_eq!(throttle.locked_for(&ravi, clock.now()), None);
+        throttle.record_failure(ravi.clone(), clock.now());
+        assert_eq!(throttle.locked_for(&ravi, clock.now()), None);
+    }
+
+    #[test]
+    fn test_failures_outside_the_window_are_forgotten() {
+        let clock = FakeClock::default();
+        let throttle = LoginThrottle::default();
+        let ravi = email("ravi@gmail.com");
+
+        for _ in 0..MAX_FAILED_LOGINS - 1 {
+            throttle.record_failure(ravi.clone(), clock.now());
+        }
+        clock.advance(Duration::seconds(FAILED_LOGIN_WINDOW_SECONDS));
+        throttle.record_failure(ravi.clone(), clock.now());
+        assert_eq!(throttle.locked_for(&ravi, clock.now()), None);
+    }
+
+    #[test]
+    fn test_success_clears_the_count() {
+        let clock = FakeClock::default();
+        let throttle = LoginThrottle::default();
+        let ravi = email("ravi@gmail.com");
+
+        for _ in 0..MAX_FAILED_LOGINS - 1 {
+            throttle.record_failure(ravi.clone(), clock.now());
+        }
+        throttle.record_success(&ravi);
+        throttle.record_failure(ravi.clone(), clock.now());
+        assert_eq!(throttle.locked_for(&ravi, clock.now()), None);
+    }
+
+    #[test]
+    fn test_remove_expired_keeps_open_windows_and_lockouts() {
+        let clock = FakeClock::default();
+        let throttle = LoginThrottle::new(2, Duration::seconds(60), Duration::seconds(600));
+        let locked = email("locked@gmail.com");
+        let counted = email("counted@gmail.com");
+
+        throttle.record_failure(locked.clone(), clock.now());
+        throttle.record_failure(locked.clone(), clock.now());
+        throttle.record_failure(counted.clone(), clock.now());
+
+        clock.advance(Duration::seconds(59));
+        assert_eq!(throttle.remove_expired(clock.now()), 0);
+
+        clock.advance(Duration::seconds(1));
+        assert_eq!(throttle.remove_expired(clock.now()), 1);
+        assert!(throttle.locked_for(&locked, clock.now()).is_some());
+
+        clock.advance(Duration::seconds(540));
+        assert_eq!(throttle.remove_expired(clock.now()), 1);
+        assert!(throttle.failures.is_empty());
+    }
+}
@@ -1,2 +1,6 @@
 pub mod auth;
+pub mod clock;
 pub mod constants;
+pub mod login_throttle;
+pub mod password_hashing;
+pub mod signing_key;
@@ -0,0 +1,567 @@
+use std::error::Error;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use argon2::{
+    password_hash::{PasswordHash as PhcHash, SaltString},
+    Algorithm, Argon2, Params, PasswordHasher, PasswordVerifier, Version,
+};
+use pbkdf2::Pbkdf2;
+use tokio::sync::OnceCell;
+use zeroize::Zeroizing;
+
+use crate::domains::password_hash::PasswordHash;
+
+// Argon2id cost parameters used for new hashes. Changing them doesn't strand
+// existing users: hashes made with other parameters are upgraded on login.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HashingParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for HashingParams {
+    fn default() -> Self {
+        HashingParams {
+            memory_kib: 15000,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl HashingParams {
+    fn argon2(&self) -> Result<Argon2<'static>, argon2::Error> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, None)?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+#[derive(Debug, PartialEq)]
+pub enum HashingError {
+    // Every worker is busy and the queue is full
+    Saturated,
+    // The worker running the job went away before finishing it
+    WorkerFailed,
+    // The hash couldn't be computed, e.g. because of invalid parameters
+    HashFailed,
+}
+
+// Argon2 is deliberately slow, so hashing runs on a fixed set of dedicated threads
+// instead of the async executor. Jobs beyond the queue limit are rejected straight
+// away so callers can shed load rather than pile up behind a burst of logins.
+#[derive(Clone)]
+pub struct HashingPool {
+    sender: Sender<Job>,
+    // Jobs running or waiting, capped at `capacity`
+    in_flight: Arc<AtomicUsize>,
+    capacity: usize,
+}
+
+impl HashingPool {
+    pub fn new(workers: usize, queue_limit: usize) -> Self {
+        let workers = workers.max(1);
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for i in 0..workers {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("password-hashing-{}", i))
+                .spawn(move || worker_loop(receiver))
+                .expect("Failed to spawn password hashing worker");
+        }
+
+        HashingPool {
+            sender,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            capacity: workers + queue_limit,
+        }
+    }
+
+    // Runs `job` on a worker, or fails with `Saturated` if there's no room for it
+    pub async fn run<F, R>(&self, job: F) -> Result<R, HashingError>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let slot = self.reserve_slot()?;
+        let (result_sender, result_receiver) = tokio::sync::oneshot::channel();
+        let job: Job = Box::new(move || {
+            let result = job();
+            // Free the slot before handing back the result so the caller can
+            // immediately submit more work
+            drop(slot);
+            let _ = result_sender.send(result);
+        });
+
+        self.sender
+            .send(job)
+            .map_err(|_| HashingError::WorkerFailed)?;
+
+        result_receiver
+            .await
+            .map_err(|_| HashingError::WorkerFailed)
+    }
+
+    fn reserve_slot(&self) -> Result<Slot, HashingError> {
+        self.in_flight
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                (n < self.capacity).then_some(n + 1)
+            })
+            .map_err(|_| HashingError::Saturated)?;
+
+        Ok(Slot(self.in_flight.clone()))
+    }
+
+    // For tests: takes up a worker until the returned handle is released or
+    // dropped, so they can fill the pool on purpose. Returns once the worker
+    // is held.
+    pub async fn park_worker(&self) -> Result<ParkedWorker, HashingError> {
+        let (parked_sender, parked_receiver) = tokio::sync::oneshot::channel();
+        let (release_sender, release_receiver) = mpsc::channel::<()>();
+        let pool = self.clone();
+        let job = tokio::spawn(async move {
+            pool.run(move || {
+                let _ = parked_sender.send(());
+                // Also let go when the handle is dropped
+                let _ = release_receiver.recv();
+            })
+            .await
+        });
+
+        if parked_receiver.await.is_err() {
+            return match job.await {
+                Ok(Err(e)) => Err(e),
+                _ => Err(HashingError::WorkerFailed),
+            };
+        }
+        Ok(ParkedWorker {
+            release: release_sender,
+            job,
+        })
+    }
+}
+
+pub struct ParkedWorker {
+    release: Sender<()>,
+    job: tokio::task::JoinHandle<Result<(), HashingError>>,
+}
+
+impl ParkedWorker {
+    // Lets the worker go and waits until it's free again
+    pub async fn release(self) -> Result<(), HashingError> {
+        let _ = self.release.send(());
+        self.job.await.map_err(|_| HashingError::WorkerFailed)?
+    }
+}
+
+// Gives its place in the pool back when dropped, even if the job panics
+struct Slot(Arc<AtomicUsize>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl Default for HashingPool {
+    fn default() -> Self {
+        let workers = thread::available_parallelism().map_or(1, |n| n.get());
+        HashingPool::new(workers, 32)
+    }
+}
+
+fn worker_loop(receiver: Arc<Mutex<Receiver<Job>>>) {
+    loop {
+        // The lock is released as soon as a job is received
+        let job = match receiver.lock() {
+            Ok(receiver) => receiver.recv(),
+            Err(_) => return,
+        };
+
+        match job {
+            // A panicking job drops its result sender, which the caller sees as
+            // `WorkerFailed`. The worker itself keeps going.
+            Ok(job) => {
+                let _ = catch_unwind(AssertUnwindSafe(job));
+            }
+            // Every pool handle is gone
+            Err(_) => return,
+        }
+    }
+}
+
+// Hashes and verifies passwords on a `HashingPool` with one set of parameters.
+// The user stores share it so they handle credentials the same way.
+#[derive(Clone, Default)]
+pub struct CredentialHasher {
+    params: HashingParams,
+    pool: HashingPool,
+    // Checked against when there's no user to verify, so those requests take as
+    // long as a wrong password would. Made lazily with the current parameters.
+    // Users still on an imported bcrypt/PBKDF2/SHA-crypt hash or on older
+    // Argon2 parameters verify in a different time until their first login
+    // upgrades them, so for those accounts timing can still tell them apart
+    // from unknown emails.
+    dummy_password_hash: Arc<OnceCell<PasswordHash>>,
+}
+
+impl CredentialHasher {
+    pub fn new(params: HashingParams, pool: HashingPool) -> Self {
+        CredentialHasher {
+            params,
+            pool,
+            dummy_password_hash: Arc::new(OnceCell::new()),
+        }
+    }
+
+    pub fn params(&self) -> &HashingParams {
+        &self.params
+    }
+
+    pub fn pool(&self) -> &HashingPool {
+        &self.pool
+    }
+
+    pub async fn hash(&self, password: &str) -> Result<PasswordHash, HashingError> {
+        let password = Zeroizing::new(password.to_owned());
+        let params = self.params.clone();
+
+        let password_hash = self
+            .pool
+            .run(move || compute_password_hash(&password, &params))
+            .await?
+            .map_err(|_| HashingError::HashFailed)?;
+
+        PasswordHash::parse(password_hash).map_err(|_| HashingError::HashFailed)
+    }
+
+    pub async fn verify(
+        &self,
+        password_hash: &PasswordHash,
+        password: &str,
+    ) -> Result<bool, HashingError> {
+        let password_hash = password_hash.clone();
+        let password = Zeroizing::new(password.to_owned());
+
+        self.pool
+            .run(move || verify_password_hash(password_hash.as_ref(), &password).is_ok())
+            .await
+    }
+
+    // Does the same work as `verify` without a user to check against
+    pub async fn verify_dummy(&self, password: &str) -> Result<(), HashingError> {
+        let dummy_password_hash = self
+            .dummy_password_hash
+            .get_or_try_init(|| self.hash("not a real password"))
+            .await?;
+
+        self.verify(dummy_password_hash, password).await.map(|_| ())
+    }
+
+    pub fn needs_rehash(&self, password_hash: &PasswordHash) -> bool {
+        needs_rehash(password_hash.as_ref(), &self.params)
+    }
+}
+
+// CPU heavy, run it through a `HashingPool` from async code
+pub fn compute_password_hash(
+    password: &str,
+    params: &HashingParams,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let salt: SaltString = SaltString::generate(&mut rand::thread_rng());
+    let password_hash = params
+        .argon2()?
+        .hash_password(password.as_bytes(), &salt)?
+        .to_string();
+
+    Ok(password_hash)
+}
+
+// Formats we can verify. Anything other than Argon2 comes from user imports
+// and is replaced with an Argon2id hash the first time the user logs in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HashFormat {
+    // PHC strings: `$argon2id$v=19$m=...`
+    Argon2,
+    // PHC strings: `$pbkdf2-sha256$i=...,l=...$salt$hash`
+    Pbkdf2,
+    // Modular crypt: `$2a$`, `$2b$`, `$2x$` or `$2y$`
+    Bcrypt,
+    // Modular crypt: `$5$` (SHA-256) or `$6$` (SHA-512)
+    ShaCrypt,
+}
+
+impl HashFormat {
+    pub fn detect(password_hash: &str) -> Option<Self> {
+        let id = password_hash.strip_prefix('$')?.split('$').next()?;
+        match id {
+            "argon2id" | "argon2i" | "argon2d" => Some(HashFormat::Argon2),
+            "pbkdf2" | "pbkdf2-sha256" | "pbkdf2-sha512" => Some(HashFormat::Pbkdf2),
+            "2a" | "2b" | "2x" | "2y" => Some(HashFormat::Bcrypt),
+            "5" | "6" => Some(HashFormat::ShaCrypt),
+            _ => None,
+        }
+    }
+}
+
+// Verifies against whichever format is stored. For PHC strings the algorithm
+// and parameters are read from the hash itself. CPU heavy like hashing.
+pub fn verify_password_hash(
+    expected_password_hash: &str,
+    password_candidate: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let format =
+        HashFormat::detect(expected_password_hash).ok_or("Unsupported password hash format")?;
+
+    match format {
+        HashFormat::Argon2 | HashFormat::Pbkdf2 => {
+            let expected_password_hash: PhcHash<'_> = PhcHash::new(expected_password_hash)?;
+
+            expected_password_hash
+                .verify_password(&[&Argon2::default(), &Pbkdf2], password_candidate)
+                .map_err(|e| e.into())
+        }
+        HashFormat::Bcrypt => {
+            if bcrypt::verify(password_candidate, expected_password_hash)? {
+                Ok(())
+            } else {
+                Err("Password does not match".into())
+            }
+        }
+        HashFormat::ShaCrypt => {
+            let result = if expected_password_hash.starts_with("$6$") {
+                sha_crypt::sha512_check(password_candidate, expected_password_hash)
+            } else {
+                sha_crypt::sha256_check(password_candidate, expected_password_hash)
+            };
+
+            result.map_err(|e| format!("{:?}", e).into())
+        }
+    }
+}
+
+// True when the stored hash wasn't made with Argon2id and exactly `params`
+pub fn needs_rehash(password_hash: &str, params: &HashingParams) -> bool {
+    let password_hash = match PhcHash::new(password_hash) {
+        Ok(h) => h,
+        Err(_) => return true,
+    };
+
+    if password_hash.algorithm != Algorithm::Argon2id.ident()
+        || password_hash.version != Some(Version::V0x13.into())
+    {
+        return true;
+    }
+
+    match Params::try_from(&password_hash) {
+        Ok(stored) => {
+            stored.m_cost() != params.memory_kib
+                || stored.t_cost() != params.iterations
+                || stored.p_cost() != params.parallelism
+        }
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pbkdf2::password_hash::PasswordHasher as _;
+
+    fn cheap_params() -> HashingParams {
+        HashingParams {
+            memory_kib: 4096,
+            iterations: 1,
+            parallelism: 1,
+        }
+    }
+
+    #[test]
+    fn hash_round_trip() {
+        let params = cheap_params();
+        let hash = compute_password_hash("password123", &params).unwrap();
+
+        assert!(verify_password_hash(&hash, "password123").is_ok());
+        assert!(verify_password_hash(&hash, "password124").is_err());
+    }
+
+    #[test]
+    fn hash_with_current_params_is_not_rehashed() {
+        let params = cheap_params();
+        let hash = compute_password_hash("password123", &params).unwrap();
+
+        assert!(!needs_rehash(&hash, &params));
+    }
+
+    #[test]
+    fn hash_with_outdated_params_is_rehashed() {
+        let old = cheap_params();
+        let hash = compute_password_hash("password123", &old).unwrap();
+
+        let new = HashingParams {
+            iterations: 2,
+            ..cheap_params()
+        };
+        assert!(needs_rehash(&hash, &new));
+
+        let new = HashingParams {
+            memory_kib: 8192,
+            ..cheap_params()
+        };
+        assert!(needs_rehash(&hash, &new));
+    }
+
+    #[test]
+    fn hash_with_other_algorithm_is_rehashed() {
+        let salt = SaltString::generate(&mut rand::thread_rng());
+        let hash = Argon2::new(
+            Algorithm::Argon2i,
+            Version::V0x13,
+            Params::new(4096, 1, 1, None).unwrap(),
+        )
+        .hash_password(b"password123", &salt)
+        .unwrap()
+        .to_string();
+
+        assert!(verify_password_hash(&hash, "password123").is_ok());
+        assert!(needs_rehash(&hash, &cheap_params()));
+    }
+
+    #[test]
+    fn detects_hash_formats() {
+        let cases = [
+            (
+                "$argon2id$v=19$m=4096,t=1,p=1$c2FsdA$aGFzaA",
+                Some(HashFormat::Argon2),
+            ),
+            (
+                "$pbkdf2-sha256$i=1000,l=32$c2FsdA$aGFzaA",
+                Some(HashFormat::Pbkdf2),
+            ),
+            ("$2b$04$abcdefghijklmnopqrstuu", Some(HashFormat::Bcrypt)),
+            ("$2y$04$abcdefghijklmnopqrstuu", Some(HashFormat::Bcrypt)),
+            ("$6$rounds=1000$salt$hash", Some(HashFormat::ShaCrypt)),
+            ("$5$salt$hash", Some(HashFormat::ShaCrypt)),
+            ("$1$salt$hash", None),
+            ("5f4dcc3b5aa765d61d8327deb882cf99", None),
+            ("", None),
+        ];
+
+        for (hash, expected) in cases {
+            assert_eq!(HashFormat::detect(hash), expected, "{}", hash);
+        }
+    }
+
+    #[test]
+    fn verifies_and_upgrades_bcrypt_hashes() {
+        let hash = bcrypt::hash("password123", 4).unwrap();
+
+        assert!(verify_password_hash(&hash, "password123").is_ok());
+        assert!(verify_password_hash(&hash, "password124").is_err());
+        assert!(needs_rehash(&hash, &cheap_params()));
+    }
+
+    #[test]
+    fn verifies_and_upgrades_pbkdf2_hashes() {
+        let salt = SaltString::generate(&mut rand::thread_rng());
+        let hash = Pbkdf2
+            .hash_password_customized(
+                b"password123",
+                Some(pbkdf2::Algorithm::Pbkdf2Sha256.ident()),
+                None,
+                pbkdf2::Params {
+                    rounds: 1000,
+                    output_length: 32,
+                },
+                &salt,
+            )
+            .unwrap()
+            .to_string();
+        assert!(hash.starts_with("$pbkdf2-sha256$"));
+
+        assert!(verify_password_hash(&hash, "password123").is_ok());
+        assert!(verify_password_hash(&hash, "password124").is_err());
+        assert!(needs_rehash(&hash, &cheap_params()));
+    }
+
+    #[test]
+    fn verifies_and_upgrades_sha_crypt_hashes() {
+        let sha512 =
+            sha_crypt::sha512_simple("password123", &sha_crypt::Sha512Params::new(1000).unwrap())
+                .unwrap();
+        let sha256 =
+            sha_crypt::sha256_simple("password123", &sha_crypt::Sha256Params::new(1000).unwrap())
+                .unwrap();
+
+        for hash in [sha512, sha256] {
+            assert!(verify_password_hash(&hash, "password123").is_ok());
+            assert!(verify_password_hash(&hash, "password124").is_err());
+            assert!(needs_rehash(&hash, &cheap_params()));
+        }
+    }
+
+    #[test]
+    fn rejects_unsupported_hashes() {
+        let md5 = "5f4dcc3b5aa765d61d8327deb882cf99";
+        assert!(verify_password_hash(md5, "password").is_err());
+    }
+
+    #[tokio::test]
+    async fn pool_runs_jobs_off_the_caller_thread() {
+        let pool = HashingPool::new(2, 4);
+        let caller = thread::current().id();
+
+        let worker = pool.run(|| thread::current().id()).await.unwrap();
+        assert_ne!(worker, caller);
+
+        let hash = pool
+            .run(|| compute_password_hash("password123", &cheap_params()))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(verify_password_hash(&hash, "password123").is_ok());
+    }
+
+    #[tokio::test]
+    async fn pool_rejects_jobs_when_saturated() {
+        let pool = HashingPool::new(1, 1);
+
+        // Park the only worker until we let it go
+        let parked = pool.park_worker().await.unwrap();
+
+        // One job fits in the queue behind it, the next one is turned away
+        let queued = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.run(|| 1).await }
+        });
+        while pool.in_flight.load(Ordering::Acquire) < 2 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(pool.run(|| 2).await, Err(HashingError::Saturated));
+        assert!(matches!(
+            pool.park_worker().await,
+            Err(HashingError::Saturated)
+        ));
+
+        assert_eq!(parked.release().await, Ok(()));
+        assert_eq!(queued.await.unwrap(), Ok(1));
+        assert_eq!(pool.run(|| 3).await, Ok(3));
+    }
+
+    #[tokio::test]
+    async fn pool_survives_panicking_jobs() {
+        let pool = HashingPool::new(1, 1);
+
+        let result = pool.run(|| panic!("boom")).await;
+        assert_eq!(result, Err::<(), _>(HashingError::WorkerFailed));
+        assert_eq!(pool.run(|| 1).await, Ok(1));
+    }
+}
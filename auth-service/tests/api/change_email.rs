@@ -0,0 +1,270 @@
+use auth_service::domains::email::Email;
+use auth_service::domains::user_id::UserId;
+use auth_service::routes::ChangeEmailResponse;
+use auth_service::utils::auth::validate_token;
+use auth_service::utils::constants::JWT_COOKIE_NAME;
+
+use crate::helpers::TestApp;
+
+// Signs up and logs in a user without 2FA, returning the session token
+async fn signup_and_login(app: &TestApp, email: &str) -> String {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false,
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+    token
+}
+
+#[tokio::test]
+async fn should_return_422_if_malformed_input() {
+    let app = TestApp::new().await;
+    signup_and_login(&app, &TestApp::get_random_email()).await;
+
+    let body = serde_json::json!({
+        "email": "new@example.com",
+    });
+
+    let response = app.post_change_email(&body).await;
+    assert_eq!(response.status().as_u16(), 422);
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let app = TestApp::new().await;
+
+    let body = serde_json::json!({
+        "newEmail": TestApp::get_random_email(),
+        "password": "password123",
+    });
+
+    let response = app.post_change_email(&body).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_return_400_if_invalid_new_email() {
+    let app = TestApp::new().await;
+    signup_and_login(&app, &TestApp::get_random_email()).await;
+
+    let body = serde_json::json!({
+        "newEmail": "not-an-email",
+        "password": "password123",
+    });
+
+    let response = app.post_change_email(&body).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_return_401_if_incorrect_password() {
+    let app = TestApp::new().await;
+    signup_and_login(&app, &TestApp::get_random_email()).await;
+
+    let body = serde_json::json!({
+        "newEmail": TestApp::get_random_email(),
+        "password": "wrongpassword",
+    });
+
+    let response = app.post_change_email(&body).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_409_if_new_email_taken() {
+    let app = TestApp::new().await;
+    let taken = TestApp::get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": taken,
+        "password": "password123",
+        "requires2FA": false,
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    signup_and_login(&app, &TestApp::get_random_email()).await;
+
+    let body = serde_json::json!({
+        "newEmail": taken,
+        "password": "password123",
+    });
+
+    let response = app.post_change_email(&body).await;
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn should_return_202_and_keep_email_until_confirmed() {
+    let app = TestApp::new().await;
+    let old_email = TestApp::get_random_email();
+    let new_email = TestApp::get_random_email();
+    signup_and_login(&app, &old_email).await;
+
+    let body = serde_json::json!({
+        "newEmail": new_email,
+        "password": "password123",
+    });
+
+    let response = app.post_change_email(&body).await;
+    assert_eq!(response.status().as_u16(), 202);
+    assert_eq!(
+        response
+            .json::<ChangeEmailResponse>()
+            .await
+            .expect("Could not deserialize response body to ChangeEmailResponse"),
+        ChangeEmailResponse {
+            message: "Confirmation email sent".to_owned()
+        }
+    );
+
+    // The new address gets the link, the current one a heads-up
+    let new_address = Email::parse(new_email.clone()).unwrap();
+    let old_address = Email::parse(old_email.clone()).unwrap();
+    let sent = app.email_client.sent_to(&new_address);
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].subject, "Confirm your new email address");
+    assert!(sent[0].content.contains("/confirm-email-change?token="));
+    let notice = app
+        .email_client
+        .last_sent_to(&old_address)
+        .expect("No notice sent to the current address");
+    assert_eq!(notice.subject, "Your email address is being changed");
+    assert!(notice.content.contains(new_address.as_ref()));
+
+    // Nothing changes until the new address is confirmed
+    let login_body = serde_json::json!({
+        "email": old_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_confirmation_token() {
+    let app = TestApp::new().await;
+
+    let response = app.get_confirm_email_change("invalid").await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_swap_email_and_revoke_sessions_after_confirmation() {
+    let app = TestApp::new().await;
+    let old_email = TestApp::get_random_email();
+    let new_email = TestApp::get_random_email();
+    let token = signup_and_login(&app, &old_email).await;
+
+    let claims = validate_token(&token, None, app.banned_token.clone(), &app.clock)
+        .await
+        .unwrap();
+    let user_id = UserId::parse(claims.sub).unwrap();
+
+    let body = serde_json::json!({
+        "newEmail": new_email,
+        "password": "password123",
+    });
+    let response = app.post_change_email(&body).await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    // Follow the link from the confirmation email
+    let confirmation_email = app
+        .email_client
+        .last_sent_to(&Email::parse(new_email.clone()).unwrap())
+        .expect("No confirmation email sent");
+    let (_, confirmation_token) = confirmation_email
+        .content
+        .split_once("confirm-email-change?token=")
+        .expect("No confirmation link in email");
+
+    let response = app.get_confirm_email_change(confirmation_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The link can't be replayed once the address has changed
+    let response = app.get_confirm_email_change(confirmation_token).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The old session is no longer accepted
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let login_body = serde_json::json!({
+        "email": old_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let login_body = serde_json::json!({
+        "email": new_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let new_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+    let claims = validate_token(&new_token, None, app.banned_token.clone(), &app.clock)
+        .await
+        .unwrap();
+    assert_eq!(claims.sub, user_id.to_string());
+}
+
+#[tokio::test]
+async fn should_void_pending_confirmation_links_on_logout_all() {
+    let app = TestApp::new().await;
+    let old_email = TestApp::get_random_email();
+    let new_email = TestApp::get_random_email();
+    signup_and_login(&app, &old_email).await;
+
+    let body = serde_json::json!({
+        "newEmail": new_email,
+        "password": "password123",
+    });
+    let response = app.post_change_email(&body).await;
+    assert_eq!(response.status().as_u16(), 202);
+    let confirmation_email = app
+        .email_client
+        .last_sent_to(&Email::parse(new_email.clone()).unwrap())
+        .expect("No confirmation email sent");
+    let (_, confirmation_token) = confirmation_email
+        .content
+        .split_once("confirm-email-change?token=")
+        .expect("No confirmation link in email");
+
+    // Someone else asked for the change, and the user ends every session
+    assert_eq!(app.post_logout_all().await.status().as_u16(), 200);
+
+    let response = app.get_confirm_email_change(confirmation_token).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let login_body = serde_json::json!({
+        "email": old_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
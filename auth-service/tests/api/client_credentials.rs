@@ -0,0 +1,186 @@
+use auth_service::domains::client_secret::ClientSecret;
+use auth_service::domains::data_stores::OAuthClientStore;
+use auth_service::domains::oauth_client::OAuthClient;
+use auth_service::routes::{ClientInfoResponse, TokenResponse};
+use auth_service::utils::auth::CLIENT_TOKEN_TTL_SECONDS;
+use auth_service::utils::constants::JWT_COOKIE_NAME;
+use chrono::Duration;
+use reqwest::header::WWW_AUTHENTICATE;
+
+use crate::helpers::TestApp;
+use crate::oauth::signup;
+
+pub const SERVICE_ID: &str = "reports";
+
+// Registers a service that may ask for `scopes`, returning its secret
+pub async fn register_service(app: &TestApp, scopes: &[&str]) -> ClientSecret {
+    let secret = ClientSecret::generate();
+    let client = OAuthClient::confidential(
+        SERVICE_ID.to_string(),
+        "Reporting service".to_string(),
+        secret.hash(),
+        scopes.iter().map(|scope| scope.to_string()).collect(),
+    )
+    .unwrap();
+    app.oauth_clients.add_client(client).await.unwrap();
+    secret
+}
+
+async fn post_client_credentials(
+    app: &TestApp,
+    secret: &str,
+    scope: Option<&str>,
+) -> reqwest::Response {
+    let mut form = vec![("grant_type", "client_credentials")];
+    if let Some(scope) = scope {
+        form.push(("scope", scope));
+    }
+
+    app.http_client
+        .post(format!("{}/oauth/token", &app.address))
+        .basic_auth(SERVICE_ID, Some(secret))
+        .form(&form)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+pub async fn client_token(app: &TestApp, secret: &ClientSecret, scope: Option<&str>) -> String {
+    let response = post_client_credentials(app, secret.as_ref(), scope).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.json::<TokenResponse>().await.unwrap().access_token
+}
+
+fn new_client() -> serde_json::Value {
+    serde_json::json!({
+        "clientId": "new-spa",
+        "name": "New SPA",
+        "redirectUris": ["https://new.example.com/callback"],
+    })
+}
+
+#[tokio::test]
+async fn should_issue_scoped_token_to_authenticated_client() {
+    let app = TestApp::new().await;
+    let secret = register_service(&app, &["introspect", "admin"]).await;
+
+    let response = post_client_credentials(&app, secret.as_ref(), None).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token = response.json::<TokenResponse>().await.unwrap();
+    assert_eq!(token.token_type, "Bearer");
+    assert_eq!(token.expires_in, CLIENT_TOKEN_TTL_SECONDS);
+    assert_eq!(token.scope.as_deref(), Some("introspect admin"));
+
+    // Credentials can also be sent in the form instead of a Basic header
+    let response = app
+        .post_oauth_token(&[
+            ("grant_type", "client_credentials"),
+            ("client_id", SERVICE_ID),
+            ("client_secret", secret.as_ref()),
+            ("scope", "introspect"),
+        ])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token = response.json::<TokenResponse>().await.unwrap();
+    assert_eq!(token.scope.as_deref(), Some("introspect"));
+
+    // A client token is not a user session
+    let verify = app
+        .post_verify_token(&serde_json::json!({ "token": token.access_token }))
+        .await;
+    assert_eq!(verify.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_reject_wrong_secret_and_unregistered_scope() {
+    let app = TestApp::new().await;
+    let secret = register_service(&app, &["introspect"]).await;
+
+    let response = post_client_credentials(&app, "not-the-secret", None).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(response.headers().contains_key(WWW_AUTHENTICATE));
+
+    let response = post_client_credentials(&app, secret.as_ref(), Some("introspect admin")).await;
+    assert_eq!(response.status().as_u16(), 400);
+    let body = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["error"], "invalid_scope");
+
+    // Public clients have no secret to get a token of their own with
+    crate::oauth::register_client(&app).await;
+    let response = app
+        .post_oauth_token(&[
+            ("grant_type", "client_credentials"),
+            ("client_id", crate::oauth::CLIENT_ID),
+        ])
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_register_client_with_admin_token() {
+    let app = TestApp::new().await;
+    let secret = register_service(&app, &["admin"]).await;
+    let token = client_token(&app, &secret, None).await;
+
+    let response = app.post_oauth_clients(&new_client(), &token).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let client = response.json::<ClientInfoResponse>().await.unwrap();
+    assert_eq!(client.client_id, "new-spa");
+    assert!(app.oauth_clients.get_client("new-spa").await.is_ok());
+
+    let response = app.post_oauth_clients(&new_client(), &token).await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    let mut invalid = new_client();
+    invalid["clientId"] = "other-spa".into();
+    invalid["redirectUris"] = serde_json::json!(["/callback"]);
+    let response = app.post_oauth_clients(&invalid, &token).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_require_client_token_with_admin_scope() {
+    let app = TestApp::new().await;
+    let secret = register_service(&app, &["introspect", "admin"]).await;
+
+    // Not a token at all
+    let response = app.post_oauth_clients(&new_client(), "not-a-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(response.headers()[WWW_AUTHENTICATE]
+        .to_str()
+        .unwrap()
+        .contains("invalid_token"));
+
+    // A client token without the admin scope
+    let token = client_token(&app, &secret, Some("introspect")).await;
+    let response = app.post_oauth_clients(&new_client(), &token).await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert!(response.headers()[WWW_AUTHENTICATE]
+        .to_str()
+        .unwrap()
+        .contains("insufficient_scope"));
+
+    // A logged in user's token
+    let email = TestApp::get_random_email();
+    signup(&app, &email, false).await;
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": "password123" }))
+        .await;
+    let user_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+    let response = app.post_oauth_clients(&new_client(), &user_token).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // An expired admin token
+    let token = client_token(&app, &secret, Some("admin")).await;
+    app.clock
+        .advance(Duration::seconds(CLIENT_TOKEN_TTL_SECONDS));
+    let response = app.post_oauth_clients(&new_client(), &token).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    assert!(app.oauth_clients.get_client("new-spa").await.is_err());
+}
@@ -0,0 +1,151 @@
+use auth_service::domains::data_stores::TwoFACodeStore;
+use auth_service::domains::email::Email;
+use auth_service::utils::constants::JWT_COOKIE_NAME;
+use auth_service::utils::password_hashing::HashingPool;
+use tokio::task::JoinSet;
+
+use crate::helpers::TestApp;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn should_handle_concurrent_2fa_logins_without_panicking() {
+    let app = TestApp::new().await;
+    let email = TestApp::get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": true,
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let mut logins = JoinSet::new();
+    for _ in 0..16 {
+        let (client, address) = (app.http_client.clone(), app.address.clone());
+        let login_body = serde_json::json!({
+            "email": email,
+            "password": "password123",
+        });
+        logins.spawn(async move {
+            client
+                .post(format!("{}/login", address))
+                .json(&login_body)
+                .send()
+                .await
+                .expect("Failed to execute request.")
+                .status()
+                .as_u16()
+        });
+    }
+
+    while let Some(status) = logins.join_next().await {
+        assert_eq!(status.unwrap(), 206);
+    }
+
+    // The last code written is the one that can be used
+    let email = Email::parse(email).unwrap();
+    assert!(app.two_fa_code.get_code(&email).await.is_ok());
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn should_keep_serving_sessions_while_signups_are_in_flight() {
+    let hashing_pool = HashingPool::new(1, 16);
+    let app = TestApp::builder()
+        .hashing_pool(hashing_pool.clone())
+        .build()
+        .await;
+    let email = TestApp::get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false,
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    // Park the only hashing worker, so no signup can finish until it's let go
+    let parked = hashing_pool.park_worker().await.unwrap();
+
+    let mut signups = JoinSet::new();
+    for _ in 0..8 {
+        let (client, address) = (app.http_client.clone(), app.address.clone());
+        let signup_body = serde_json::json!({
+            "email": TestApp::get_random_email(),
+            "password": "password123",
+            "requires2FA": false,
+        });
+        signups.spawn(async move {
+            client
+                .post(format!("{}/signup", address))
+                .json(&signup_body)
+                .send()
+                .await
+                .expect("Failed to execute request.")
+                .status()
+                .as_u16()
+        });
+    }
+
+    // With the user store locked for each signup these would wait for the
+    // password hashes, which can't happen while the worker is parked
+    for _ in 0..16 {
+        let response = app
+            .post_verify_token(&serde_json::json!({ "token": token }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    assert!(signups.try_join_next().is_none());
+
+    parked.release().await.unwrap();
+    while let Some(status) = signups.join_next().await {
+        assert_eq!(status.unwrap(), 201);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn should_return_409_for_all_but_one_concurrent_signup_with_same_email() {
+    let app = TestApp::new().await;
+    let email = TestApp::get_random_email();
+
+    let mut signups = JoinSet::new();
+    for _ in 0..8 {
+        let (client, address) = (app.http_client.clone(), app.address.clone());
+        let signup_body = serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": false,
+        });
+        signups.spawn(async move {
+            client
+                .post(format!("{}/signup", address))
+                .json(&signup_body)
+                .send()
+                .await
+                .expect("Failed to execute request.")
+                .status()
+                .as_u16()
+        });
+    }
+
+    let mut statuses = Vec::new();
+    while let Some(status) = signups.join_next().await {
+        statuses.push(status.unwrap());
+    }
+    statuses.sort();
+
+    assert_eq!(statuses, [201, 409, 409, 409, 409, 409, 409, 409]);
+}
@@ -1,43 +1,180 @@
+// Shared by the Postgres and in-memory API test binaries, which don't use
+// every helper.
+#![allow(dead_code)]
+
 use auth_service::app_state::AppState;
+use auth_service::domains::data_stores::{
+    BannedTokenStore, SessionStore, TwoFACodeStore, UserStore,
+};
+use auth_service::services::data_stores::hashmap_oauth_client_store::HashmapOAuthClientStore;
+use auth_service::services::data_stores::hashmap_session_store::HashmapSessionStore;
 use auth_service::services::data_stores::hashmap_two_fa_code_store::HashmapTwoFACodeStore;
 use auth_service::services::data_stores::hashmap_user_store::HashMapUserStore;
 use auth_service::services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore;
+use auth_service::services::data_stores::postgres_session_store::PostgresSessionStore;
 use auth_service::services::data_stores::postgres_user_store::PostgresUserStore;
-use auth_service::services::mock_email_client::MockEmailClient;
-use auth_service::utils::constants::DATABASE_URL;
-use auth_service::{get_postgres_pool, Application};
+use auth_service::services::recording_email_client::RecordingEmailClient;
+use auth_service::utils::clock::FakeClock;
+use auth_service::utils::login_throttle::LoginThrottle;
+use auth_service::utils::password_hashing::HashingPool;
+use auth_service::Application;
 
 use reqwest::cookie::Jar;
 use reqwest::Client;
 use serde::Serialize;
-use sqlx::postgres::PgPoolOptions;
-use sqlx::{Executor, PgPool};
 use std::sync::Arc;
-use tokio::sync::RwLock;
 use uuid::Uuid;
 
-pub struct TestApp {
+pub use crate::common::TestDatabase;
+
+// Where the app under test keeps its users. Each test binary picks its
+// default in `USER_STORE_BACKEND` at the crate root.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UserStoreBackend {
+    Postgres,
+    InMemory,
+}
+
+pub struct TestApp<T1 = HashsetBannedTokenStore, T2 = HashmapTwoFACodeStore> {
     pub address: String,
     pub cookie_jar: Arc<Jar>,
     pub http_client: reqwest::Client,
-    pub banned_token: Arc<RwLock<HashsetBannedTokenStore>>,
-    pub two_fa_code: Arc<RwLock<HashmapTwoFACodeStore>>,
+    pub banned_token: Arc<T1>,
+    pub two_fa_code: Arc<T2>,
+    pub email_client: Arc<RecordingEmailClient>,
+    // Tests register the OAuth clients they need here
+    pub oauth_clients: Arc<HashmapOAuthClientStore>,
+    // Time as the app sees it, only moves when a test advances it
+    pub clock: FakeClock,
+    // Dropped with the app, which drops the test database
+    database: Option<TestDatabase>,
+}
+
+pub struct TestAppBuilder<T1 = HashsetBannedTokenStore, T2 = HashmapTwoFACodeStore> {
+    user_store_backend: UserStoreBackend,
+    banned_token: Arc<T1>,
+    two_fa_code: Arc<T2>,
+    hashing_pool: Option<HashingPool>,
+    login_throttle: Option<LoginThrottle>,
+    clock: FakeClock,
 }
 
 impl TestApp {
     pub async fn new() -> Self {
-        let pg_pool = configure_postgresql().await;
-        let users_store = Arc::new(RwLock::new(PostgresUserStore::new(pg_pool)));
-        let banned_stoken_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
-        let two_fa_store = Arc::new(RwLock::new(HashmapTwoFACodeStore::default()));
-        let email_cient = Arc::new(RwLock::new(MockEmailClient));
-
-        let app_state = AppState::new(
-            users_store,
-            banned_stoken_store.clone(),
-            two_fa_store.clone(),
-            email_cient,
-        );
+        Self::builder().build().await
+    }
+
+    pub fn builder() -> TestAppBuilder {
+        let clock = FakeClock::default();
+        TestAppBuilder {
+            user_store_backend: crate::USER_STORE_BACKEND,
+            banned_token: Arc::new(
+                HashsetBannedTokenStore::default().with_clock(Arc::new(clock.clone())),
+            ),
+            two_fa_code: Arc::new(
+                HashmapTwoFACodeStore::default().with_clock(Arc::new(clock.clone())),
+            ),
+            hashing_pool: None,
+            login_throttle: None,
+            clock,
+        }
+    }
+
+    pub fn get_random_email() -> String {
+        format!("{}@example.com", Uuid::new_v4())
+    }
+}
+
+impl<T1: BannedTokenStore, T2: TwoFACodeStore> TestAppBuilder<T1, T2> {
+    pub fn user_store_backend(mut self, user_store_backend: UserStoreBackend) -> Self {
+        self.user_store_backend = user_store_backend;
+        self
+    }
+
+    // Stores passed in should be given this clock to expire entries with
+    pub fn clock(&self) -> &FakeClock {
+        &self.clock
+    }
+
+    pub fn banned_token_store<T: BannedTokenStore>(self, store: T) -> TestAppBuilder<T, T2> {
+        TestAppBuilder {
+            user_store_backend: self.user_store_backend,
+            banned_token: Arc::new(store),
+            two_fa_code: self.two_fa_code,
+            hashing_pool: self.hashing_pool,
+            login_throttle: self.login_throttle,
+            clock: self.clock,
+        }
+    }
+
+    pub fn two_fa_code_store<T: TwoFACodeStore>(self, store: T) -> TestAppBuilder<T1, T> {
+        TestAppBuilder {
+            user_store_backend: self.user_store_backend,
+            banned_token: self.banned_token,
+            two_fa_code: Arc::new(store),
+            hashing_pool: self.hashing_pool,
+            login_throttle: self.login_throttle,
+            clock: self.clock,
+        }
+    }
+
+    // Hashes passwords on the given pool, so a test can hold up its workers
+    pub fn hashing_pool(mut self, hashing_pool: HashingPool) -> Self {
+        self.hashing_pool = Some(hashing_pool);
+        self
+    }
+
+    pub fn login_throttle(mut self, login_throttle: LoginThrottle) -> Self {
+        self.login_throttle = Some(login_throttle);
+        self
+    }
+
+    pub async fn build(self) -> TestApp<T1, T2> {
+        match self.user_store_backend {
+            UserStoreBackend::Postgres => {
+                let database = TestDatabase::new().await;
+                let mut users_store = PostgresUserStore::new(database.pool().clone())
+                    .with_clock(Arc::new(self.clock.clone()));
+                if let Some(hashing_pool) = self.hashing_pool.clone() {
+                    users_store = users_store.with_hashing_pool(hashing_pool);
+                }
+                let session_store = PostgresSessionStore::new(database.pool().clone())
+                    .with_clock(Arc::new(self.clock.clone()));
+                self.spawn(users_store, session_store, Some(database)).await
+            }
+            UserStoreBackend::InMemory => {
+                let mut users_store = HashMapUserStore::default();
+                if let Some(hashing_pool) = self.hashing_pool.clone() {
+                    users_store = users_store.with_hashing_pool(hashing_pool);
+                }
+                let session_store =
+                    HashmapSessionStore::default().with_clock(Arc::new(self.clock.clone()));
+                self.spawn(users_store, session_store, None).await
+            }
+        }
+    }
+
+    async fn spawn<T: UserStore, T5: SessionStore>(
+        self,
+        users_store: T,
+        session_store: T5,
+        database: Option<TestDatabase>,
+    ) -> TestApp<T1, T2> {
+        let email_client = Arc::new(RecordingEmailClient::default());
+        let oauth_clients = Arc::new(HashmapOAuthClientStore::default());
+
+        let mut app_state = AppState::new(
+            Arc::new(users_store),
+            self.banned_token.clone(),
+            self.two_fa_code.clone(),
+            email_client.clone(),
+            oauth_clients.clone(),
+            Arc::new(session_store),
+        )
+        .with_clock(Arc::new(self.clock.clone()));
+        if let Some(login_throttle) = self.login_throttle {
+            app_state = app_state.with_login_throttle(login_throttle);
+        }
         let cookie_jar = Arc::new(Jar::default());
 
         let app = Application::build(app_state, "127.0.0.1:0")
@@ -51,21 +188,28 @@ impl TestApp {
         #[allow(clippy::let_underscore_future)]
         let _ = tokio::spawn(app.run());
 
+        // Redirects are followed by hand, like the steps of a browser flow
         let http_client = Client::builder()
             .cookie_provider(cookie_jar.clone())
+            .redirect(reqwest::redirect::Policy::none())
             .build()
             .unwrap(); // Create a Reqwest http client instance
 
-        // Create new `TestApp` instance and return it
-        //todo!()
         TestApp {
             address,
             cookie_jar,
             http_client,
-            banned_token: banned_stoken_store,
-            two_fa_code: two_fa_store,
+            banned_token: self.banned_token,
+            two_fa_code: self.two_fa_code,
+            email_client,
+            oauth_clients,
+            clock: self.clock,
+            database,
         }
     }
+}
+
+impl<T1, T2> TestApp<T1, T2> {
     pub async fn post_signup<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: Serialize,
@@ -77,10 +221,6 @@ impl TestApp {
             .await
             .expect("Failed to execute request.")
     }
-    pub fn get_random_email() -> String {
-        format!("{}@example.com", Uuid::new_v4())
-    }
-
     pub async fn get_root(&self) -> reqwest::Response {
         self.http_client
             .get(format!("{}/", &self.address))
@@ -108,6 +248,29 @@ impl TestApp {
             .await
             .expect("Failed to execute request.")
     }
+    pub async fn post_logout_all(&self) -> reqwest::Response {
+        self.http_client
+            .post(format!("{}/logout-all", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_sessions(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/sessions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_session(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/sessions/{}", &self.address, id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn post_verify_token<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: Serialize,
@@ -120,58 +283,129 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
-    pub async fn post_verify_2fa<Body>(&self, body: &Body) -> reqwest::Response
+    pub async fn post_change_email<Body>(&self, body: &Body) -> reqwest::Response
     where
-        Body: serde::Serialize,
+        Body: Serialize,
     {
         self.http_client
-            .post(format!("{}/verify-2fa", &self.address))
+            .post(format!("{}/change-email", &self.address))
             .json(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
-}
-async fn configure_postgresql() -> PgPool {
-    let postgresql_conn_url = DATABASE_URL.to_owned();
 
-    // We are creating a new database for each test case, and we need to ensure each database has a unique name!
-    let db_name = Uuid::new_v4().to_string();
+    pub async fn get_confirm_email_change(&self, token: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/confirm-email-change", &self.address))
+            .query(&[("token", token)])
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_oauth_authorize<Params>(&self, params: &Params) -> reqwest::Response
+    where
+        Params: Serialize + ?Sized,
+    {
+        self.http_client
+            .get(format!("{}/oauth/authorize", &self.address))
+            .query(params)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_oauth_authorize<Form>(&self, form: &Form) -> reqwest::Response
+    where
+        Form: Serialize + ?Sized,
+    {
+        self.http_client
+            .post(format!("{}/oauth/authorize", &self.address))
+            .form(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_oauth_token<Form>(&self, form: &Form) -> reqwest::Response
+    where
+        Form: Serialize + ?Sized,
+    {
+        self.http_client
+            .post(format!("{}/oauth/token", &self.address))
+            .form(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_oauth_introspect<Form>(&self, form: &Form, token: &str) -> reqwest::Response
+    where
+        Form: Serialize + ?Sized,
+    {
+        self.http_client
+            .post(format!("{}/oauth/introspect", &self.address))
+            .bearer_auth(token)
+            .form(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
 
-    configure_database(&postgresql_conn_url, &db_name).await;
+    pub async fn post_oauth_revoke<Form>(&self, form: &Form) -> reqwest::Response
+    where
+        Form: Serialize + ?Sized,
+    {
+        self.http_client
+            .post(format!("{}/oauth/revoke", &self.address))
+            .form(form)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
 
-    let postgresql_conn_url_with_db = format!("{}/{}", postgresql_conn_url, db_name);
+    pub async fn post_oauth_clients<Body>(&self, body: &Body, token: &str) -> reqwest::Response
+    where
+        Body: Serialize + ?Sized,
+    {
+        self.http_client
+            .post(format!("{}/oauth/clients", &self.address))
+            .bearer_auth(token)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
 
-    // Create a new connection pool and return it
-    get_postgres_pool(&postgresql_conn_url_with_db)
-        .await
-        .expect("Failed to create Postgres connection pool!")
+    // A path returned in a `Location` header, or any other path on the app
+    pub async fn get_path(&self, path: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_verify_2fa<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/verify-2fa", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
 }
 
-async fn configure_database(db_conn_string: &str, db_name: &str) {
-    // Create database connection
-    let connection = PgPoolOptions::new()
-        .connect(db_conn_string)
-        .await
-        .expect("Failed to create Postgres connection pool.");
-
-    // Create a new database
-    connection
-        .execute(format!(r#"CREATE DATABASE "{}";"#, db_name).as_str())
-        .await
-        .expect("Failed to create database.");
-
-    // Connect to new database
-    let db_conn_string = format!("{}/{}", db_conn_string, db_name);
-
-    let connection = PgPoolOptions::new()
-        .connect(&db_conn_string)
-        .await
-        .expect("Failed to create Postgres connection pool.");
-
-    // Run migrations against new database
-    sqlx::migrate!()
-        .run(&connection)
-        .await
-        .expect("Failed to migrate the database");
+// Whether /verify-token accepts a token, for the audience given or else as a
+// login session
+pub async fn is_valid<T1, T2>(app: &TestApp<T1, T2>, token: &str, audience: Option<&str>) -> bool {
+    let mut body = serde_json::json!({ "token": token });
+    if let Some(audience) = audience {
+        body["audience"] = audience.into();
+    }
+    app.post_verify_token(&body).await.status().as_u16() == 200
 }
@@ -0,0 +1,170 @@
+use auth_service::routes::{IntrospectionResponse, TokenResponse};
+use auth_service::utils::auth::TOKEN_TTL_SECONDS;
+use auth_service::utils::constants::{JWT_COOKIE_NAME, JWT_ISSUER, SESSION_AUDIENCE};
+use chrono::Duration;
+use reqwest::cookie::CookieStore;
+
+use crate::client_credentials::{client_token, register_service, SERVICE_ID};
+use crate::helpers::TestApp;
+use crate::oauth::{authorize, login, register_client, signup, token_form, CLIENT_ID};
+
+// Logs a new user in and returns their session token
+pub async fn session_token(app: &TestApp, requires_2fa: bool) -> String {
+    let email = TestApp::get_random_email();
+    signup(app, &email, requires_2fa).await;
+    login(app, &email).await;
+    session_cookie(app)
+}
+
+// The session token the browser currently holds
+pub fn session_cookie(app: &TestApp) -> String {
+    app.cookie_jar
+        .cookies(&app.address.parse().unwrap())
+        .and_then(|cookies| {
+            cookies.to_str().unwrap().split("; ").find_map(|cookie| {
+                cookie
+                    .strip_prefix(&format!("{}=", JWT_COOKIE_NAME))
+                    .map(str::to_string)
+            })
+        })
+        .expect("No auth cookie found")
+}
+
+async fn introspect(app: &TestApp, bearer: &str, token: &str) -> IntrospectionResponse {
+    let response = app.post_oauth_introspect(&[("token", token)], bearer).await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn should_describe_access_token_issued_to_oauth_client() {
+    let app = TestApp::new().await;
+    let secret = register_service(&app, &["introspect"]).await;
+    let bearer = client_token(&app, &secret, None).await;
+    register_client(&app).await;
+    session_token(&app, true).await;
+
+    let code = authorize(&app).await;
+    let response = app.post_oauth_token(&token_form(&code)).await;
+    let access_token = response.json::<TokenResponse>().await.unwrap().access_token;
+
+    let introspection = introspect(&app, &bearer, &access_token).await;
+    assert!(introspection.active);
+    assert!(introspection.sub.is_some());
+    assert_eq!(introspection.client_id.as_deref(), Some(CLIENT_ID));
+    assert_eq!(introspection.aud.as_deref(), Some(CLIENT_ID));
+    assert_eq!(introspection.iss.as_deref(), Some(JWT_ISSUER.as_str()));
+    assert!(introspection.jti.is_some());
+    assert_eq!(introspection.scope.as_deref(), Some("profile"));
+    assert_eq!(
+        introspection.amr,
+        Some(vec!["pwd".into(), "otp".into(), "mfa".into()])
+    );
+    let (iat, exp) = (introspection.iat.unwrap(), introspection.exp.unwrap());
+    assert_eq!(exp - iat, TOKEN_TTL_SECONDS as usize);
+}
+
+#[tokio::test]
+async fn should_describe_session_and_client_tokens() {
+    let app = TestApp::new().await;
+    let secret = register_service(&app, &["introspect", "admin"]).await;
+    let bearer = client_token(&app, &secret, Some("introspect")).await;
+
+    let session = session_token(&app, false).await;
+    let introspection = introspect(&app, &bearer, &session).await;
+    assert!(introspection.active);
+    assert_eq!(introspection.amr, Some(vec!["pwd".into()]));
+    assert_eq!(introspection.client_id, None);
+    assert_eq!(
+        introspection.aud.as_deref(),
+        Some(SESSION_AUDIENCE.as_str())
+    );
+
+    let admin_token = client_token(&app, &secret, Some("admin")).await;
+    let introspection = introspect(&app, &bearer, &admin_token).await;
+    assert!(introspection.active);
+    assert_eq!(introspection.sub.as_deref(), Some(SERVICE_ID));
+    assert_eq!(introspection.client_id.as_deref(), Some(SERVICE_ID));
+    assert_eq!(introspection.scope.as_deref(), Some("admin"));
+    assert_eq!(introspection.amr, None);
+}
+
+#[tokio::test]
+async fn should_report_revoked_expired_and_invalid_tokens_as_inactive() {
+    let app = TestApp::new().await;
+    let secret = register_service(&app, &["introspect"]).await;
+
+    let revoked = session_token(&app, false).await;
+    assert_eq!(app.post_logout().await.status().as_u16(), 200);
+    let expired = session_token(&app, false).await;
+    app.clock.advance(Duration::seconds(TOKEN_TTL_SECONDS));
+
+    let bearer = client_token(&app, &secret, None).await;
+    for token in [revoked.as_str(), expired.as_str(), "not-a-token"] {
+        let response = app
+            .post_oauth_introspect(&[("token", token)], &bearer)
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+        // Nothing but `active` for inactive tokens
+        let body = response.json::<serde_json::Value>().await.unwrap();
+        assert_eq!(body, serde_json::json!({ "active": false }));
+    }
+}
+
+#[tokio::test]
+async fn should_accept_client_credentials_instead_of_bearer_token() {
+    let app = TestApp::new().await;
+    let secret = register_service(&app, &["introspect"]).await;
+    let session = session_token(&app, false).await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/oauth/introspect", &app.address))
+        .basic_auth(SERVICE_ID, Some(secret.as_ref()))
+        .form(&[("token", session.as_str())])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(
+        response
+            .json::<IntrospectionResponse>()
+            .await
+            .unwrap()
+            .active
+    );
+}
+
+#[tokio::test]
+async fn should_require_client_allowed_to_introspect() {
+    let app = TestApp::new().await;
+    let secret = register_service(&app, &["admin"]).await;
+    let session = session_token(&app, false).await;
+    let form = [("token", session.as_str())];
+
+    // Anonymous callers, and users with their own session, learn nothing
+    let response = app
+        .http_client
+        .post(format!("{}/oauth/introspect", &app.address))
+        .form(&form)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.post_oauth_introspect(&form, &session).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // A client that wasn't registered for introspection
+    let bearer = client_token(&app, &secret, None).await;
+    let response = app.post_oauth_introspect(&form, &bearer).await;
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app
+        .http_client
+        .post(format!("{}/oauth/introspect", &app.address))
+        .basic_auth(SERVICE_ID, Some(secret.as_ref()))
+        .form(&form)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+}
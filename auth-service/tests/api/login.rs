@@ -1,8 +1,14 @@
 use auth_service::domains::data_stores::TwoFACodeStore;
 use auth_service::domains::email::Email;
-use auth_service::utils::constants::JWT_COOKIE_NAME;
-use axum::Json;
+use auth_service::domains::user_id::UserId;
+use auth_service::utils::auth::validate_token;
+use auth_service::utils::constants::{JWT_COOKIE_NAME, SESSION_AUDIENCE};
+use auth_service::utils::login_throttle::{
+    LoginThrottle, LOGIN_LOCKOUT_SECONDS, MAX_FAILED_LOGINS,
+};
+use auth_service::utils::password_hashing::HashingPool;
 use serde::Serialize;
+use std::time::{Duration, Instant};
 
 use crate::helpers::TestApp;
 
@@ -79,6 +85,149 @@ async fn should_return_401_if_incorrect_credentials() {
     );
 }
 
+#[tokio::test]
+async fn should_respond_the_same_for_unknown_email_and_wrong_password() {
+    let app = TestApp::new().await;
+    let email = TestApp::get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false,
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let wrong_password = serde_json::json!({
+        "email": email,
+        "password": "wrongpassword",
+    });
+    let unknown_email = serde_json::json!({
+        "email": TestApp::get_random_email(),
+        "password": "wrongpassword",
+    });
+
+    let wrong_password = app.post_login(&wrong_password).await;
+    let unknown_email = app.post_login(&unknown_email).await;
+    assert_eq!(wrong_password.status().as_u16(), 401);
+    assert_eq!(unknown_email.status().as_u16(), 401);
+    assert_eq!(
+        wrong_password.text().await.unwrap(),
+        unknown_email.text().await.unwrap()
+    );
+}
+
+// Wall-clock timing swings with machine load, so this only runs on request:
+// cargo test --test api -- --ignored
+#[tokio::test]
+#[ignore = "timing sensitive"]
+async fn should_take_as_long_for_unknown_email_as_for_wrong_password() {
+    const SAMPLES: usize = 15;
+
+    // Every sample is a failed login, which would otherwise lock the email out
+    let app = TestApp::builder()
+        .login_throttle(LoginThrottle::new(
+            u32::MAX,
+            chrono::Duration::minutes(15),
+            chrono::Duration::minutes(15),
+        ))
+        .build()
+        .await;
+    let email = TestApp::get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false,
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let wrong_password = serde_json::json!({
+        "email": email,
+        "password": "wrongpassword",
+    });
+    let unknown_email = serde_json::json!({
+        "email": TestApp::get_random_email(),
+        "password": "wrongpassword",
+    });
+
+    // Warm up, the dummy hash is made on first use
+    app.post_login(&unknown_email).await;
+
+    // Interleave the two so drift in machine load hits both equally
+    let mut known = Vec::with_capacity(SAMPLES);
+    let mut unknown = Vec::with_capacity(SAMPLES);
+    for _ in 0..SAMPLES {
+        let started = Instant::now();
+        assert_eq!(app.post_login(&wrong_password).await.status().as_u16(), 401);
+        known.push(started.elapsed());
+
+        let started = Instant::now();
+        assert_eq!(app.post_login(&unknown_email).await.status().as_u16(), 401);
+        unknown.push(started.elapsed());
+    }
+
+    let median = |samples: &mut Vec<Duration>| {
+        samples.sort();
+        samples[samples.len() / 2].as_secs_f64()
+    };
+    let (known, unknown) = (median(&mut known), median(&mut unknown));
+
+    // Without the dummy verification unknown emails skip Argon2 entirely and
+    // come back an order of magnitude faster, so the bounds can stay loose
+    let ratio = unknown / known;
+    assert!(
+        (0.5..2.0).contains(&ratio),
+        "median for unknown email {:.4}s vs wrong password {:.4}s",
+        unknown,
+        known
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn should_hash_the_password_for_unknown_email_as_for_wrong_password() {
+    // One worker and no queue, so a login that needs it while it's held up
+    // is turned away
+    let hashing_pool = HashingPool::new(1, 0);
+    let app = TestApp::builder()
+        .hashing_pool(hashing_pool.clone())
+        .build()
+        .await;
+    let email = TestApp::get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false,
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let wrong_password = serde_json::json!({
+        "email": email,
+        "password": "wrongpassword",
+    });
+    let unknown_email = serde_json::json!({
+        "email": TestApp::get_random_email(),
+        "password": "wrongpassword",
+    });
+
+    // Warm up, the dummy hash is made on first use
+    assert_eq!(app.post_login(&unknown_email).await.status().as_u16(), 401);
+
+    let parked = hashing_pool.park_worker().await.unwrap();
+
+    // Without the dummy verification unknown emails would skip Argon2 and
+    // come back 401 right away, which is what gives them away
+    assert_eq!(app.post_login(&wrong_password).await.status().as_u16(), 503);
+    assert_eq!(app.post_login(&unknown_email).await.status().as_u16(), 503);
+
+    parked.release().await.unwrap();
+    assert_eq!(app.post_login(&wrong_password).await.status().as_u16(), 401);
+    assert_eq!(app.post_login(&unknown_email).await.status().as_u16(), 401);
+}
+
 #[tokio::test]
 async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
     let app: TestApp = TestApp::new().await;
@@ -110,6 +259,45 @@ async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
     assert!(!auth_cookie.value().is_empty());
 }
 
+#[tokio::test]
+async fn should_issue_token_with_user_id_subject() {
+    let app = TestApp::new().await;
+    let email = TestApp::get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false,
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+
+    let claims = validate_token(
+        auth_cookie.value(),
+        Some(&SESSION_AUDIENCE),
+        app.banned_token.clone(),
+        &app.clock,
+    )
+    .await
+    .expect("Token should be valid");
+
+    assert!(UserId::parse(claims.sub.clone()).is_ok());
+    assert_ne!(claims.sub, email);
+    assert_eq!(claims.email, email);
+}
+
 #[tokio::test]
 async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
     let app = TestApp::new().await;
@@ -129,7 +317,7 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
         "password": "password123",
     });
 
-    let response = app.post_login(&test_case).await;
+    let response = app.post_login(&login_body).await;
     // assert_eq!(response.status().as_u16(), 206);
     // let message = response
     //     .clone()
@@ -149,8 +337,6 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
     assert_eq!(
         json_body.login_attempt_id,
         app.two_fa_code
-            .read()
-            .await
             .get_code(&Email::parse("ravi@gmail.com".to_string()).unwrap())
             .await
             .unwrap()
@@ -159,6 +345,85 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
     );
 }
 
+#[tokio::test]
+async fn should_lock_out_an_email_after_too_many_failed_logins() {
+    let app = TestApp::new().await;
+    let email = TestApp::get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false,
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let wrong_password = serde_json::json!({
+        "email": email,
+        "password": "wrongpassword",
+    });
+    let right_password = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let unknown_email = serde_json::json!({
+        "email": TestApp::get_random_email(),
+        "password": "wrongpassword",
+    });
+
+    for body in [&wrong_password, &unknown_email] {
+        for _ in 0..MAX_FAILED_LOGINS {
+            assert_eq!(app.post_login(body).await.status().as_u16(), 401);
+        }
+        let response = app.post_login(body).await;
+        assert_eq!(response.status().as_u16(), 429);
+        assert_eq!(
+            response.headers()["retry-after"],
+            LOGIN_LOCKOUT_SECONDS.to_string().as_str()
+        );
+    }
+
+    // Even the right password is turned away until the lockout is over
+    app.clock
+        .advance(chrono::Duration::seconds(LOGIN_LOCKOUT_SECONDS - 60));
+    let response = app.post_login(&right_password).await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert_eq!(response.headers()["retry-after"], "60");
+
+    app.clock.advance(chrono::Duration::seconds(60));
+    assert_eq!(app.post_login(&right_password).await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_reset_failed_logins_after_a_successful_login() {
+    let app = TestApp::new().await;
+    let email = TestApp::get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false,
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let wrong_password = serde_json::json!({
+        "email": email,
+        "password": "wrongpassword",
+    });
+    let right_password = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+
+    for _ in 0..2 {
+        for _ in 0..MAX_FAILED_LOGINS - 1 {
+            assert_eq!(app.post_login(&wrong_password).await.status().as_u16(), 401);
+        }
+        assert_eq!(app.post_login(&right_password).await.status().as_u16(), 200);
+    }
+}
+
 #[derive(Serialize, PartialEq, Debug, serde::Deserialize)]
 pub struct SigninResponse {
     pub message: String,
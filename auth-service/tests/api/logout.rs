@@ -1,9 +1,12 @@
-use crate::helpers::TestApp;
-use crate::login::SigninResponse;
+use crate::helpers::{is_valid, TestApp};
+use crate::introspect::session_cookie;
+use crate::oauth::{authorize, login, register_client, signup, token_form, CLIENT_ID};
 
 use auth_service::domains::data_stores::BannedTokenStore;
-use auth_service::domains::email::{self, Email};
-use auth_service::utils::auth::{generate_auth_cookie, generate_auth_token};
+use auth_service::domains::email::Email;
+use auth_service::domains::user_id::UserId;
+use auth_service::routes::TokenResponse;
+use auth_service::utils::auth::{generate_auth_cookie, validate_token, Authentication};
 use auth_service::utils::constants::JWT_COOKIE_NAME;
 
 use reqwest::Url;
@@ -36,8 +39,18 @@ async fn should_return_401_if_invalid_token() {
 async fn should_return_200_if_valid_jwt_cookie() {
     let app = TestApp::new().await;
     let email = Email::parse("lravikanth@gmail.com".to_string()).unwrap();
-    let cookie = generate_auth_cookie(&email).unwrap();
-    let token = generate_auth_token(&email).unwrap();
+    let user_id = UserId::default();
+    let cookie = generate_auth_cookie(
+        &user_id,
+        &email,
+        0,
+        &Authentication::password(&app.clock),
+        &app.clock,
+    )
+    .unwrap();
+    let claims = validate_token(cookie.value(), None, app.banned_token.clone(), &app.clock)
+        .await
+        .unwrap();
 
     app.cookie_jar.add_cookie_str(
         &cookie.to_string(),
@@ -47,8 +60,8 @@ async fn should_return_200_if_valid_jwt_cookie() {
     let resp = app.post_logout().await;
     assert_eq!(resp.status().as_u16(), 200);
 
-    let guard = app.banned_token.read().await;
-    let exists = guard.does_token_exist(token).await;
+    // Only the token's id is kept
+    let exists = app.banned_token.does_token_exist(claims.jti).await;
     assert!(exists);
 }
 
@@ -56,7 +69,14 @@ async fn should_return_200_if_valid_jwt_cookie() {
 async fn should_return_400_if_logout_called_twice_in_a_row() {
     let app = TestApp::new().await;
     let email = Email::parse("lravikanth@gmail.com".to_string()).unwrap();
-    let cookie = generate_auth_cookie(&email).unwrap();
+    let cookie = generate_auth_cookie(
+        &UserId::default(),
+        &email,
+        0,
+        &Authentication::password(&app.clock),
+        &app.clock,
+    )
+    .unwrap();
 
     app.cookie_jar.add_cookie_str(
         &cookie.to_string(),
@@ -69,3 +89,55 @@ async fn should_return_400_if_logout_called_twice_in_a_row() {
     let resp2 = app.post_logout().await;
     assert_eq!(resp2.status().as_u16(), 400);
 }
+
+#[tokio::test]
+async fn should_end_every_session_on_logout_all() {
+    let app = TestApp::new().await;
+    register_client(&app).await;
+    let email = TestApp::get_random_email();
+    signup(&app, &email, false).await;
+
+    // A session on another device, a client acting for the user and a code
+    // the client is yet to exchange
+    login(&app, &email).await;
+    let other_device = session_cookie(&app);
+    let code = authorize(&app).await;
+    let response = app.post_oauth_token(&token_form(&code)).await;
+    let access_token = response.json::<TokenResponse>().await.unwrap().access_token;
+    login(&app, &email).await;
+    let this_device = session_cookie(&app);
+    let pending_code = authorize(&app).await;
+
+    let resp = app.post_logout_all().await;
+    assert_eq!(resp.status().as_u16(), 200);
+
+    assert!(!is_valid(&app, &other_device, None).await);
+    assert!(!is_valid(&app, &this_device, None).await);
+    assert!(!is_valid(&app, &access_token, Some(CLIENT_ID)).await);
+    let response = app.post_oauth_token(&token_form(&pending_code)).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    // The cookie is gone too
+    let resp = app.post_logout_all().await;
+    assert_eq!(resp.status().as_u16(), 400);
+
+    // Logging in again starts a fresh session
+    login(&app, &email).await;
+    assert!(is_valid(&app, &session_cookie(&app), None).await);
+}
+
+#[tokio::test]
+async fn should_return_401_from_logout_all_if_invalid_token() {
+    let app = TestApp::new().await;
+
+    app.cookie_jar.add_cookie_str(
+        &format!(
+            "{}=invalid; HttpOnly; SameSite=Lax; Secure; Path=/",
+            JWT_COOKIE_NAME
+        ),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+
+    let resp = app.post_logout_all().await;
+    assert_eq!(resp.status().as_u16(), 401);
+}
@@ -1,8 +1,24 @@
+use helpers::UserStoreBackend;
+
+#[path = "../common/mod.rs"]
+mod common;
+
+mod change_email;
+mod client_credentials;
+mod concurrency;
 mod helpers;
+mod introspect;
 mod login;
 mod logout;
+mod oauth;
+mod oidc;
+mod password_hash_upgrade;
+mod revoke;
 mod root;
+mod sessions;
 
 mod signup;
 mod verify_2fa;
 mod verify_token;
+
+const USER_STORE_BACKEND: UserStoreBackend = UserStoreBackend::Postgres;
@@ -0,0 +1,360 @@
+use auth_service::domains::data_stores::OAuthClientStore;
+use auth_service::domains::email::Email;
+use auth_service::domains::oauth_client::OAuthClient;
+use auth_service::domains::pkce::{CodeChallenge, CodeVerifier};
+use auth_service::routes::{TokenResponse, TwoFactorAuthResponse};
+use auth_service::utils::auth::AUTHORIZATION_CODE_TTL_SECONDS;
+use auth_service::OAuthErrorResponse;
+use chrono::Duration;
+use reqwest::header::{CACHE_CONTROL, LOCATION};
+use url::Url;
+
+use crate::helpers::TestApp;
+
+pub const CLIENT_ID: &str = "test-spa";
+pub const REDIRECT_URI: &str = "http://localhost:8000/callback";
+const OTHER_REDIRECT_URI: &str = "http://localhost:8000/other-callback";
+// Example from RFC 7636, appendix B
+const CODE_VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+const STATE: &str = "af0ifjsldkj";
+
+pub async fn register_client(app: &TestApp) {
+    let mut client = OAuthClient::new(
+        CLIENT_ID.to_string(),
+        "Test SPA".to_string(),
+        vec![REDIRECT_URI.to_string(), OTHER_REDIRECT_URI.to_string()],
+    )
+    .unwrap();
+    client.scopes = vec!["profile".to_string()];
+    app.oauth_clients.add_client(client).await.unwrap();
+}
+
+fn code_challenge() -> String {
+    let verifier = CodeVerifier::parse(CODE_VERIFIER.to_string()).unwrap();
+    CodeChallenge::s256(&verifier).as_ref().to_string()
+}
+
+// What a client would put in the link to /oauth/authorize
+fn authorize_params() -> Vec<(&'static str, String)> {
+    vec![
+        ("response_type", "code".to_string()),
+        ("client_id", CLIENT_ID.to_string()),
+        ("redirect_uri", REDIRECT_URI.to_string()),
+        ("scope", "profile".to_string()),
+        ("state", STATE.to_string()),
+        ("code_challenge", code_challenge()),
+        ("code_challenge_method", "S256".to_string()),
+    ]
+}
+
+pub fn token_form(code: &str) -> Vec<(&'static str, String)> {
+    vec![
+        ("grant_type", "authorization_code".to_string()),
+        ("code", code.to_string()),
+        ("redirect_uri", REDIRECT_URI.to_string()),
+        ("client_id", CLIENT_ID.to_string()),
+        ("code_verifier", CODE_VERIFIER.to_string()),
+    ]
+}
+
+// Where a redirect points, resolved the way a browser would
+pub fn location(app: &TestApp, response: &reqwest::Response) -> Url {
+    assert!(
+        response.status().is_redirection(),
+        "Expected a redirect, got {}",
+        response.status()
+    );
+    let location = response.headers()[LOCATION].to_str().unwrap();
+    Url::parse(&app.address).unwrap().join(location).unwrap()
+}
+
+pub fn query_param(url: &Url, name: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+pub fn path_and_query(url: &Url) -> String {
+    format!("{}?{}", url.path(), url.query().unwrap_or_default())
+}
+
+pub async fn signup(app: &TestApp, email: &str, requires_2fa: bool) {
+    let body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": requires_2fa,
+    });
+    assert_eq!(app.post_signup(&body).await.status().as_u16(), 201);
+}
+
+// Logs in through the same routes the login page uses, including the 2FA
+// step with the code from the email
+pub async fn login(app: &TestApp, email: &str) {
+    let body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&body).await;
+    if response.status().as_u16() == 200 {
+        return;
+    }
+    assert_eq!(response.status().as_u16(), 206);
+
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .unwrap()
+        .login_attempt_id;
+    let code = app
+        .email_client
+        .last_sent_to(&Email::parse(email.to_string()).unwrap())
+        .expect("No 2FA email sent")
+        .content;
+
+    let body = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code,
+    });
+    assert_eq!(app.post_verify_2fa(&body).await.status().as_u16(), 200);
+}
+
+// Goes from the client's link to the code it gets back, as a logged in user
+// who approves the request
+pub async fn authorize(app: &TestApp) -> String {
+    let response = app.get_oauth_authorize(&authorize_params()).await;
+    let consent_page = location(app, &response);
+    assert_eq!(consent_page.path(), "/consent.html");
+
+    // The consent page posts the request it was given back with the decision
+    let mut form: Vec<(String, String)> = consent_page.query_pairs().into_owned().collect();
+    form.push(("decision".to_string(), "approve".to_string()));
+    let response = app.post_oauth_authorize(&form).await;
+
+    let callback = location(app, &response);
+    assert!(callback.as_str().starts_with(REDIRECT_URI));
+    assert_eq!(query_param(&callback, "state").as_deref(), Some(STATE));
+    query_param(&callback, "code").expect("No code in the redirect")
+}
+
+async fn assert_token_error(response: reqwest::Response, status: u16, error: &str) {
+    assert_eq!(response.status().as_u16(), status);
+    let body = response.json::<OAuthErrorResponse>().await.unwrap();
+    assert_eq!(body.error, error);
+}
+
+#[tokio::test]
+async fn should_issue_access_token_after_login_and_consent() {
+    let app = TestApp::new().await;
+    register_client(&app).await;
+    let email = TestApp::get_random_email();
+    signup(&app, &email, false).await;
+
+    // Without a session the user is sent to the login page first
+    let response = app.get_oauth_authorize(&authorize_params()).await;
+    let login_page = location(&app, &response);
+    assert_eq!(login_page.path(), "/");
+    let next = query_param(&login_page, "next").expect("No next link to come back to");
+    assert!(next.starts_with("/oauth/authorize?"));
+
+    login(&app, &email).await;
+
+    // Back at /oauth/authorize, now with a session, the consent page is next
+    let response = app.get_path(&next).await;
+    let consent_page = location(&app, &response);
+    assert_eq!(consent_page.path(), "/consent.html");
+    let page = app.get_path(&path_and_query(&consent_page)).await;
+    assert_eq!(page.status().as_u16(), 200);
+    let client = app.get_path(&format!("/oauth/clients/{}", CLIENT_ID)).await;
+    assert_eq!(client.status().as_u16(), 200);
+
+    let code = authorize(&app).await;
+    let response = app.post_oauth_token(&token_form(&code)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()[CACHE_CONTROL], "no-store");
+
+    let token = response.json::<TokenResponse>().await.unwrap();
+    assert_eq!(token.token_type, "Bearer");
+    assert_eq!(token.scope.as_deref(), Some("profile"));
+    // Only OpenID Connect requests get an ID token
+    assert!(token.id_token.is_none());
+
+    // The token is for the client, not for the app holding login sessions
+    let verify = app
+        .post_verify_token(&serde_json::json!({ "token": token.access_token }))
+        .await;
+    assert_eq!(verify.status().as_u16(), 401);
+    let verify = app
+        .post_verify_token(&serde_json::json!({
+            "token": token.access_token,
+            "audience": CLIENT_ID,
+        }))
+        .await;
+    assert_eq!(verify.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_issue_access_token_to_2fa_user() {
+    let app = TestApp::new().await;
+    register_client(&app).await;
+    let email = TestApp::get_random_email();
+    signup(&app, &email, true).await;
+
+    login(&app, &email).await;
+    let code = authorize(&app).await;
+
+    let response = app.post_oauth_token(&token_form(&code)).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_not_redirect_to_unregistered_redirect_uri() {
+    let app = TestApp::new().await;
+    register_client(&app).await;
+
+    let mut params = authorize_params();
+    params[2].1 = "http://evil.example.com/callback".to_string();
+    let response = app.get_oauth_authorize(&params).await;
+    assert!(response.headers().get(LOCATION).is_none());
+    assert_token_error(response, 400, "invalid_request").await;
+
+    let mut params = authorize_params();
+    params[1].1 = "unknown-client".to_string();
+    let response = app.get_oauth_authorize(&params).await;
+    assert!(response.headers().get(LOCATION).is_none());
+    assert_token_error(response, 400, "invalid_request").await;
+}
+
+#[tokio::test]
+async fn should_require_pkce_with_s256() {
+    let app = TestApp::new().await;
+    register_client(&app).await;
+
+    let without_challenge: Vec<_> = authorize_params()
+        .into_iter()
+        .filter(|(key, _)| !key.starts_with("code_challenge"))
+        .collect();
+    let mut plain = authorize_params();
+    plain[5].1 = CODE_VERIFIER.to_string();
+    plain[6].1 = "plain".to_string();
+
+    for params in [without_challenge, plain] {
+        let response = app.get_oauth_authorize(&params).await;
+        let callback = location(&app, &response);
+        assert!(callback.as_str().starts_with(REDIRECT_URI));
+        assert_eq!(
+            query_param(&callback, "error").as_deref(),
+            Some("invalid_request")
+        );
+        assert_eq!(query_param(&callback, "state").as_deref(), Some(STATE));
+    }
+}
+
+#[tokio::test]
+async fn should_reject_scopes_the_client_was_not_registered_for() {
+    let app = TestApp::new().await;
+    register_client(&app).await;
+
+    // A public client asking for what only confidential clients get
+    let mut params = authorize_params();
+    params[3].1 = "openid admin introspect".to_string();
+    let response = app.get_oauth_authorize(&params).await;
+
+    let callback = location(&app, &response);
+    assert!(callback.as_str().starts_with(REDIRECT_URI));
+    assert_eq!(
+        query_param(&callback, "error").as_deref(),
+        Some("invalid_scope")
+    );
+    assert_eq!(query_param(&callback, "state").as_deref(), Some(STATE));
+    assert_eq!(query_param(&callback, "code"), None);
+}
+
+#[tokio::test]
+async fn should_redirect_with_access_denied_if_user_denies() {
+    let app = TestApp::new().await;
+    register_client(&app).await;
+    let email = TestApp::get_random_email();
+    signup(&app, &email, false).await;
+    login(&app, &email).await;
+
+    let mut form = authorize_params();
+    form.push(("decision", "deny".to_string()));
+    let response = app.post_oauth_authorize(&form).await;
+
+    let callback = location(&app, &response);
+    assert_eq!(
+        query_param(&callback, "error").as_deref(),
+        Some("access_denied")
+    );
+    assert_eq!(query_param(&callback, "code"), None);
+}
+
+#[tokio::test]
+async fn should_reject_wrong_code_verifier() {
+    let app = TestApp::new().await;
+    register_client(&app).await;
+    let email = TestApp::get_random_email();
+    signup(&app, &email, false).await;
+    login(&app, &email).await;
+    let code = authorize(&app).await;
+
+    let mut form = token_form(&code);
+    form[4].1 = "a".repeat(43);
+    let response = app.post_oauth_token(&form).await;
+    assert_token_error(response, 400, "invalid_grant").await;
+
+    // The code was issued for one redirect URI only
+    let mut form = token_form(&code);
+    form[2].1 = OTHER_REDIRECT_URI.to_string();
+    let response = app.post_oauth_token(&form).await;
+    assert_token_error(response, 400, "invalid_grant").await;
+}
+
+#[tokio::test]
+async fn should_reject_reused_code() {
+    let app = TestApp::new().await;
+    register_client(&app).await;
+    let email = TestApp::get_random_email();
+    signup(&app, &email, false).await;
+    login(&app, &email).await;
+    let code = authorize(&app).await;
+
+    let response = app.post_oauth_token(&token_form(&code)).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_oauth_token(&token_form(&code)).await;
+    assert_token_error(response, 400, "invalid_grant").await;
+}
+
+#[tokio::test]
+async fn should_reject_expired_code() {
+    let app = TestApp::new().await;
+    register_client(&app).await;
+    let email = TestApp::get_random_email();
+    signup(&app, &email, false).await;
+    login(&app, &email).await;
+    let code = authorize(&app).await;
+
+    app.clock
+        .advance(Duration::seconds(AUTHORIZATION_CODE_TTL_SECONDS));
+
+    let response = app.post_oauth_token(&token_form(&code)).await;
+    assert_token_error(response, 400, "invalid_grant").await;
+}
+
+#[tokio::test]
+async fn should_reject_unsupported_grant_type() {
+    let app = TestApp::new().await;
+    register_client(&app).await;
+
+    let response = app
+        .post_oauth_token(&[("grant_type", "password"), ("client_id", CLIENT_ID)])
+        .await;
+    assert_token_error(response, 400, "unsupported_grant_type").await;
+
+    let mut form = token_form("code");
+    form[3].1 = "unknown-client".to_string();
+    let response = app.post_oauth_token(&form).await;
+    assert_token_error(response, 401, "invalid_client").await;
+}
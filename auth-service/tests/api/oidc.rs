@@ -0,0 +1,263 @@
+use auth_service::domains::email::Email;
+use auth_service::domains::pkce::{CodeChallenge, CodeVerifier};
+use auth_service::routes::{OpenIdConfiguration, TokenResponse, UserInfoResponse};
+use auth_service::utils::auth::IdTokenClaims;
+use auth_service::utils::constants::JWT_ISSUER;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use reqwest::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use url::Url;
+use uuid::Uuid;
+
+use crate::helpers::TestApp;
+use crate::introspect::session_cookie;
+use crate::oauth::{
+    authorize, location, login, path_and_query, query_param, register_client, signup, token_form,
+    CLIENT_ID, REDIRECT_URI,
+};
+
+// A relying party that only knows our issuer, and learns everything else
+// from discovery like a stock OIDC client library would
+struct RelyingParty<'a> {
+    app: &'a TestApp,
+    config: OpenIdConfiguration,
+    code_verifier: CodeVerifier,
+    state: String,
+    nonce: String,
+}
+
+impl<'a> RelyingParty<'a> {
+    async fn discover(app: &'a TestApp) -> Self {
+        let response = app.get_path("/.well-known/openid-configuration").await;
+        assert_eq!(response.status().as_u16(), 200);
+        let config = response.json::<OpenIdConfiguration>().await.unwrap();
+
+        let code_verifier = CodeVerifier::parse(format!(
+            "{}{}",
+            Uuid::new_v4().simple(),
+            Uuid::new_v4().simple()
+        ))
+        .unwrap();
+
+        RelyingParty {
+            app,
+            config,
+            code_verifier,
+            state: Uuid::new_v4().to_string(),
+            nonce: Uuid::new_v4().to_string(),
+        }
+    }
+
+    // Discovered endpoints carry the public issuer URL, the test talks to the
+    // app on whatever port it was given
+    fn local(&self, endpoint: &str) -> String {
+        let url = Url::parse(endpoint).unwrap();
+        assert!(endpoint.starts_with(&self.config.issuer));
+        path_and_query(&url).trim_end_matches('?').to_string()
+    }
+
+    fn authorization_url(&self) -> String {
+        let mut url = Url::parse(&self.config.authorization_endpoint).unwrap();
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", CLIENT_ID)
+            .append_pair("redirect_uri", REDIRECT_URI)
+            .append_pair("scope", "openid")
+            .append_pair("state", &self.state)
+            .append_pair("nonce", &self.nonce)
+            .append_pair(
+                "code_challenge",
+                CodeChallenge::s256(&self.code_verifier).as_ref(),
+            )
+            .append_pair("code_challenge_method", "S256");
+        self.local(url.as_str())
+    }
+
+    // Plays the browser: login page, back to /oauth/authorize, consent, and
+    // finally the redirect to the relying party's callback
+    async fn sign_in(&self, email: &str) -> Url {
+        let response = self.app.get_path(&self.authorization_url()).await;
+        let login_page = location(self.app, &response);
+        let next = query_param(&login_page, "next").unwrap();
+
+        login(self.app, email).await;
+
+        let response = self.app.get_path(&next).await;
+        let consent_page = location(self.app, &response);
+        let mut form: Vec<(String, String)> = consent_page.query_pairs().into_owned().collect();
+        form.push(("decision".to_string(), "approve".to_string()));
+        let response = self.app.post_oauth_authorize(&form).await;
+
+        location(self.app, &response)
+    }
+
+    async fn exchange(&self, callback: &Url) -> TokenResponse {
+        assert_eq!(query_param(callback, "state"), Some(self.state.clone()));
+        let code = query_param(callback, "code").unwrap();
+
+        let response = self
+            .app
+            .http_client
+            .post(format!(
+                "{}{}",
+                self.app.address,
+                self.local(&self.config.token_endpoint)
+            ))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", &code),
+                ("redirect_uri", REDIRECT_URI),
+                ("client_id", CLIENT_ID),
+                ("code_verifier", self.code_verifier.as_ref()),
+            ])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        response.json().await.unwrap()
+    }
+
+    // Checks the signature against the published keys, then issuer,
+    // audience, expiry and our nonce
+    async fn verify_id_token(&self, id_token: &str) -> IdTokenClaims {
+        let jwks = self
+            .app
+            .get_path(&self.local(&self.config.jwks_uri))
+            .await
+            .json::<JwkSet>()
+            .await
+            .unwrap();
+        let kid = decode_header(id_token).unwrap().kid.unwrap();
+        let jwk = jwks.find(&kid).expect("Signing key is not published");
+
+        let mut validation = Validation::new(Algorithm::EdDSA);
+        validation.set_issuer(&[&self.config.issuer]);
+        validation.set_audience(&[CLIENT_ID]);
+        let claims =
+            decode::<IdTokenClaims>(id_token, &DecodingKey::from_jwk(jwk).unwrap(), &validation)
+                .expect("ID token did not verify")
+                .claims;
+
+        assert_eq!(claims.nonce, Some(self.nonce.clone()));
+        claims
+    }
+
+    async fn userinfo(&self, access_token: &str) -> reqwest::Response {
+        self.app
+            .http_client
+            .get(format!(
+                "{}{}",
+                self.app.address,
+                self.local(&self.config.userinfo_endpoint)
+            ))
+            .header(AUTHORIZATION, format!("Bearer {}", access_token))
+            .send()
+            .await
+            .unwrap()
+    }
+}
+
+#[tokio::test]
+async fn should_publish_discovery_document() {
+    let app = TestApp::new().await;
+    let relying_party = RelyingParty::discover(&app).await;
+    let config = &relying_party.config;
+
+    assert_eq!(config.issuer, JWT_ISSUER.as_str());
+    assert!(config.scopes_supported.contains(&"openid".to_string()));
+    assert_eq!(config.code_challenge_methods_supported, vec!["S256"]);
+    assert_eq!(config.id_token_signing_alg_values_supported, vec!["EdDSA"]);
+
+    let jwks = app.get_path(&relying_party.local(&config.jwks_uri)).await;
+    assert_eq!(jwks.status().as_u16(), 200);
+    assert!(!jwks.json::<JwkSet>().await.unwrap().keys.is_empty());
+}
+
+#[tokio::test]
+async fn should_complete_oidc_flow_with_password_login() {
+    let app = TestApp::new().await;
+    register_client(&app).await;
+    let email = TestApp::get_random_email();
+    signup(&app, &email, false).await;
+    let relying_party = RelyingParty::discover(&app).await;
+
+    let callback = relying_party.sign_in(&email).await;
+    let token = relying_party.exchange(&callback).await;
+    let claims = relying_party
+        .verify_id_token(&token.id_token.expect("No ID token"))
+        .await;
+
+    assert_eq!(claims.authentication.amr, vec!["pwd"]);
+    assert!(claims.authentication.auth_time <= claims.iat);
+
+    let response = relying_party.userinfo(&token.access_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let userinfo = response.json::<UserInfoResponse>().await.unwrap();
+    assert_eq!(userinfo.sub, claims.sub);
+    assert_eq!(Email::parse(userinfo.email), Email::parse(email));
+}
+
+#[tokio::test]
+async fn should_report_2fa_in_amr() {
+    let app = TestApp::new().await;
+    register_client(&app).await;
+    let email = TestApp::get_random_email();
+    signup(&app, &email, true).await;
+    let relying_party = RelyingParty::discover(&app).await;
+
+    let callback = relying_party.sign_in(&email).await;
+    let token = relying_party.exchange(&callback).await;
+    let claims = relying_party
+        .verify_id_token(&token.id_token.expect("No ID token"))
+        .await;
+
+    assert_eq!(claims.authentication.amr, vec!["pwd", "otp", "mfa"]);
+}
+
+#[tokio::test]
+async fn should_return_401_from_userinfo_without_valid_token() {
+    let app = TestApp::new().await;
+    let relying_party = RelyingParty::discover(&app).await;
+
+    let response = app.get_path("/userinfo").await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(response.headers().contains_key(WWW_AUTHENTICATE));
+
+    let response = relying_party.userinfo("not-a-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_only_answer_userinfo_for_openid_access_tokens() {
+    let app = TestApp::new().await;
+    register_client(&app).await;
+    let email = TestApp::get_random_email();
+    signup(&app, &email, false).await;
+    login(&app, &email).await;
+    let relying_party = RelyingParty::discover(&app).await;
+
+    // The login session's own token was never issued to a client
+    let response = relying_party.userinfo(&session_cookie(&app)).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(
+        response.headers()[WWW_AUTHENTICATE],
+        r#"Bearer error="invalid_token""#
+    );
+
+    // An access token the user granted without openid
+    let code = authorize(&app).await;
+    let token = app
+        .post_oauth_token(&token_form(&code))
+        .await
+        .json::<TokenResponse>()
+        .await
+        .unwrap();
+    assert!(token.id_token.is_none());
+
+    let response = relying_party.userinfo(&token.access_token).await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(
+        response.headers()[WWW_AUTHENTICATE],
+        r#"Bearer error="insufficient_scope", scope="openid""#
+    );
+}
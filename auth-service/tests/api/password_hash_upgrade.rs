@@ -0,0 +1,240 @@
+use async_trait::async_trait;
+use auth_service::domains::data_stores::{UserStore, UserStoreError};
+use auth_service::domains::email::Email;
+use auth_service::domains::password::Password;
+use auth_service::domains::password_hash::PasswordHash;
+use auth_service::domains::user::NewUser;
+use auth_service::domains::user_id::UserId;
+use auth_service::services::data_stores::hashmap_user_store::HashMapUserStore;
+use auth_service::services::data_stores::postgres_user_store::PostgresUserStore;
+use auth_service::utils::password_hashing::{HashingParams, HashingPool};
+
+use crate::helpers::{TestApp, TestDatabase, UserStoreBackend};
+
+// The hashing knobs and the import path aren't part of `UserStore`, so each
+// backend is wrapped here. Clones share their data, which lets a test
+// re-open the same users with different hashing parameters.
+#[async_trait]
+trait UpgradableUserStore: UserStore {
+    fn with_hashing_params(self, hashing_params: HashingParams) -> Self;
+    fn with_hashing_pool(self, hashing_pool: HashingPool) -> Self;
+    async fn import_user(
+        &self,
+        id: &UserId,
+        email: &Email,
+        password_hash: &PasswordHash,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError>;
+}
+
+macro_rules! impl_upgradable_user_store {
+    ($store:ty) => {
+        #[async_trait]
+        impl UpgradableUserStore for $store {
+            fn with_hashing_params(self, hashing_params: HashingParams) -> Self {
+                <$store>::with_hashing_params(self, hashing_params)
+            }
+
+            fn with_hashing_pool(self, hashing_pool: HashingPool) -> Self {
+                <$store>::with_hashing_pool(self, hashing_pool)
+            }
+
+            async fn import_user(
+                &self,
+                id: &UserId,
+                email: &Email,
+                password_hash: &PasswordHash,
+                requires_2fa: bool,
+            ) -> Result<(), UserStoreError> {
+                <$store>::import_user(self, id, email, password_hash, requires_2fa).await
+            }
+        }
+    };
+}
+
+impl_upgradable_user_store!(PostgresUserStore);
+impl_upgradable_user_store!(HashMapUserStore);
+
+// Runs `$case` against a fresh store of the suite's backend
+macro_rules! with_user_store {
+    ($case:ident) => {
+        match crate::USER_STORE_BACKEND {
+            UserStoreBackend::Postgres => {
+                let database = TestDatabase::new().await;
+                $case(PostgresUserStore::new(database.pool().clone())).await
+            }
+            UserStoreBackend::InMemory => $case(HashMapUserStore::default()).await,
+        }
+    };
+}
+
+async fn stored_hash(store: &impl UserStore, email: &Email) -> String {
+    let user = store.get_user(email).await.expect("Failed to read user");
+    user.password_hash.as_ref().to_string()
+}
+
+fn password(password: &str) -> Password {
+    Password::parse(password.to_string()).unwrap()
+}
+
+fn params(memory_kib: u32, iterations: u32) -> HashingParams {
+    HashingParams {
+        memory_kib,
+        iterations,
+        parallelism: 1,
+    }
+}
+
+async fn add_user(store: &impl UserStore) -> Email {
+    let email = Email::parse(TestApp::get_random_email()).unwrap();
+    let user = NewUser {
+        id: UserId::default(),
+        email: email.clone(),
+        password: Password::parse("password123".to_string()).unwrap(),
+        requires_2fa: false,
+    };
+    store.add_user(user).await.unwrap();
+    email
+}
+
+#[tokio::test]
+async fn should_rehash_outdated_hash_on_successful_login() {
+    with_user_store!(rehash_outdated_hash_on_successful_login)
+}
+
+async fn rehash_outdated_hash_on_successful_login(store: impl UpgradableUserStore) {
+    let old_store = store.clone().with_hashing_params(params(4096, 1));
+    let email = add_user(&old_store).await;
+
+    let old_hash = stored_hash(&store, &email).await;
+    assert!(old_hash.contains("m=4096,t=1,p=1"));
+
+    let new_store = store.clone().with_hashing_params(params(8192, 2));
+    assert_eq!(
+        new_store
+            .validate_user(&email, &password("password123"))
+            .await,
+        Ok(())
+    );
+
+    let new_hash = stored_hash(&store, &email).await;
+    assert_ne!(new_hash, old_hash);
+    assert!(new_hash.starts_with("$argon2id$v=19$m=8192,t=2,p=1$"));
+
+    // The upgraded hash keeps working and isn't rehashed again
+    assert_eq!(
+        new_store
+            .validate_user(&email, &password("password123"))
+            .await,
+        Ok(())
+    );
+    assert_eq!(stored_hash(&store, &email).await, new_hash);
+}
+
+#[tokio::test]
+async fn should_not_rehash_on_failed_login() {
+    with_user_store!(not_rehash_on_failed_login)
+}
+
+async fn not_rehash_on_failed_login(store: impl UpgradableUserStore) {
+    let old_store = store.clone().with_hashing_params(params(4096, 1));
+    let email = add_user(&old_store).await;
+    let old_hash = stored_hash(&store, &email).await;
+
+    let new_store = store.clone().with_hashing_params(params(8192, 2));
+    assert_eq!(
+        new_store
+            .validate_user(&email, &password("wrongpassword"))
+            .await,
+        Err(UserStoreError::InvalidCredentials)
+    );
+
+    assert_eq!(stored_hash(&store, &email).await, old_hash);
+}
+
+#[tokio::test]
+async fn should_upgrade_imported_bcrypt_hash_to_argon2id() {
+    with_user_store!(upgrade_imported_bcrypt_hash_to_argon2id)
+}
+
+async fn upgrade_imported_bcrypt_hash_to_argon2id(store: impl UpgradableUserStore) {
+    let store = store.with_hashing_params(params(4096, 1));
+    let email = Email::parse(TestApp::get_random_email()).unwrap();
+    let bcrypt_hash = PasswordHash::parse(bcrypt::hash("password123", 4).unwrap()).unwrap();
+
+    store
+        .import_user(&UserId::default(), &email, &bcrypt_hash, false)
+        .await
+        .unwrap();
+    assert_eq!(stored_hash(&store, &email).await, bcrypt_hash.as_ref());
+
+    assert_eq!(
+        store
+            .validate_user(&email, &password("wrongpassword"))
+            .await,
+        Err(UserStoreError::InvalidCredentials)
+    );
+    assert_eq!(stored_hash(&store, &email).await, bcrypt_hash.as_ref());
+
+    assert_eq!(
+        store.validate_user(&email, &password("password123")).await,
+        Ok(())
+    );
+    let upgraded = stored_hash(&store, &email).await;
+    assert!(upgraded.starts_with("$argon2id$v=19$m=4096,t=1,p=1$"));
+    assert_eq!(
+        store.validate_user(&email, &password("password123")).await,
+        Ok(())
+    );
+}
+
+#[tokio::test]
+async fn should_reject_import_with_unknown_hash_format_or_duplicate_email() {
+    with_user_store!(reject_import_with_unknown_hash_format_or_duplicate_email)
+}
+
+async fn reject_import_with_unknown_hash_format_or_duplicate_email(
+    store: impl UpgradableUserStore,
+) {
+    let email = Email::parse(TestApp::get_random_email()).unwrap();
+
+    // Unsalted MD5 isn't something we can verify
+    assert!(PasswordHash::parse("5f4dcc3b5aa765d61d8327deb882cf99".to_string()).is_err());
+
+    let bcrypt_hash = PasswordHash::parse(bcrypt::hash("password123", 4).unwrap()).unwrap();
+    store
+        .import_user(&UserId::default(), &email, &bcrypt_hash, false)
+        .await
+        .unwrap();
+    let result = store
+        .import_user(&UserId::default(), &email, &bcrypt_hash, true)
+        .await;
+    assert_eq!(result, Err(UserStoreError::UserAlreadyExists));
+}
+
+#[tokio::test]
+async fn should_return_unavailable_when_hashing_pool_is_saturated() {
+    with_user_store!(unavailable_when_hashing_pool_is_saturated)
+}
+
+async fn unavailable_when_hashing_pool_is_saturated(store: impl UpgradableUserStore) {
+    let hashing_pool = HashingPool::new(1, 0);
+    let store = store
+        .with_hashing_params(params(4096, 1))
+        .with_hashing_pool(hashing_pool.clone());
+    let email = add_user(&store).await;
+
+    // Park the only worker, with no queue behind it
+    let parked = hashing_pool.park_worker().await.unwrap();
+
+    assert_eq!(
+        store.validate_user(&email, &password("password123")).await,
+        Err(UserStoreError::Unavailable)
+    );
+
+    parked.release().await.unwrap();
+    assert_eq!(
+        store.validate_user(&email, &password("password123")).await,
+        Ok(())
+    );
+}
@@ -0,0 +1,138 @@
+use auth_service::routes::TokenResponse;
+
+use crate::client_credentials::{client_token, register_service, SERVICE_ID};
+use crate::helpers::{is_valid, TestApp};
+use crate::introspect::session_token;
+use crate::oauth::{authorize, register_client, token_form, CLIENT_ID};
+
+// Access token the test SPA got for a newly logged in user
+async fn spa_access_token(app: &TestApp) -> String {
+    session_token(app, false).await;
+    let code = authorize(app).await;
+    let response = app.post_oauth_token(&token_form(&code)).await;
+    response.json::<TokenResponse>().await.unwrap().access_token
+}
+
+async fn post_revoke_as_service(app: &TestApp, secret: &str, token: &str) -> reqwest::Response {
+    app.http_client
+        .post(format!("{}/oauth/revoke", &app.address))
+        .basic_auth(SERVICE_ID, Some(secret))
+        .form(&[("token", token)])
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn should_let_public_client_revoke_its_own_token() {
+    let app = TestApp::new().await;
+    register_client(&app).await;
+    let access_token = spa_access_token(&app).await;
+    assert!(is_valid(&app, &access_token, Some(CLIENT_ID)).await);
+
+    let response = app
+        .post_oauth_revoke(&[
+            ("token", access_token.as_str()),
+            ("token_type_hint", "access_token"),
+            ("client_id", CLIENT_ID),
+        ])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(!is_valid(&app, &access_token, Some(CLIENT_ID)).await);
+
+    // Revoking again, or something that was never a token, is still fine
+    for token in [access_token.as_str(), "not-a-token"] {
+        let response = app
+            .post_oauth_revoke(&[("token", token), ("client_id", CLIENT_ID)])
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[tokio::test]
+async fn should_not_revoke_tokens_of_others() {
+    let app = TestApp::new().await;
+    register_client(&app).await;
+    let secret = register_service(&app, &["introspect"]).await;
+    let access_token = spa_access_token(&app).await;
+    let session = session_token(&app, false).await;
+
+    // Other clients can't revoke the SPA's token
+    let response = post_revoke_as_service(&app, secret.as_ref(), &access_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(is_valid(&app, &access_token, Some(CLIENT_ID)).await);
+
+    // And the SPA can't end the user's login session
+    let response = app
+        .post_oauth_revoke(&[("token", session.as_str()), ("client_id", CLIENT_ID)])
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(is_valid(&app, &session, None).await);
+}
+
+#[tokio::test]
+async fn should_let_privileged_client_revoke_any_token() {
+    let app = TestApp::new().await;
+    let secret = register_service(&app, &["revoke", "admin"]).await;
+
+    let session = session_token(&app, false).await;
+    let response = post_revoke_as_service(&app, secret.as_ref(), &session).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(!is_valid(&app, &session, None).await);
+
+    // The same with a client token for the revoke scope, here revoking a
+    // client token so it's no longer good for the admin API
+    let admin_token = client_token(&app, &secret, Some("admin")).await;
+    let revoke_token = client_token(&app, &secret, Some("revoke")).await;
+    let response = app
+        .http_client
+        .post(format!("{}/oauth/revoke", &app.address))
+        .bearer_auth(&revoke_token)
+        .form(&[("token", admin_token.as_str())])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = serde_json::json!({
+        "clientId": "new-spa",
+        "name": "New SPA",
+        "redirectUris": ["https://new.example.com/callback"],
+    });
+    let response = app.post_oauth_clients(&body, &admin_token).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_require_known_client_and_token() {
+    let app = TestApp::new().await;
+    register_client(&app).await;
+    let secret = register_service(&app, &["revoke"]).await;
+    let session = session_token(&app, false).await;
+    let form = |client_id: &'static str| {
+        vec![
+            ("token", session.clone()),
+            ("client_id", client_id.to_string()),
+        ]
+    };
+
+    let response = app.post_oauth_revoke(&form("unknown-client")).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Confidential clients have to authenticate
+    let response = app.post_oauth_revoke(&form(SERVICE_ID)).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = post_revoke_as_service(&app, "not-the-secret", &session).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(is_valid(&app, &session, None).await);
+
+    let response = app
+        .http_client
+        .post(format!("{}/oauth/revoke", &app.address))
+        .basic_auth(SERVICE_ID, Some(secret.as_ref()))
+        .form(&[("token_type_hint", "refresh_token")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 400);
+}
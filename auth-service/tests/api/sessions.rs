@@ -0,0 +1,238 @@
+use crate::helpers::{is_valid, TestApp};
+use crate::introspect::session_cookie;
+use crate::oauth::{authorize, login, register_client, signup, token_form, CLIENT_ID};
+
+use auth_service::domains::email::Email;
+use auth_service::routes::{SessionInfo, SessionsResponse, TokenResponse, TwoFactorAuthResponse};
+use auth_service::utils::constants::JWT_COOKIE_NAME;
+use chrono::Duration;
+use reqwest::header::USER_AGENT;
+use reqwest::Url;
+
+async fn sessions(app: &TestApp) -> Vec<SessionInfo> {
+    let response = app.get_sessions().await;
+    assert_eq!(response.status().as_u16(), 200);
+    response.json::<SessionsResponse>().await.unwrap().sessions
+}
+
+// Logs in and returns the new session's id along with its token
+async fn new_session(app: &TestApp, email: &str) -> (String, String) {
+    login(app, email).await;
+    let current = sessions(app)
+        .await
+        .into_iter()
+        .find(|session| session.current)
+        .expect("No current session listed");
+    (current.id, session_cookie(app))
+}
+
+// Puts a session's cookie back, like switching to the device holding it
+fn use_session(app: &TestApp, token: &str) {
+    app.cookie_jar.add_cookie_str(
+        &format!(
+            "{}={}; HttpOnly; SameSite=Lax; Path=/",
+            JWT_COOKIE_NAME, token
+        ),
+        &Url::parse(&app.address).expect("Failed to parse URL"),
+    );
+}
+
+#[tokio::test]
+async fn should_list_a_session_for_each_login() {
+    let app = TestApp::new().await;
+    let email = TestApp::get_random_email();
+    signup(&app, &email, false).await;
+
+    let (first, _) = new_session(&app, &email).await;
+    let (second, _) = new_session(&app, &email).await;
+
+    let listed = sessions(&app).await;
+    assert_eq!(listed.len(), 2);
+    assert!(listed.iter().any(|s| s.id == first && !s.current));
+    assert!(listed.iter().any(|s| s.id == second && s.current));
+    assert!(listed.iter().all(|s| s.ip.as_deref() == Some("127.0.0.1")));
+}
+
+#[tokio::test]
+async fn should_record_the_user_agent_of_a_2fa_login() {
+    let app = TestApp::new().await;
+    let email = TestApp::get_random_email();
+    signup(&app, &email, true).await;
+
+    let body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app
+        .http_client
+        .post(format!("{}/login", &app.address))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .unwrap()
+        .login_attempt_id;
+    let code = app
+        .email_client
+        .last_sent_to(&Email::parse(email.clone()).unwrap())
+        .expect("No 2FA email sent")
+        .content;
+
+    let body = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code,
+    });
+    let response = app
+        .http_client
+        .post(format!("{}/verify-2fa", &app.address))
+        .header(USER_AGENT, "Mozilla/5.0 (X11; Linux x86_64)")
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+
+    let listed = sessions(&app).await;
+    assert_eq!(listed.len(), 1);
+    assert_eq!(
+        listed[0].user_agent.as_deref(),
+        Some("Mozilla/5.0 (X11; Linux x86_64)")
+    );
+}
+
+#[tokio::test]
+async fn should_end_another_session_on_delete() {
+    let app = TestApp::new().await;
+    let email = TestApp::get_random_email();
+    signup(&app, &email, false).await;
+    let (other_device, other_token) = new_session(&app, &email).await;
+    let (this_device, this_token) = new_session(&app, &email).await;
+
+    let response = app.delete_session(&other_device).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    assert!(!is_valid(&app, &other_token, None).await);
+    assert!(is_valid(&app, &this_token, None).await);
+    let listed = sessions(&app).await;
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].id, this_device);
+
+    let response = app.delete_session(&other_device).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn should_drop_the_cookie_when_ending_the_current_session() {
+    let app = TestApp::new().await;
+    let email = TestApp::get_random_email();
+    signup(&app, &email, false).await;
+    let (id, token) = new_session(&app, &email).await;
+
+    let response = app.delete_session(&id).await;
+    assert_eq!(response.status().as_u16(), 204);
+
+    assert!(!is_valid(&app, &token, None).await);
+    assert_eq!(app.get_sessions().await.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_void_oauth_grants_of_a_deleted_session() {
+    let app = TestApp::new().await;
+    register_client(&app).await;
+    let email = TestApp::get_random_email();
+    signup(&app, &email, false).await;
+
+    let (ended, _) = new_session(&app, &email).await;
+    let code = authorize(&app).await;
+    let response = app.post_oauth_token(&token_form(&code)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let access_token = response.json::<TokenResponse>().await.unwrap().access_token;
+    let pending_code = authorize(&app).await;
+
+    new_session(&app, &email).await;
+    assert_eq!(app.delete_session(&ended).await.status().as_u16(), 204);
+
+    assert!(!is_valid(&app, &access_token, Some(CLIENT_ID)).await);
+    let response = app.post_oauth_token(&token_form(&pending_code)).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    // What the session still going grants is unaffected
+    let code = authorize(&app).await;
+    let response = app.post_oauth_token(&token_form(&code)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let access_token = response.json::<TokenResponse>().await.unwrap().access_token;
+    assert!(is_valid(&app, &access_token, Some(CLIENT_ID)).await);
+}
+
+#[tokio::test]
+async fn should_return_404_for_a_session_of_another_user() {
+    let app = TestApp::new().await;
+    let victim = TestApp::get_random_email();
+    signup(&app, &victim, false).await;
+    let (victim_session, victim_token) = new_session(&app, &victim).await;
+
+    let attacker = TestApp::get_random_email();
+    signup(&app, &attacker, false).await;
+    login(&app, &attacker).await;
+
+    let response = app.delete_session(&victim_session).await;
+    assert_eq!(response.status().as_u16(), 404);
+    assert!(is_valid(&app, &victim_token, None).await);
+}
+
+#[tokio::test]
+async fn should_stop_listing_ended_sessions() {
+    let app = TestApp::new().await;
+    let email = TestApp::get_random_email();
+    signup(&app, &email, false).await;
+    let (kept, kept_token) = new_session(&app, &email).await;
+    new_session(&app, &email).await;
+
+    assert_eq!(app.post_logout().await.status().as_u16(), 200);
+    use_session(&app, &kept_token);
+    let listed = sessions(&app).await;
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].id, kept);
+
+    assert_eq!(app.post_logout_all().await.status().as_u16(), 200);
+    new_session(&app, &email).await;
+    assert_eq!(sessions(&app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn should_update_last_seen_when_the_token_is_verified() {
+    let app = TestApp::new().await;
+    let email = TestApp::get_random_email();
+    signup(&app, &email, false).await;
+    let (other_device, other_token) = new_session(&app, &email).await;
+    new_session(&app, &email).await;
+
+    app.clock.advance(Duration::minutes(2));
+    assert!(is_valid(&app, &other_token, None).await);
+
+    let listed = sessions(&app).await;
+    let other = listed.iter().find(|s| s.id == other_device).unwrap();
+    assert_ne!(other.last_seen_at, other.created_at);
+}
+
+#[tokio::test]
+async fn should_return_400_if_jwt_cookie_missing() {
+    let app = TestApp::new().await;
+
+    assert_eq!(app.get_sessions().await.status().as_u16(), 400);
+    assert_eq!(app.delete_session("unknown").await.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_token() {
+    let app = TestApp::new().await;
+    use_session(&app, "invalid");
+
+    assert_eq!(app.get_sessions().await.status().as_u16(), 401);
+    assert_eq!(app.delete_session("unknown").await.status().as_u16(), 401);
+}
@@ -1,13 +1,11 @@
 use crate::helpers::TestApp;
+use auth_service::domains::password_policy::PasswordPolicyError;
 use auth_service::routes::SignupResponse;
-use axum::{http::response, response::ErrorResponse};
 
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
     let app = TestApp::new().await;
 
-    let random_email = TestApp::get_random_email();
-
     let test_cases = [serde_json::json!({
         "password": "password123",
         "requires2FA": true
@@ -56,7 +54,6 @@ async fn should_return_400_if_invalid_input() {
     // The signup route should return a 400 HTTP status code if an invalid input is sent.
     // The input is considered invalid if:
     // - The email is empty or does not contain '@'
-    // - The password is less than 8 characters
 
     // Create an array of invalid inputs. Then, iterate through the array and
     // make HTTP calls to the signup route. Assert a 400 HTTP status code is returned.
@@ -73,11 +70,6 @@ async fn should_return_400_if_invalid_input() {
             "password": "password123",
             "requires2FA": true
         }),
-        serde_json::json!({
-            "email": "ra@gnmai.com",
-            "password": "passe",
-            "requires2FA": true
-        }),
     ];
 
     for i in test_cases.iter() {
@@ -95,6 +87,48 @@ async fn should_return_400_if_invalid_input() {
     }
 }
 
+#[tokio::test]
+async fn should_return_400_with_policy_errors_if_weak_password() {
+    let app = TestApp::new().await;
+
+    let test_case = serde_json::json!({
+        "email": "ra@gnmai.com",
+        "password": "passe",
+        "requires2FA": true
+    });
+
+    let response = app.post_signup(&test_case).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body = response
+        .json::<auth_service::ErrorResponse>()
+        .await
+        .expect("Could not deserialize response body to ErrorResponse");
+    assert_eq!(body.message, "Password does not meet the requirements");
+    assert_eq!(
+        body.errors,
+        vec![PasswordPolicyError {
+            code: "too_short".to_owned(),
+            message: "Password must be at least 8 characters".to_owned(),
+        }]
+    );
+}
+
+#[tokio::test]
+async fn should_count_password_length_in_characters() {
+    let app = TestApp::new().await;
+
+    // 8 characters, 16 bytes
+    let test_case = serde_json::json!({
+        "email": TestApp::get_random_email(),
+        "password": "éééééééé",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&test_case).await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
 #[tokio::test]
 async fn should_return_409_if_email_already_exists() {
     let app = TestApp::new().await;
@@ -123,3 +157,33 @@ async fn should_return_409_if_email_already_exists() {
         "User Already exists".to_owned()
     );
 }
+
+#[tokio::test]
+async fn should_return_409_if_email_differs_only_by_case() {
+    let app = TestApp::new().await;
+    let test_case = serde_json::json!({
+        "email": "Ravi@X.com",
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&test_case).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let test_case = serde_json::json!({
+        "email": "ravi@x.COM",
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&test_case).await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    // Logging in works whatever case the address is typed in
+    let login_body = serde_json::json!({
+        "email": "RAVI@x.com",
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -1,7 +1,8 @@
-use auth_service::domains::data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore};
+use auth_service::domains::data_stores::TWO_FA_CODE_TTL_SECONDS;
 use auth_service::domains::email::Email;
+use auth_service::routes::TwoFactorAuthResponse;
 use auth_service::utils::constants::JWT_COOKIE_NAME;
-use axum::Json;
+use chrono::Duration;
 
 use crate::helpers::TestApp;
 
@@ -47,6 +48,31 @@ async fn should_return_401_if_incorrect_credentials() {
     assert_eq!(resp.status().as_u16(), 401);
 }
 
+// Logs in like a user would and returns the attempt id from the response
+// together with the code from the email that came with it
+async fn login_and_read_code(app: &TestApp, email: &str) -> (String, String) {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let sent = app
+        .email_client
+        .last_sent_to(&Email::parse(email.to_string()).unwrap())
+        .expect("No 2FA email sent");
+    assert_eq!(sent.subject, "Security code");
+
+    (login_attempt_id, sent.content)
+}
+
 #[tokio::test]
 async fn should_return_401_if_old_code() {
     let app: TestApp = TestApp::new().await;
@@ -57,35 +83,28 @@ async fn should_return_401_if_old_code() {
         "password": "password123" ,
         "requires2FA": true,
     });
-
-    let login_body = serde_json::json!({
-        "email": email,
-        "password": "password123" ,
-    });
-
-    let email_obj = Email::parse(email.clone()).unwrap();
     let response = app.post_signup(&signup_body).await;
-    let response = app.post_login(&login_body).await;
-    let login_attempt_id: LoginAttemptId;
-    let first_code: TwoFACode;
-    {
-        let read_lock = app.two_fa_code.read().await;
-        login_attempt_id = read_lock.get_code(&email_obj).await.unwrap().0.clone();
-        first_code = read_lock.get_code(&email_obj).await.unwrap().1.clone();
-    }
+    assert_eq!(response.status().as_u16(), 201);
 
-    let response = app.post_login(&login_body).await;
-    assert_eq!(response.status().as_u16(), 206);
+    let (first_login_attempt_id, first_code) = login_and_read_code(&app, &email).await;
+    let (login_attempt_id, code) = login_and_read_code(&app, &email).await;
 
     let body = serde_json::json!({
             "email": email,
-            "loginAttemptId": login_attempt_id.as_ref(),
-            "2FACode": first_code.as_ref()
+            "loginAttemptId": first_login_attempt_id,
+            "2FACode": first_code
     });
-
     let response = app.post_verify_2fa(&body).await;
-
     assert_eq!(response.status().as_u16(), 401);
+
+    // Only the code from the latest email works
+    let body = serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code
+    });
+    let response = app.post_verify_2fa(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
 }
 
 #[tokio::test]
@@ -98,28 +117,15 @@ async fn should_return_200_if_correct_code() {
         "password": "password123" ,
         "requires2FA": true,
     });
-
-    let login_body = serde_json::json!({
-        "email": email,
-        "password": "password123" ,
-    });
-
-    let email_obj = Email::parse(email.clone()).unwrap();
     let response = app.post_signup(&signup_body).await;
-    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 201);
 
-    let login_attempt_id: LoginAttemptId;
-    let first_code: TwoFACode;
-    {
-        let read_lock = app.two_fa_code.read().await;
-        login_attempt_id = read_lock.get_code(&email_obj).await.unwrap().0.clone();
-        first_code = read_lock.get_code(&email_obj).await.unwrap().1.clone();
-    }
+    let (login_attempt_id, code) = login_and_read_code(&app, &email).await;
 
     let body = serde_json::json!({
             "email": email,
-            "loginAttemptId": login_attempt_id.as_ref(),
-            "2FACode": first_code.as_ref()
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code
     });
 
     let response = app.post_verify_2fa(&body).await;
@@ -132,4 +138,34 @@ async fn should_return_200_if_correct_code() {
         .expect("No auth cookie found");
 
     assert!(!auth_cookie.value().is_empty());
+
+    // The code is spent once used
+    let response = app.post_verify_2fa(&body).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_401_if_code_expired() {
+    let app: TestApp = TestApp::new().await;
+    let email = TestApp::get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123" ,
+        "requires2FA": true,
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let (login_attempt_id, code) = login_and_read_code(&app, &email).await;
+    app.clock
+        .advance(Duration::seconds(TWO_FA_CODE_TTL_SECONDS));
+
+    let body = serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": code
+    });
+    let response = app.post_verify_2fa(&body).await;
+    assert_eq!(response.status().as_u16(), 401);
 }
@@ -1,7 +1,13 @@
 use crate::helpers::{self, TestApp};
 use auth_service::domains::data_stores::BannedTokenStore;
 use auth_service::domains::email::Email;
-use auth_service::utils::auth::{generate_auth_token, validate_token};
+use auth_service::domains::user_id::UserId;
+use auth_service::utils::auth::{
+    generate_auth_token, validate_token, Authentication, TOKEN_TTL_SECONDS,
+};
+use auth_service::utils::clock::Clock;
+use auth_service::utils::constants::JWT_COOKIE_NAME;
+use chrono::Duration;
 
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
@@ -17,9 +23,29 @@ async fn should_return_422_if_malformed_input() {
 #[tokio::test]
 async fn should_return_200_valid_token() {
     let app = TestApp::new().await;
-    let email = Email::parse(helpers::TestApp::get_random_email()).unwrap();
+    let email = helpers::TestApp::get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false,
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
 
-    let token = generate_auth_token(&email).unwrap();
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
 
     let body = serde_json::json!({
         "token": token
@@ -27,6 +53,35 @@ async fn should_return_200_valid_token() {
 
     let resp = app.post_verify_token(&body).await;
     assert_eq!(resp.status().as_u16(), 200);
+
+    let body = serde_json::json!({
+        "token": token,
+        "audience": "someone-else",
+    });
+    let resp = app.post_verify_token(&body).await;
+    assert_eq!(resp.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_401_if_unknown_user() {
+    let app = TestApp::new().await;
+    let email = Email::parse(helpers::TestApp::get_random_email()).unwrap();
+
+    let token = generate_auth_token(
+        &UserId::default(),
+        &email,
+        0,
+        &Authentication::password(&app.clock),
+        &app.clock,
+    )
+    .unwrap();
+
+    let body = serde_json::json!({
+        "token": token
+    });
+
+    let resp = app.post_verify_token(&body).await;
+    assert_eq!(resp.status().as_u16(), 401);
 }
 
 #[tokio::test]
@@ -34,7 +89,14 @@ async fn should_return_401_if_invalid_token() {
     let app = TestApp::new().await;
     let email = Email::parse(helpers::TestApp::get_random_email()).unwrap();
 
-    let token = generate_auth_token(&email).unwrap();
+    let token = generate_auth_token(
+        &UserId::default(),
+        &email,
+        0,
+        &Authentication::password(&app.clock),
+        &app.clock,
+    )
+    .unwrap();
 
     let body = serde_json::json!({
         "token": token + "12"
@@ -47,15 +109,65 @@ async fn should_return_401_if_banned_token() {
     let app = TestApp::new().await;
     let email = Email::parse(helpers::TestApp::get_random_email()).unwrap();
 
-    let token = generate_auth_token(&email).unwrap();
+    let token = generate_auth_token(
+        &UserId::default(),
+        &email,
+        0,
+        &Authentication::password(&app.clock),
+        &app.clock,
+    )
+    .unwrap();
+
+    let claims = validate_token(&token, None, app.banned_token.clone(), &app.clock)
+        .await
+        .unwrap();
+    assert!(app
+        .banned_token
+        .add_banned_token(claims.jti, app.clock.now() + Duration::minutes(10))
+        .await
+        .is_ok());
+    let body = serde_json::json!({
+        "token": token
+    });
+    let resp = app.post_verify_token(&body).await;
+    assert_eq!(resp.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_401_if_token_expired() {
+    let app = TestApp::new().await;
+    let email = helpers::TestApp::get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false,
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
 
-    {
-        let mut guard = app.banned_token.write().await;
-        guard.add_banned_token(token.clone()).await;
-    }
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
     let body = serde_json::json!({
         "token": token
     });
+
+    app.clock.advance(Duration::seconds(TOKEN_TTL_SECONDS - 1));
+    let resp = app.post_verify_token(&body).await;
+    assert_eq!(resp.status().as_u16(), 200);
+
+    app.clock.advance(Duration::seconds(1));
     let resp = app.post_verify_token(&body).await;
     assert_eq!(resp.status().as_u16(), 401);
 }
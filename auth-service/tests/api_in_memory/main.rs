@@ -0,0 +1,41 @@
+// The API suite from tests/api with every store kept in memory, so it runs
+// without Postgres. Modules that exercise Postgres directly stay in tests/api.
+use helpers::UserStoreBackend;
+
+#[path = "../common/mod.rs"]
+mod common;
+
+#[path = "../api/change_email.rs"]
+mod change_email;
+#[path = "../api/client_credentials.rs"]
+mod client_credentials;
+#[path = "../api/concurrency.rs"]
+mod concurrency;
+#[path = "../api/helpers.rs"]
+mod helpers;
+#[path = "../api/introspect.rs"]
+mod introspect;
+#[path = "../api/login.rs"]
+mod login;
+#[path = "../api/logout.rs"]
+mod logout;
+#[path = "../api/oauth.rs"]
+mod oauth;
+#[path = "../api/oidc.rs"]
+mod oidc;
+#[path = "../api/password_hash_upgrade.rs"]
+mod password_hash_upgrade;
+#[path = "../api/revoke.rs"]
+mod revoke;
+#[path = "../api/root.rs"]
+mod root;
+#[path = "../api/sessions.rs"]
+mod sessions;
+#[path = "../api/signup.rs"]
+mod signup;
+#[path = "../api/verify_2fa.rs"]
+mod verify_2fa;
+#[path = "../api/verify_token.rs"]
+mod verify_token;
+
+const USER_STORE_BACKEND: UserStoreBackend = UserStoreBackend::InMemory;
@@ -0,0 +1,186 @@
+// Helpers shared by the test binaries. Each binary includes this with
+// `#[path = "../common/mod.rs"]`, so not every helper is used everywhere.
+#![allow(dead_code)]
+
+use auth_service::utils::constants::DATABASE_URL;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{Executor, PgPool};
+use std::str::FromStr;
+use uuid::Uuid;
+
+// Every schema-isolated test lives in this one database
+const SHARED_TEST_DATABASE: &str = "auth_service_tests";
+
+// How each test is kept apart from the others, picked with
+// `TEST_DATABASE_ISOLATION=database|schema`. A schema is much cheaper to
+// create and drop than a whole database.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Isolation {
+    Database,
+    Schema,
+}
+
+impl Isolation {
+    fn from_env() -> Self {
+        match std::env::var("TEST_DATABASE_ISOLATION").as_deref() {
+            Ok("schema") => Isolation::Schema,
+            Ok("database") | Err(_) => Isolation::Database,
+            Ok(other) => panic!("Unknown TEST_DATABASE_ISOLATION: {}", other),
+        }
+    }
+}
+
+// A migrated database (or schema) of its own for one test, dropped again
+// when this goes out of scope.
+pub struct TestDatabase {
+    pool: PgPool,
+    name: String,
+    isolation: Isolation,
+}
+
+impl TestDatabase {
+    pub async fn new() -> Self {
+        Self::with_isolation(Isolation::from_env()).await
+    }
+
+    pub async fn with_isolation(isolation: Isolation) -> Self {
+        // We are creating a new database or schema for each test case, and we need to ensure each one has a unique name!
+        let name = Uuid::new_v4().to_string();
+
+        let pool = match isolation {
+            Isolation::Database => {
+                create_database(&name).await;
+                connect(PgConnectOptions::from_str(&database_url(&name)).unwrap()).await
+            }
+            Isolation::Schema => {
+                create_shared_database().await;
+                let options = PgConnectOptions::from_str(&database_url(SHARED_TEST_DATABASE))
+                    .unwrap()
+                    .options([("search_path", name.as_str())]);
+                let pool = connect(options).await;
+                pool.execute(format!(r#"CREATE SCHEMA "{}";"#, name).as_str())
+                    .await
+                    .expect("Failed to create schema.");
+                pool
+            }
+        };
+
+        // Run migrations against the new database or schema
+        sqlx::migrate!()
+            .run(&pool)
+            .await
+            .expect("Failed to migrate the database");
+
+        Self {
+            pool,
+            name,
+            isolation,
+        }
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    async fn drop_database(isolation: Isolation, name: &str) -> Result<(), sqlx::Error> {
+        let (database, statement) = match isolation {
+            // FORCE disconnects the app's pool, which may still be open
+            Isolation::Database => (
+                None,
+                format!(r#"DROP DATABASE IF EXISTS "{}" WITH (FORCE);"#, name),
+            ),
+            Isolation::Schema => (
+                Some(SHARED_TEST_DATABASE),
+                format!(r#"DROP SCHEMA IF EXISTS "{}" CASCADE;"#, name),
+            ),
+        };
+        let url = database.map_or_else(|| DATABASE_URL.to_owned(), database_url);
+
+        let connection = PgPoolOptions::new()
+            .max_connections(1)
+            .connect(&url)
+            .await?;
+        connection.execute(statement.as_str()).await?;
+        connection.close().await;
+        Ok(())
+    }
+}
+
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        // Drop can't await, and the test's runtime may be shutting down, so
+        // clean up on a thread with a runtime of its own
+        let isolation = self.isolation;
+        let name = std::mem::take(&mut self.name);
+        let result = std::thread::spawn(move || {
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to build cleanup runtime")
+                .block_on(Self::drop_database(isolation, &name))
+        })
+        .join();
+
+        // Never panic here, a failing test may already be unwinding
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => eprintln!("Failed to drop test database: {}", e),
+            Err(_) => eprintln!("Failed to drop test database"),
+        }
+    }
+}
+
+fn database_url(name: &str) -> String {
+    format!("{}/{}", DATABASE_URL.as_str(), name)
+}
+
+async fn connect(options: PgConnectOptions) -> PgPool {
+    PgPoolOptions::new()
+        .max_connections(5)
+        .connect_with(options)
+        .await
+        .expect("Failed to create Postgres connection pool!")
+}
+
+async fn create_database(name: &str) {
+    // Create database connection
+    let connection = PgPoolOptions::new()
+        .connect(&DATABASE_URL)
+        .await
+        .expect("Failed to create Postgres connection pool.");
+
+    // Create a new database
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, name).as_str())
+        .await
+        .expect("Failed to create database.");
+}
+
+async fn create_shared_database() {
+    let connection = PgPoolOptions::new()
+        .connect(&DATABASE_URL)
+        .await
+        .expect("Failed to create Postgres connection pool.");
+
+    let exists: bool =
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM pg_database WHERE datname = $1)")
+            .bind(SHARED_TEST_DATABASE)
+            .fetch_one(&connection)
+            .await
+            .expect("Failed to look up the shared test database.");
+    if exists {
+        return;
+    }
+
+    // Tests start in parallel, another one may have just created it
+    if let Err(e) = connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, SHARED_TEST_DATABASE).as_str())
+        .await
+    {
+        let created_elsewhere = e
+            .as_database_error()
+            .and_then(|db_err| db_err.code())
+            .is_some_and(|code| code == "42P04" || code == "23505");
+        assert!(created_elsewhere, "Failed to create database: {}", e);
+    }
+}
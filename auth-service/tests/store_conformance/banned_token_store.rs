@@ -0,0 +1,158 @@
+use auth_service::domains::data_stores::{BannedTokenError, BannedTokenStore};
+use auth_service::utils::clock::{Clock, FakeClock};
+use chrono::{DateTime, Duration, Utc};
+use tokio::task::JoinSet;
+use uuid::Uuid;
+
+// The constructor is handed the clock the store should expire entries by
+#[macro_export]
+macro_rules! banned_token_store_conformance {
+    ($backend:ident, $store:expr) => {
+        mod $backend {
+            use super::*;
+
+            $crate::banned_token_store_conformance!(
+                @cases $store;
+                added_token_exists,
+                unknown_token_does_not_exist,
+                duplicate_token_is_rejected,
+                concurrent_adds_of_same_token_have_one_winner,
+                token_is_gone_once_expired,
+                expired_token_can_be_banned_again,
+                only_live_tokens_are_rejected_as_duplicates,
+            );
+        }
+    };
+    (@cases $store:expr; $($case:ident),* $(,)?) => {
+        $(
+            #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+            async fn $case() {
+                let clock = $crate::FakeClock::default();
+                let (store, _database): (_, Option<$crate::common::TestDatabase>) =
+                    ($store)(clock.clone()).await;
+                $crate::banned_token_store::$case(store, clock).await;
+            }
+        )*
+    };
+}
+
+fn random_jti() -> String {
+    Uuid::new_v4().to_string()
+}
+
+fn expires_at(clock: &FakeClock) -> DateTime<Utc> {
+    clock.now() + Duration::minutes(10)
+}
+
+pub async fn added_token_exists(store: impl BannedTokenStore, clock: FakeClock) {
+    let jti = random_jti();
+
+    assert!(store
+        .add_banned_token(jti.clone(), expires_at(&clock))
+        .await
+        .is_ok());
+    assert!(store.does_token_exist(jti).await);
+}
+
+pub async fn unknown_token_does_not_exist(store: impl BannedTokenStore, clock: FakeClock) {
+    store
+        .add_banned_token(random_jti(), expires_at(&clock))
+        .await
+        .ok();
+
+    assert!(!store.does_token_exist(random_jti()).await);
+}
+
+pub async fn duplicate_token_is_rejected(store: impl BannedTokenStore, clock: FakeClock) {
+    let jti = random_jti();
+    assert!(store
+        .add_banned_token(jti.clone(), expires_at(&clock))
+        .await
+        .is_ok());
+
+    assert!(matches!(
+        store
+            .add_banned_token(jti.clone(), expires_at(&clock))
+            .await,
+        Err(BannedTokenError::TokenExists)
+    ));
+    assert!(store.does_token_exist(jti).await);
+}
+
+pub async fn concurrent_adds_of_same_token_have_one_winner(
+    store: impl BannedTokenStore,
+    clock: FakeClock,
+) {
+    let jti = random_jti();
+
+    let mut tasks = JoinSet::new();
+    for _ in 0..16 {
+        let store = store.clone();
+        let jti = jti.clone();
+        let expires_at = expires_at(&clock);
+        tasks.spawn(async move { store.add_banned_token(jti, expires_at).await.is_ok() });
+    }
+
+    let mut added = 0;
+    while let Some(result) = tasks.join_next().await {
+        added += result.unwrap() as usize;
+    }
+
+    assert_eq!(added, 1);
+    assert!(store.does_token_exist(jti).await);
+}
+
+pub async fn token_is_gone_once_expired(store: impl BannedTokenStore, clock: FakeClock) {
+    let jti = random_jti();
+    store
+        .add_banned_token(jti.clone(), expires_at(&clock))
+        .await
+        .ok();
+
+    clock.advance(Duration::minutes(10) - Duration::seconds(1));
+    assert!(store.does_token_exist(jti.clone()).await);
+
+    clock.advance(Duration::seconds(1));
+    assert!(!store.does_token_exist(jti).await);
+}
+
+pub async fn expired_token_can_be_banned_again(store: impl BannedTokenStore, clock: FakeClock) {
+    let jti = random_jti();
+    store
+        .add_banned_token(jti.clone(), expires_at(&clock))
+        .await
+        .ok();
+    clock.advance(Duration::minutes(10));
+
+    assert!(store
+        .add_banned_token(jti.clone(), expires_at(&clock))
+        .await
+        .is_ok());
+    assert!(store.does_token_exist(jti).await);
+}
+
+pub async fn only_live_tokens_are_rejected_as_duplicates(
+    store: impl BannedTokenStore,
+    clock: FakeClock,
+) {
+    let soon = random_jti();
+    let later = random_jti();
+    store
+        .add_banned_token(soon.clone(), clock.now() + Duration::minutes(1))
+        .await
+        .ok();
+    store
+        .add_banned_token(later.clone(), expires_at(&clock))
+        .await
+        .ok();
+    clock.advance(Duration::minutes(1));
+
+    assert!(store
+        .add_banned_token(soon, expires_at(&clock))
+        .await
+        .is_ok());
+    assert!(matches!(
+        store.add_banned_token(later, expires_at(&clock)).await,
+        Err(BannedTokenError::TokenExists)
+    ));
+}
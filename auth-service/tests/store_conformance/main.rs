@@ -0,0 +1,81 @@
+// Every store implementation runs the same cases, so a new backend only has
+// to plug its constructor into one of the macros below to be held to the
+// behaviour the routes rely on.
+
+#[path = "../common/mod.rs"]
+mod common;
+
+mod banned_token_store;
+mod oauth_client_store;
+mod session_store;
+mod two_fa_code_store;
+mod user_store;
+
+use auth_service::services::data_stores::hashmap_oauth_client_store::HashmapOAuthClientStore;
+use auth_service::services::data_stores::hashmap_session_store::HashmapSessionStore;
+use auth_service::services::data_stores::hashmap_two_fa_code_store::HashmapTwoFACodeStore;
+use auth_service::services::data_stores::hashmap_user_store::HashMapUserStore;
+use auth_service::services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore;
+use auth_service::services::data_stores::postgres_oauth_client_store::PostgresOAuthClientStore;
+use auth_service::services::data_stores::postgres_session_store::PostgresSessionStore;
+use auth_service::services::data_stores::postgres_user_store::PostgresUserStore;
+use auth_service::utils::clock::FakeClock;
+use auth_service::utils::password_hashing::HashingParams;
+use common::TestDatabase;
+use std::sync::Arc;
+
+// Cheap enough that the suite is about the stores, not about Argon2
+fn hashing_params() -> HashingParams {
+    HashingParams {
+        memory_kib: 4096,
+        iterations: 1,
+        parallelism: 1,
+    }
+}
+
+// Each constructor also hands back the test database, if any, so it lives
+// as long as the test does
+user_store_conformance!(hashmap_user_store, async {
+    let store = HashMapUserStore::default().with_hashing_params(hashing_params());
+    (store, None)
+});
+
+user_store_conformance!(postgres_user_store, async {
+    let database = TestDatabase::new().await;
+    let store =
+        PostgresUserStore::new(database.pool().clone()).with_hashing_params(hashing_params());
+    (store, Some(database))
+});
+
+banned_token_store_conformance!(hashset_banned_token_store, |clock: FakeClock| async move {
+    let store = HashsetBannedTokenStore::default().with_clock(Arc::new(clock));
+    (store, None)
+});
+
+two_fa_code_store_conformance!(hashmap_two_fa_code_store, |clock: FakeClock| async move {
+    let store = HashmapTwoFACodeStore::default().with_clock(Arc::new(clock));
+    (store, None)
+});
+
+oauth_client_store_conformance!(hashmap_oauth_client_store, async {
+    (HashmapOAuthClientStore::default(), None)
+});
+
+oauth_client_store_conformance!(postgres_oauth_client_store, async {
+    let database = TestDatabase::new().await;
+    let store = PostgresOAuthClientStore::new(database.pool().clone());
+    (store, Some(database))
+});
+
+session_store_conformance!(hashmap_session_store, async {
+    let users = HashMapUserStore::default().with_hashing_params(hashing_params());
+    (HashmapSessionStore::default(), users, None)
+});
+
+session_store_conformance!(postgres_session_store, async {
+    let database = TestDatabase::new().await;
+    let users =
+        PostgresUserStore::new(database.pool().clone()).with_hashing_params(hashing_params());
+    let store = PostgresSessionStore::new(database.pool().clone());
+    (store, users, Some(database))
+});
@@ -0,0 +1,111 @@
+use auth_service::domains::client_secret::ClientSecret;
+use auth_service::domains::data_stores::{OAuthClientStore, OAuthClientStoreError};
+use auth_service::domains::oauth_client::OAuthClient;
+use tokio::task::JoinSet;
+use uuid::Uuid;
+
+#[macro_export]
+macro_rules! oauth_client_store_conformance {
+    ($backend:ident, $store:expr) => {
+        mod $backend {
+            use super::*;
+
+            $crate::oauth_client_store_conformance!(
+                @cases $store;
+                added_client_can_be_found,
+                confidential_client_keeps_secret_and_scopes,
+                unknown_client_is_not_found,
+                duplicate_client_id_is_rejected,
+                concurrent_adds_of_same_client_have_one_winner,
+            );
+        }
+    };
+    (@cases $store:expr; $($case:ident),* $(,)?) => {
+        $(
+            #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+            async fn $case() {
+                let (store, _database): (_, Option<$crate::common::TestDatabase>) =
+                    $store.await;
+                $crate::oauth_client_store::$case(store).await;
+            }
+        )*
+    };
+}
+
+fn random_client() -> OAuthClient {
+    OAuthClient::new(
+        Uuid::new_v4().to_string(),
+        "Single page app".to_string(),
+        vec![
+            "https://app.example.com/callback".to_string(),
+            "http://localhost:8000/callback".to_string(),
+        ],
+    )
+    .unwrap()
+}
+
+pub async fn added_client_can_be_found(store: impl OAuthClientStore) {
+    let client = random_client();
+
+    assert!(store.add_client(client.clone()).await.is_ok());
+    assert_eq!(store.get_client(&client.client_id).await, Ok(client));
+}
+
+pub async fn confidential_client_keeps_secret_and_scopes(store: impl OAuthClientStore) {
+    let secret = ClientSecret::generate();
+    let client = OAuthClient::confidential(
+        Uuid::new_v4().to_string(),
+        "Reporting service".to_string(),
+        secret.hash(),
+        vec!["introspect".to_string(), "admin".to_string()],
+    )
+    .unwrap();
+
+    assert!(store.add_client(client.clone()).await.is_ok());
+    let found = store.get_client(&client.client_id).await.unwrap();
+    assert_eq!(found, client);
+    assert!(found.authenticate(&secret));
+}
+
+pub async fn unknown_client_is_not_found(store: impl OAuthClientStore) {
+    store.add_client(random_client()).await.ok();
+
+    assert_eq!(
+        store.get_client(&Uuid::new_v4().to_string()).await,
+        Err(OAuthClientStoreError::ClientNotFound)
+    );
+}
+
+pub async fn duplicate_client_id_is_rejected(store: impl OAuthClientStore) {
+    let client = random_client();
+    assert!(store.add_client(client.clone()).await.is_ok());
+
+    let mut other = random_client();
+    other.client_id = client.client_id.clone();
+    other.redirect_uris = vec!["https://evil.example.com/callback".to_string()];
+    assert_eq!(
+        store.add_client(other).await,
+        Err(OAuthClientStoreError::ClientAlreadyExists)
+    );
+
+    // The original registration is untouched
+    assert_eq!(store.get_client(&client.client_id).await, Ok(client));
+}
+
+pub async fn concurrent_adds_of_same_client_have_one_winner(store: impl OAuthClientStore) {
+    let client = random_client();
+
+    let mut tasks = JoinSet::new();
+    for _ in 0..16 {
+        let store = store.clone();
+        let client = client.clone();
+        tasks.spawn(async move { store.add_client(client).await.is_ok() });
+    }
+
+    let mut added = 0;
+    while let Some(result) = tasks.join_next().await {
+        added += result.unwrap() as usize;
+    }
+
+    assert_eq!(added, 1);
+}
@@ -0,0 +1,272 @@
+use auth_service::domains::data_stores::{
+    SessionStore, SessionStoreError, UserStore, SESSION_LAST_SEEN_RESOLUTION_SECONDS,
+};
+use auth_service::domains::email::Email;
+use auth_service::domains::password::Password;
+use auth_service::domains::session::Session;
+use auth_service::domains::user::NewUser;
+use auth_service::domains::user_id::UserId;
+use auth_service::services::data_stores::postgres_session_store::PostgresSessionStore;
+use auth_service::services::data_stores::postgres_user_store::PostgresUserStore;
+use auth_service::utils::clock::{Clock, FakeClock};
+use chrono::{DateTime, Duration, SubsecRound, Utc};
+use std::sync::Arc;
+use tokio::task::JoinSet;
+use uuid::Uuid;
+
+// Sessions belong to users, so the constructor also hands back a user store
+// to add them to
+#[macro_export]
+macro_rules! session_store_conformance {
+    ($backend:ident, $store:expr) => {
+        mod $backend {
+            use super::*;
+
+            $crate::session_store_conformance!(
+                @cases $store;
+                added_sessions_are_listed_newest_first,
+                sessions_of_other_users_are_not_listed,
+                duplicate_session_id_is_rejected,
+                expired_sessions_can_be_told_from_active_ones,
+                touch_moves_last_seen_forward_only,
+                touching_unknown_session_is_ignored,
+                removed_session_is_handed_back_and_gone,
+                sessions_of_other_users_cannot_be_removed,
+                remove_sessions_forgets_all_of_the_user,
+                concurrent_removes_of_same_session_have_one_winner,
+            );
+        }
+    };
+    (@cases $store:expr; $($case:ident),* $(,)?) => {
+        $(
+            #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+            async fn $case() {
+                let (store, users, _database): (_, _, Option<$crate::common::TestDatabase>) =
+                    $store.await;
+                $crate::session_store::$case(store, users).await;
+            }
+        )*
+    };
+}
+
+// Whole seconds, which every backend stores exactly
+fn now() -> DateTime<Utc> {
+    Utc::now().trunc_subsecs(0)
+}
+
+async fn add_user(users: &impl UserStore) -> UserId {
+    let id = UserId::default();
+    users
+        .add_user(NewUser {
+            id,
+            email: Email::parse(format!("{}@example.com", Uuid::new_v4())).unwrap(),
+            password: Password::parse("Password123".to_string()).unwrap(),
+            requires_2fa: false,
+        })
+        .await
+        .expect("Failed to add user");
+    id
+}
+
+fn session(user_id: UserId, created_at: DateTime<Utc>) -> Session {
+    Session::new(
+        Uuid::new_v4().to_string(),
+        user_id,
+        created_at,
+        created_at + Duration::minutes(10),
+        Some("127.0.0.1".to_string()),
+        Some("Mozilla/5.0".to_string()),
+    )
+}
+
+pub async fn added_sessions_are_listed_newest_first(
+    store: impl SessionStore,
+    users: impl UserStore,
+) {
+    let user_id = add_user(&users).await;
+    let older = session(user_id, now() - Duration::minutes(1));
+    let newer = Session {
+        ip: None,
+        user_agent: None,
+        ..session(user_id, now())
+    };
+
+    assert!(store.add_session(older.clone()).await.is_ok());
+    assert!(store.add_session(newer.clone()).await.is_ok());
+    assert_eq!(store.get_sessions(&user_id).await, Ok(vec![newer, older]));
+}
+
+pub async fn sessions_of_other_users_are_not_listed(
+    store: impl SessionStore,
+    users: impl UserStore,
+) {
+    let user_id = add_user(&users).await;
+    let other_id = add_user(&users).await;
+    store.add_session(session(other_id, now())).await.unwrap();
+
+    assert_eq!(store.get_sessions(&user_id).await, Ok(vec![]));
+}
+
+pub async fn duplicate_session_id_is_rejected(store: impl SessionStore, users: impl UserStore) {
+    let user_id = add_user(&users).await;
+    let session = session(user_id, now());
+    store.add_session(session.clone()).await.unwrap();
+
+    assert_eq!(
+        store.add_session(session.clone()).await,
+        Err(SessionStoreError::SessionAlreadyExists)
+    );
+    assert_eq!(store.get_sessions(&user_id).await, Ok(vec![session]));
+}
+
+// Stores may keep expired sessions until they sweep them, but a login never
+// touches other users' sessions
+pub async fn expired_sessions_can_be_told_from_active_ones(
+    store: impl SessionStore,
+    users: impl UserStore,
+) {
+    let user_id = add_user(&users).await;
+    let other_id = add_user(&users).await;
+    let long_ago = now() - Duration::hours(1);
+    store.add_session(session(user_id, long_ago)).await.unwrap();
+    let others = session(other_id, long_ago);
+    store.add_session(others.clone()).await.unwrap();
+
+    let current = session(user_id, now());
+    store.add_session(current.clone()).await.unwrap();
+
+    let sessions = store.get_sessions(&user_id).await.unwrap();
+    assert_eq!(sessions.first(), Some(&current));
+    assert!(sessions[1..].iter().all(|s| s.is_expired(now())));
+    assert_eq!(store.get_sessions(&other_id).await, Ok(vec![others]));
+}
+
+pub async fn touch_moves_last_seen_forward_only(store: impl SessionStore, users: impl UserStore) {
+    let user_id = add_user(&users).await;
+    let created_at = now();
+    let session = session(user_id, created_at);
+    store.add_session(session.clone()).await.unwrap();
+
+    let seen_at = created_at + Duration::minutes(2);
+    assert!(store.touch_session(&session.id, seen_at).await.is_ok());
+    assert!(store
+        .touch_session(&session.id, created_at + Duration::minutes(1))
+        .await
+        .is_ok());
+
+    let sessions = store.get_sessions(&user_id).await.unwrap();
+    assert_eq!(sessions[0].last_seen_at, seen_at);
+    assert_eq!(sessions[0].created_at, created_at);
+}
+
+pub async fn touching_unknown_session_is_ignored(store: impl SessionStore, users: impl UserStore) {
+    let user_id = add_user(&users).await;
+    let session = session(user_id, now());
+    store.add_session(session.clone()).await.unwrap();
+
+    assert!(store
+        .touch_session(&Uuid::new_v4().to_string(), now() + Duration::minutes(2))
+        .await
+        .is_ok());
+    assert_eq!(store.get_sessions(&user_id).await, Ok(vec![session]));
+}
+
+// Only Postgres remembers its writes of `last_seen_at`, to skip touches
+// closer together than the resolution
+#[tokio::test]
+async fn postgres_forgets_its_writes_once_older_than_the_resolution() {
+    let database = crate::common::TestDatabase::new().await;
+    let clock = FakeClock::new(now());
+    let users = PostgresUserStore::new(database.pool().clone())
+        .with_hashing_params(crate::hashing_params());
+    let store =
+        PostgresSessionStore::new(database.pool().clone()).with_clock(Arc::new(clock.clone()));
+    let user_id = add_user(&users).await;
+    store
+        .add_session(session(user_id, clock.now()))
+        .await
+        .unwrap();
+
+    let resolution = Duration::seconds(SESSION_LAST_SEEN_RESOLUTION_SECONDS);
+    clock.advance(resolution - Duration::seconds(1));
+    assert_eq!(store.remove_expired(), 0);
+
+    clock.advance(Duration::seconds(1));
+    assert_eq!(store.remove_expired(), 1);
+}
+
+pub async fn removed_session_is_handed_back_and_gone(
+    store: impl SessionStore,
+    users: impl UserStore,
+) {
+    let user_id = add_user(&users).await;
+    let removed = session(user_id, now() - Duration::minutes(1));
+    let kept = session(user_id, now());
+    store.add_session(removed.clone()).await.unwrap();
+    store.add_session(kept.clone()).await.unwrap();
+
+    assert_eq!(
+        store.remove_session(&user_id, &removed.id).await,
+        Ok(removed.clone())
+    );
+    assert_eq!(store.get_sessions(&user_id).await, Ok(vec![kept]));
+    assert_eq!(
+        store.remove_session(&user_id, &removed.id).await,
+        Err(SessionStoreError::SessionNotFound)
+    );
+}
+
+pub async fn sessions_of_other_users_cannot_be_removed(
+    store: impl SessionStore,
+    users: impl UserStore,
+) {
+    let user_id = add_user(&users).await;
+    let other_id = add_user(&users).await;
+    let others = session(other_id, now());
+    store.add_session(others.clone()).await.unwrap();
+
+    assert_eq!(
+        store.remove_session(&user_id, &others.id).await,
+        Err(SessionStoreError::SessionNotFound)
+    );
+    assert_eq!(store.get_sessions(&other_id).await, Ok(vec![others]));
+}
+
+pub async fn remove_sessions_forgets_all_of_the_user(
+    store: impl SessionStore,
+    users: impl UserStore,
+) {
+    let user_id = add_user(&users).await;
+    let other_id = add_user(&users).await;
+    store.add_session(session(user_id, now())).await.unwrap();
+    store.add_session(session(user_id, now())).await.unwrap();
+    let others = session(other_id, now());
+    store.add_session(others.clone()).await.unwrap();
+
+    assert!(store.remove_sessions(&user_id).await.is_ok());
+    assert_eq!(store.get_sessions(&user_id).await, Ok(vec![]));
+    assert_eq!(store.get_sessions(&other_id).await, Ok(vec![others]));
+}
+
+pub async fn concurrent_removes_of_same_session_have_one_winner(
+    store: impl SessionStore,
+    users: impl UserStore,
+) {
+    let user_id = add_user(&users).await;
+    let session = session(user_id, now());
+    store.add_session(session.clone()).await.unwrap();
+
+    let mut tasks = JoinSet::new();
+    for _ in 0..16 {
+        let store = store.clone();
+        let id = session.id.clone();
+        tasks.spawn(async move { store.remove_session(&user_id, &id).await.is_ok() });
+    }
+
+    let mut removed = 0;
+    while let Some(result) = tasks.join_next().await {
+        removed += result.unwrap() as usize;
+    }
+
+    assert_eq!(removed, 1);
+}
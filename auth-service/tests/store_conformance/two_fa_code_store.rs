@@ -0,0 +1,305 @@
+use auth_service::domains::data_stores::{
+    LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError, TWO_FA_CODE_TTL_SECONDS,
+};
+use auth_service::domains::email::Email;
+use auth_service::utils::clock::FakeClock;
+use chrono::Duration;
+use tokio::task::JoinSet;
+use uuid::Uuid;
+
+// The constructor is handed the clock the store should expire codes by
+#[macro_export]
+macro_rules! two_fa_code_store_conformance {
+    ($backend:ident, $store:expr) => {
+        mod $backend {
+            use super::*;
+
+            $crate::two_fa_code_store_conformance!(
+                @cases $store;
+                added_code_can_be_read_back,
+                unknown_email_is_not_found,
+                adding_again_overwrites_the_code,
+                removed_code_is_gone,
+                removing_unknown_email_is_not_found,
+                concurrent_removes_have_one_winner,
+                taking_needs_the_matching_attempt_and_code,
+                concurrent_takes_have_one_winner,
+                expired_code_cannot_be_taken,
+                code_expires_after_its_ttl,
+                new_code_restarts_the_ttl,
+            );
+        }
+    };
+    (@cases $store:expr; $($case:ident),* $(,)?) => {
+        $(
+            #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+            async fn $case() {
+                let clock = $crate::FakeClock::default();
+                let (store, _database): (_, Option<$crate::common::TestDatabase>) =
+                    ($store)(clock.clone()).await;
+                $crate::two_fa_code_store::$case(store, clock).await;
+            }
+        )*
+    };
+}
+
+fn random_email() -> Email {
+    Email::parse(format!("{}@example.com", Uuid::new_v4())).unwrap()
+}
+
+pub async fn added_code_can_be_read_back(store: impl TwoFACodeStore, _clock: FakeClock) {
+    let email = random_email();
+    let login_attempt_id = LoginAttemptId::default();
+    let code = TwoFACode::default();
+
+    assert_eq!(
+        store
+            .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+            .await,
+        Ok(())
+    );
+    assert_eq!(store.get_code(&email).await, Ok((login_attempt_id, code)));
+}
+
+pub async fn unknown_email_is_not_found(store: impl TwoFACodeStore, _clock: FakeClock) {
+    store
+        .add_code(
+            random_email(),
+            LoginAttemptId::default(),
+            TwoFACode::default(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(
+        store.get_code(&random_email()).await,
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+    );
+}
+
+// A new login replaces the pending attempt, so only the latest code works
+pub async fn adding_again_overwrites_the_code(store: impl TwoFACodeStore, _clock: FakeClock) {
+    let email = random_email();
+    store
+        .add_code(
+            email.clone(),
+            LoginAttemptId::default(),
+            TwoFACode::default(),
+        )
+        .await
+        .unwrap();
+
+    let login_attempt_id = LoginAttemptId::default();
+    let code = TwoFACode::default();
+    assert_eq!(
+        store
+            .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+            .await,
+        Ok(())
+    );
+    assert_eq!(store.get_code(&email).await, Ok((login_attempt_id, code)));
+}
+
+pub async fn removed_code_is_gone(store: impl TwoFACodeStore, _clock: FakeClock) {
+    let email = random_email();
+    let other = random_email();
+    for email in [&email, &other] {
+        store
+            .add_code(
+                email.clone(),
+                LoginAttemptId::default(),
+                TwoFACode::default(),
+            )
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(store.remove_code(&email).await, Ok(()));
+    assert_eq!(
+        store.get_code(&email).await,
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+    );
+    // Other users' codes are untouched
+    assert!(store.get_code(&other).await.is_ok());
+}
+
+pub async fn removing_unknown_email_is_not_found(store: impl TwoFACodeStore, _clock: FakeClock) {
+    assert_eq!(
+        store.remove_code(&random_email()).await,
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+    );
+}
+
+// verify-2fa relies on this so one code can't be redeemed twice
+pub async fn concurrent_removes_have_one_winner(store: impl TwoFACodeStore, _clock: FakeClock) {
+    let email = random_email();
+    store
+        .add_code(
+            email.clone(),
+            LoginAttemptId::default(),
+            TwoFACode::default(),
+        )
+        .await
+        .unwrap();
+
+    let mut tasks = JoinSet::new();
+    for _ in 0..16 {
+        let store = store.clone();
+        let email = email.clone();
+        tasks.spawn(async move { store.remove_code(&email).await });
+    }
+
+    let mut results = Vec::new();
+    while let Some(result) = tasks.join_next().await {
+        results.push(result.unwrap());
+    }
+
+    assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+    assert!(results
+        .iter()
+        .filter(|r| r.is_err())
+        .all(|r| r == &Err(TwoFACodeStoreError::LoginAttemptIdNotFound)));
+}
+
+pub async fn taking_needs_the_matching_attempt_and_code(
+    store: impl TwoFACodeStore,
+    _clock: FakeClock,
+) {
+    let email = random_email();
+    let stale_login_attempt_id = LoginAttemptId::default();
+    let stale_code = TwoFACode::parse("1234".to_string()).unwrap();
+    store
+        .add_code(
+            email.clone(),
+            stale_login_attempt_id.clone(),
+            stale_code.clone(),
+        )
+        .await
+        .unwrap();
+    let login_attempt_id = LoginAttemptId::default();
+    let code = TwoFACode::parse("5678".to_string()).unwrap();
+    store
+        .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+        .await
+        .unwrap();
+
+    // Neither the replaced attempt nor a mix of the two removes the new code
+    for (attempt, guess) in [
+        (&stale_login_attempt_id, &stale_code),
+        (&stale_login_attempt_id, &code),
+        (&login_attempt_id, &stale_code),
+    ] {
+        assert_eq!(
+            store.take_code_if(&email, attempt, guess).await,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        );
+    }
+    assert_eq!(
+        store.get_code(&email).await,
+        Ok((login_attempt_id.clone(), code.clone()))
+    );
+
+    assert_eq!(
+        store.take_code_if(&email, &login_attempt_id, &code).await,
+        Ok(())
+    );
+    assert_eq!(
+        store.get_code(&email).await,
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+    );
+}
+
+pub async fn concurrent_takes_have_one_winner(store: impl TwoFACodeStore, _clock: FakeClock) {
+    let email = random_email();
+    let login_attempt_id = LoginAttemptId::default();
+    let code = TwoFACode::default();
+    store
+        .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+        .await
+        .unwrap();
+
+    let mut tasks = JoinSet::new();
+    for _ in 0..16 {
+        let store = store.clone();
+        let email = email.clone();
+        let login_attempt_id = login_attempt_id.clone();
+        let code = code.clone();
+        tasks.spawn(async move { store.take_code_if(&email, &login_attempt_id, &code).await });
+    }
+
+    let mut results = Vec::new();
+    while let Some(result) = tasks.join_next().await {
+        results.push(result.unwrap());
+    }
+
+    assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+    assert!(results
+        .iter()
+        .filter(|r| r.is_err())
+        .all(|r| r == &Err(TwoFACodeStoreError::LoginAttemptIdNotFound)));
+}
+
+pub async fn expired_code_cannot_be_taken(store: impl TwoFACodeStore, clock: FakeClock) {
+    let email = random_email();
+    let login_attempt_id = LoginAttemptId::default();
+    let code = TwoFACode::default();
+    store
+        .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+        .await
+        .unwrap();
+
+    clock.advance(Duration::seconds(TWO_FA_CODE_TTL_SECONDS));
+    assert_eq!(
+        store.take_code_if(&email, &login_attempt_id, &code).await,
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+    );
+}
+
+pub async fn code_expires_after_its_ttl(store: impl TwoFACodeStore, clock: FakeClock) {
+    let email = random_email();
+    store
+        .add_code(
+            email.clone(),
+            LoginAttemptId::default(),
+            TwoFACode::default(),
+        )
+        .await
+        .unwrap();
+
+    clock.advance(Duration::seconds(TWO_FA_CODE_TTL_SECONDS - 1));
+    assert!(store.get_code(&email).await.is_ok());
+
+    clock.advance(Duration::seconds(1));
+    assert_eq!(
+        store.get_code(&email).await,
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+    );
+    assert_eq!(
+        store.remove_code(&email).await,
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+    );
+}
+
+pub async fn new_code_restarts_the_ttl(store: impl TwoFACodeStore, clock: FakeClock) {
+    let email = random_email();
+    store
+        .add_code(
+            email.clone(),
+            LoginAttemptId::default(),
+            TwoFACode::default(),
+        )
+        .await
+        .unwrap();
+
+    clock.advance(Duration::seconds(TWO_FA_CODE_TTL_SECONDS - 1));
+    let login_attempt_id = LoginAttemptId::default();
+    let code = TwoFACode::default();
+    store
+        .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+        .await
+        .unwrap();
+
+    clock.advance(Duration::seconds(TWO_FA_CODE_TTL_SECONDS - 1));
+    assert_eq!(store.get_code(&email).await, Ok((login_attempt_id, code)));
+    assert_eq!(store.remove_code(&email).await, Ok(()));
+}
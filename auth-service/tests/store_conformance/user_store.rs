@@ -0,0 +1,352 @@
+use auth_service::domains::data_stores::{UserStore, UserStoreError};
+use auth_service::domains::email::Email;
+use auth_service::domains::password::Password;
+use auth_service::domains::user::NewUser;
+use auth_service::domains::user_id::UserId;
+use auth_service::services::data_stores::postgres_user_store::PostgresUserStore;
+use auth_service::utils::clock::FakeClock;
+use chrono::Duration;
+use std::sync::Arc;
+use tokio::task::JoinSet;
+use uuid::Uuid;
+
+// One `#[tokio::test]` per case, so a failure names both the backend and the case
+#[macro_export]
+macro_rules! user_store_conformance {
+    ($backend:ident, $store:expr) => {
+        mod $backend {
+            use super::*;
+
+            $crate::user_store_conformance!(
+                @cases $store;
+                add_then_get_by_email_and_id,
+                duplicate_email_is_rejected,
+                unknown_user_is_not_found,
+                only_the_password_hash_is_stored,
+                validate_user_checks_the_password,
+                update_email_moves_the_user,
+                update_email_rejects_taken_or_unknown,
+                end_all_sessions_bumps_the_session_version,
+                update_email_ends_all_sessions,
+                concurrent_ends_of_all_sessions_all_count,
+                concurrent_email_change_and_ends_of_all_sessions_all_count,
+                concurrent_adds_with_same_email_have_one_winner,
+                concurrent_adds_with_distinct_emails_all_land,
+            );
+        }
+    };
+    (@cases $store:expr; $($case:ident),* $(,)?) => {
+        $(
+            #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+            async fn $case() {
+                let (store, _database): (_, Option<$crate::common::TestDatabase>) =
+                    $store.await;
+                $crate::user_store::$case(store).await;
+            }
+        )*
+    };
+}
+
+const PASSWORD: &str = "Password123";
+
+fn password(password: &str) -> Password {
+    Password::parse(password.to_string()).unwrap()
+}
+
+fn random_email() -> Email {
+    Email::parse(format!("{}@example.com", Uuid::new_v4())).unwrap()
+}
+
+fn new_user(id: UserId, email: Email) -> NewUser {
+    NewUser {
+        id,
+        email,
+        password: Password::parse(PASSWORD.to_string()).unwrap(),
+        requires_2fa: false,
+    }
+}
+
+async fn add(store: &impl UserStore) -> (UserId, Email) {
+    let id = UserId::default();
+    let email = random_email();
+    store
+        .add_user(new_user(id, email.clone()))
+        .await
+        .expect("Failed to add user");
+    (id, email)
+}
+
+pub async fn add_then_get_by_email_and_id(store: impl UserStore) {
+    let id = UserId::default();
+    let email = random_email();
+    let user = NewUser {
+        requires_2fa: true,
+        ..new_user(id, email.clone())
+    };
+    assert_eq!(store.add_user(user).await, Ok(()));
+
+    let by_email = store.get_user(&email).await.unwrap();
+    assert_eq!(by_email.id, id);
+    assert_eq!(by_email.email, email);
+    assert!(by_email.requires_2fa);
+
+    let by_id = store.get_user_by_id(&id).await.unwrap();
+    assert_eq!(by_id, by_email);
+}
+
+pub async fn duplicate_email_is_rejected(store: impl UserStore) {
+    let (id, email) = add(&store).await;
+
+    // Emails are case-insensitive
+    let shouted = Email::parse(email.as_ref().to_uppercase()).unwrap();
+    assert_eq!(
+        store.add_user(new_user(UserId::default(), shouted)).await,
+        Err(UserStoreError::UserAlreadyExists)
+    );
+
+    // The first user is left as it was
+    assert_eq!(store.get_user(&email).await.unwrap().id, id);
+}
+
+pub async fn unknown_user_is_not_found(store: impl UserStore) {
+    add(&store).await;
+
+    assert_eq!(
+        store.get_user(&random_email()).await,
+        Err(UserStoreError::UserNotFound)
+    );
+    assert_eq!(
+        store.get_user_by_id(&UserId::default()).await,
+        Err(UserStoreError::UserNotFound)
+    );
+}
+
+pub async fn only_the_password_hash_is_stored(store: impl UserStore) {
+    let (_, email) = add(&store).await;
+
+    let user = store.get_user(&email).await.unwrap();
+    assert!(user.password_hash.as_ref().starts_with("$argon2id$"));
+    assert!(!user.password_hash.as_ref().contains(PASSWORD));
+}
+
+pub async fn validate_user_checks_the_password(store: impl UserStore) {
+    let (_, email) = add(&store).await;
+
+    assert_eq!(
+        store.validate_user(&email, &password(PASSWORD)).await,
+        Ok(())
+    );
+    assert_eq!(
+        store.validate_user(&email, &password("Password124")).await,
+        Err(UserStoreError::InvalidCredentials)
+    );
+    // Unknown users look exactly like a wrong password
+    assert_eq!(
+        store
+            .validate_user(&random_email(), &password(PASSWORD))
+            .await,
+        Err(UserStoreError::InvalidCredentials)
+    );
+}
+
+pub async fn update_email_moves_the_user(store: impl UserStore) {
+    let (id, old_email) = add(&store).await;
+    let new_email = random_email();
+
+    assert_eq!(store.update_email(&id, new_email.clone()).await, Ok(()));
+
+    assert_eq!(
+        store.get_user(&old_email).await,
+        Err(UserStoreError::UserNotFound)
+    );
+    assert_eq!(store.get_user(&new_email).await.unwrap().id, id);
+    assert_eq!(store.get_user_by_id(&id).await.unwrap().email, new_email);
+    assert_eq!(
+        store.validate_user(&new_email, &password(PASSWORD)).await,
+        Ok(())
+    );
+
+    // The old address is free again
+    assert_eq!(
+        store.add_user(new_user(UserId::default(), old_email)).await,
+        Ok(())
+    );
+}
+
+pub async fn update_email_rejects_taken_or_unknown(store: impl UserStore) {
+    let (id, email) = add(&store).await;
+    let (_, taken) = add(&store).await;
+
+    assert_eq!(
+        store.update_email(&id, taken.clone()).await,
+        Err(UserStoreError::UserAlreadyExists)
+    );
+    assert_eq!(
+        store.update_email(&UserId::default(), random_email()).await,
+        Err(UserStoreError::UserNotFound)
+    );
+
+    // Nothing moved
+    assert_eq!(store.get_user_by_id(&id).await.unwrap().email, email);
+    assert_ne!(store.get_user(&taken).await.unwrap().id, id);
+}
+
+pub async fn end_all_sessions_bumps_the_session_version(store: impl UserStore) {
+    let (id, email) = add(&store).await;
+    assert_eq!(store.get_session_version(&id).await, Ok(0));
+    assert_eq!(store.get_user(&email).await.unwrap().session_version, 0);
+
+    assert_eq!(store.end_all_sessions(&id).await, Ok(1));
+    assert_eq!(store.get_session_version(&id).await, Ok(1));
+    assert_eq!(store.get_user_by_id(&id).await.unwrap().session_version, 1);
+
+    let unknown = UserId::default();
+    assert_eq!(
+        store.get_session_version(&unknown).await,
+        Err(UserStoreError::UserNotFound)
+    );
+    assert_eq!(
+        store.end_all_sessions(&unknown).await,
+        Err(UserStoreError::UserNotFound)
+    );
+}
+
+// Only Postgres caches session versions: two instances of the service
+// sharing a database
+#[tokio::test]
+async fn postgres_sees_sessions_ended_elsewhere_once_cached_version_expires() {
+    let database = crate::common::TestDatabase::new().await;
+    let clock = FakeClock::default();
+    let ttl = Duration::seconds(5);
+    let store = || {
+        PostgresUserStore::new(database.pool().clone())
+            .with_hashing_params(crate::hashing_params())
+            .with_session_version_ttl(ttl.to_std().unwrap())
+            .with_clock(Arc::new(clock.clone()))
+    };
+    let (this_instance, other_instance) = (store(), store());
+
+    let (id, _) = add(&this_instance).await;
+    assert_eq!(this_instance.get_session_version(&id).await, Ok(0));
+
+    assert_eq!(other_instance.end_all_sessions(&id).await, Ok(1));
+    assert_eq!(other_instance.get_session_version(&id).await, Ok(1));
+
+    // Still served from the cache, without asking the database
+    clock.advance(ttl - Duration::seconds(1));
+    assert_eq!(this_instance.remove_expired(), 0);
+    assert_eq!(this_instance.get_session_version(&id).await, Ok(0));
+
+    clock.advance(Duration::seconds(1));
+    assert_eq!(this_instance.remove_expired(), 1);
+    assert_eq!(this_instance.get_session_version(&id).await, Ok(1));
+}
+
+pub async fn update_email_ends_all_sessions(store: impl UserStore) {
+    let (id, _) = add(&store).await;
+    let (_, taken) = add(&store).await;
+
+    assert_eq!(store.update_email(&id, random_email()).await, Ok(()));
+    assert_eq!(store.get_session_version(&id).await, Ok(1));
+
+    // Only a change that went through counts
+    assert_eq!(
+        store.update_email(&id, taken).await,
+        Err(UserStoreError::UserAlreadyExists)
+    );
+    assert_eq!(store.get_session_version(&id).await, Ok(1));
+}
+
+pub async fn concurrent_ends_of_all_sessions_all_count(store: impl UserStore) {
+    let (id, _) = add(&store).await;
+
+    let mut tasks = JoinSet::new();
+    for _ in 0..16 {
+        let store = store.clone();
+        tasks.spawn(async move { store.end_all_sessions(&id).await.unwrap() });
+    }
+
+    let mut versions = Vec::new();
+    while let Some(result) = tasks.join_next().await {
+        versions.push(result.unwrap());
+    }
+    versions.sort();
+
+    assert_eq!(versions, (1..=16).collect::<Vec<u64>>());
+    assert_eq!(store.get_session_version(&id).await, Ok(16));
+}
+
+pub async fn concurrent_email_change_and_ends_of_all_sessions_all_count(store: impl UserStore) {
+    let (id, old_email) = add(&store).await;
+    let new_email = random_email();
+
+    let mut tasks = JoinSet::new();
+    for _ in 0..16 {
+        let store = store.clone();
+        tasks.spawn(async move { store.end_all_sessions(&id).await.is_ok() });
+    }
+    {
+        let store = store.clone();
+        let new_email = new_email.clone();
+        tasks.spawn(async move { store.update_email(&id, new_email).await.is_ok() });
+    }
+    while let Some(result) = tasks.join_next().await {
+        assert!(result.unwrap());
+    }
+
+    assert_eq!(store.get_session_version(&id).await, Ok(17));
+    assert_eq!(
+        store.get_user(&new_email).await.unwrap().session_version,
+        17
+    );
+    assert_eq!(
+        store.get_user(&old_email).await,
+        Err(UserStoreError::UserNotFound)
+    );
+}
+
+pub async fn concurrent_adds_with_same_email_have_one_winner(store: impl UserStore) {
+    let email = random_email();
+
+    let mut tasks = JoinSet::new();
+    for i in 0..8 {
+        let store = store.clone();
+        // Same address, written differently
+        let email = if i % 2 == 0 {
+            email.clone()
+        } else {
+            Email::parse(email.as_ref().to_uppercase()).unwrap()
+        };
+        tasks.spawn(async move { store.add_user(new_user(UserId::default(), email)).await });
+    }
+
+    let mut results = Vec::new();
+    while let Some(result) = tasks.join_next().await {
+        results.push(result.unwrap());
+    }
+
+    assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+    assert!(results
+        .iter()
+        .filter(|r| r.is_err())
+        .all(|r| r == &Err(UserStoreError::UserAlreadyExists)));
+    assert!(store.get_user(&email).await.is_ok());
+}
+
+pub async fn concurrent_adds_with_distinct_emails_all_land(store: impl UserStore) {
+    let mut tasks = JoinSet::new();
+    for _ in 0..8 {
+        let store = store.clone();
+        tasks.spawn(async move {
+            let id = UserId::default();
+            let email = random_email();
+            store.add_user(new_user(id, email.clone())).await.unwrap();
+            (id, email)
+        });
+    }
+
+    while let Some(result) = tasks.join_next().await {
+        let (id, email) = result.unwrap();
+        assert_eq!(store.get_user(&email).await.unwrap().id, id);
+    }
+}